@@ -0,0 +1,78 @@
+use criterion::{criterion_group, criterion_main, Criterion, BenchmarkId};
+use cubik::quadoctree::{BoundingBox, CollisionFilter};
+use cubik::spatial::{CollisionIndices, SpatialIndex};
+use cubik::collision::{check_player_collision, raycast};
+use cubik::cube::generate_cube_collideobj;
+use cubik::wavefront::{LoadTargets, load_obj};
+
+const MAP_OBJ: &str = "models/map2.obj";
+const SAMPLE_COUNT: usize = 256;
+
+// small xorshift generator so that every index is queried with the same points
+fn sample_points(bbox: &BoundingBox, count: usize) -> Vec<[f32; 3]> {
+	let mut state = 0x2545f491u32;
+	let mut next = || {
+		state ^= state << 13;
+		state ^= state >> 17;
+		state ^= state << 5;
+		state as f32 / u32::MAX as f32
+	};
+	(0..count).map(|_| {
+		let mut point = [0., 0., 0.0f32];
+		for (i, component) in point.iter_mut().enumerate() {
+			*component = bbox.start_pos[i] + (bbox.end_pos[i] - bbox.start_pos[i]) * next();
+		}
+		point
+	}).collect()
+}
+
+fn load_index(mut index: CollisionIndices) -> CollisionIndices {
+	load_obj(MAP_OBJ, "example", None, None, &[1., 1., 1.], LoadTargets { collision_index: Some(&mut index), ..Default::default() }).unwrap();
+	index.optimize();
+	index
+}
+
+fn indices() -> Vec<(&'static str, CollisionIndices)> {
+	let bbox = BoundingBox { start_pos: [-51.; 3], end_pos: [51.; 3] };
+	vec![
+		("quadtree", load_index(CollisionIndices::new_quadoctree(bbox, false, 301))),
+		("bvh", load_index(CollisionIndices::new_bvh(cubik::bvh::DEFAULT_LEAF_SIZE)))
+	]
+}
+
+fn map_bounds(index: &dyn SpatialIndex) -> BoundingBox {
+	let mut bbox = BoundingBox::empty();
+	index.visit_items(&mut |obj, _| bbox = bbox.union(&obj.bounds()));
+	bbox
+}
+
+fn bench_collision(c: &mut Criterion) {
+	let indices = indices();
+	let filter = CollisionFilter::default();
+	let points = sample_points(&map_bounds(&indices[0].1), SAMPLE_COUNT);
+
+	let mut group = c.benchmark_group("player_collision");
+	for (name, index) in &indices {
+		group.bench_with_input(BenchmarkId::from_parameter(name), index, |b, index| b.iter(|| {
+			for point in &points {
+				let player_box = generate_cube_collideobj(&[0., -0.275, 0.], point, &[0.2, 0.275, 0.2], 0.);
+				check_player_collision(index, point, &player_box, &filter);
+			}
+		}));
+	}
+	group.finish();
+
+	let mut group = c.benchmark_group("raycast");
+	for (name, index) in &indices {
+		group.bench_with_input(BenchmarkId::from_parameter(name), index, |b, index| b.iter(|| {
+			for pair in points.chunks(2) {
+				let direction = [pair[1][0] - pair[0][0], pair[1][1] - pair[0][1], pair[1][2] - pair[0][2]];
+				raycast(index, &pair[0], &direction, 100., &filter);
+			}
+		}));
+	}
+	group.finish();
+}
+
+criterion_group!(benches, bench_collision);
+criterion_main!(benches);
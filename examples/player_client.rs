@@ -5,35 +5,59 @@ use cubik::draw::{ObjDrawInfo, EnvDrawInfo, basic_render, MAX_LIGHTS, Light};
 use cubik::camera::perspective_matrix;
 use cubik::input::{InputListener, process_input_event, center_cursor};
 use cubik::skybox::Skybox;
-use cubik::animation::ObjAnimation;
 use cubik::player::{Player, PlayerControlType};
 use cubik::peer_player::PeerPlayer;
+use cubik::npc::PeerNpc;
+use cubik::weapon::{WeaponEffects, WeaponMessage};
+use cubik::health::HealthMessage;
 use support::constants::APP_ID;
 use cubik::audio::{buffer_sound, get_sound_stream, SoundStream};
 use cubik::container::RenderContainer;
 use std::collections::HashMap;
 use cubik::client::ClientContainer;
-use cubik::map::GameMap;
 use support::msg::AppMessage;
 
 const PORT: u16 = 27020;
 
-fn net_update(client_container: &mut ClientContainer<AppMessage>, peer_map: &mut HashMap<u8, PeerPlayer>, player: &mut Player, sound_stream: &SoundStream, time_delta: f32) {
+fn net_update(client_container: &mut ClientContainer<AppMessage>, peer_map: &mut HashMap<u8, PeerPlayer>, npc_map: &mut HashMap<u8, PeerNpc>, weapon_effects: &mut WeaponEffects, player: &mut Player, sound_stream: &SoundStream, time_delta: f32) {
 	let pids = client_container.pids();
 	peer_map.retain(|&k, _| pids.contains(&k));
 
 	client_container.update().unwrap();
 
 	for msg in client_container.get_msgs() {
-		if let AppMessage::PlayerChange { msg, player_id } = msg {
-			if client_container.player_id.unwrap_or(0) == player_id {
-				player.update(0., None, Some(sound_stream), Some(msg));
-			} else {
-				let peer_player = peer_map.entry(player_id)
-					.or_insert(PeerPlayer::new());
-
-				peer_player.update(Some(msg), time_delta);
-			}
+		match msg {
+			AppMessage::PlayerChange { msg, player_id } => {
+				if client_container.player_id.unwrap_or(0) == player_id {
+					player.update(0., None, Some(sound_stream), Some(msg));
+				} else {
+					let peer_player = peer_map.entry(player_id)
+						.or_insert(PeerPlayer::new());
+
+					peer_player.update(Some(msg), time_delta);
+				}
+			},
+			AppMessage::NpcChange { msg, npc_id } => {
+				npc_map.entry(npc_id).or_insert(PeerNpc::new()).update(Some(msg), time_delta);
+			},
+			AppMessage::WeaponChange { msg, .. } => {
+				if let WeaponMessage::Ammo { ammo, reserve_ammo } = msg {
+					println!("ammo: {} / {}", ammo, reserve_ammo);
+				}
+				weapon_effects.handle_message(&msg, Some(sound_stream));
+			},
+			AppMessage::HealthChange { msg, player_id } => {
+				if client_container.player_id.unwrap_or(0) != player_id {
+					continue;
+				}
+				match msg {
+					HealthMessage::Damage { event, health, armor } => println!("took {} damage from {:?}, health: {} armor: {}", event.amount, event.source, health, armor),
+					HealthMessage::Death { event } => println!("killed by {:?}", event.source),
+					HealthMessage::Respawn { position, .. } => player.respawn_at(position),
+					HealthMessage::Status { .. } => ()
+				}
+			},
+			_ => ()
 		}
 	}
 
@@ -41,6 +65,12 @@ fn net_update(client_container: &mut ClientContainer<AppMessage>, peer_map: &mut
 		peer_player.update(None, time_delta);
 	}
 
+	for peer_npc in npc_map.values_mut() {
+		peer_npc.update(None, time_delta);
+	}
+
+	weapon_effects.update(time_delta);
+
 	if let Some(out_msg) = player.update(time_delta, None, Some(sound_stream), None) {
 		client_container.send(AppMessage::PlayerChange {
 			player_id: 0,
@@ -59,6 +89,9 @@ fn main() {
 	let sound_stream = get_sound_stream().unwrap();
 
 	let mut peer_map: HashMap<u8, PeerPlayer> = HashMap::new();
+	let mut npc_map: HashMap<u8, PeerNpc> = HashMap::new();
+	let mut weapon_effects = WeaponEffects::new();
+	weapon_effects.fire_sound = Some(buffer_sound("./audio/ding.wav", APP_ID).unwrap());
 
 	let mut client_container: ClientContainer<AppMessage> = ClientContainer::new(format!("127.0.0.1:{}", PORT).as_str()).unwrap();
 	let mut player = Player::new([0.0, 1.5, 0.0], PlayerControlType::MultiplayerClient,
@@ -66,12 +99,10 @@ fn main() {
 
 	player.walking_sound = Some(buffer_sound("./audio/running.wav", APP_ID).unwrap());
 	
-	let map = GameMap::load_map("models/map2", APP_ID,
-		Some(&ctr.display), Some(&mut ctr.textures), false).unwrap();
+	let map = ctr.assets.load_map(&ctr.display, "models/map2", APP_ID, false).unwrap();
 
-	let wolf_standing = cubik::wavefront::load_obj("models/wolf_standing.obj", APP_ID, Some(&ctr.display), Some(&mut ctr.textures),
-		&[1., 1., 1.], None, None, None).unwrap();
-	let wolf_anim = ObjAnimation::load_wavefront("models/wolfrunning", APP_ID, &ctr.display, &mut ctr.textures, 0.041).unwrap();
+	let wolf_standing = ctr.assets.load_model(&ctr.display, "models/wolf_standing.obj", APP_ID).unwrap();
+	let wolf_anim = ctr.assets.load_animation(&ctr.display, "models/wolfrunning", APP_ID, 0.041).unwrap();
 
 	let skybox = Skybox::new(&ctr.display, "skybox1", APP_ID, 512, 50.).unwrap();
 
@@ -109,7 +140,12 @@ fn main() {
 									window.set_cursor_visible(!input_enabled);
 								}
 								return;
-							}
+							},
+							glutin::event::VirtualKeyCode::R => {
+								if input_enabled && input.state == glutin::event::ElementState::Pressed {
+									client_container.send(AppMessage::WeaponChange { player_id: 0, msg: WeaponMessage::ReloadRequest }).unwrap();
+								}
+							},
 							_ => ()
 						};
 					}
@@ -117,6 +153,15 @@ fn main() {
 					process_input_event(event, listeners, &ctr.display);
 					return;
 				},
+				glutin::event::WindowEvent::MouseInput { button: glutin::event::MouseButton::Left,
+					state: glutin::event::ElementState::Pressed, .. } => {
+					if !input_enabled { return; }
+					client_container.send(AppMessage::WeaponChange {
+						player_id: 0,
+						msg: WeaponMessage::FireRequest { direction: player.camera.direction }
+					}).unwrap();
+					return;
+				},
 				_ => {
 					if !input_enabled { return; }
 					process_input_event(event, listeners, &ctr.display);
@@ -140,7 +185,7 @@ fn main() {
 
 		displace += time_delta;
 
-		net_update(&mut client_container, &mut peer_map, &mut player, &sound_stream, time_delta);
+		net_update(&mut client_container, &mut peer_map, &mut npc_map, &mut weapon_effects, &mut player, &sound_stream, time_delta);
 
 		let mut target = ctr.display.draw();
 
@@ -151,7 +196,7 @@ fn main() {
 			lights: lights_arr,
 			light_count: map.lights.len(),
 			params: &ctr.params,
-			textures: &ctr.textures
+			textures: &ctr.assets.textures
 		};
 
 		target.clear_color_and_depth((0.85, 0.85, 0.85, 1.0), 1.0); 
@@ -167,6 +212,10 @@ fn main() {
 			peer_player.draw(&mut target, &env_info, &ctr.main_program, &wolf_anim, &wolf_standing, wolf_anim.get_keyframe_by_index(5));
 		}
 
+		for peer_npc in npc_map.values_mut() {
+			peer_npc.draw(&mut target, &env_info, &ctr.main_program, &wolf_anim, &wolf_standing, wolf_anim.get_keyframe_by_index(5), None);
+		}
+
 		skybox.draw(&mut target, &env_info, &ctr.skybox_program);
 
 		target.finish().unwrap();
@@ -2,15 +2,28 @@ mod support;
 
 use cubik::server::ServerContainer;
 use cubik::player::{Player, PlayerControlType};
-use cubik::quadoctree::{QuadOctreeNode, BoundingBox};
+use cubik::npc::{Npc, NpcEvent, NpcTarget};
+use cubik::weapon::{Weapon, WeaponMessage, WeaponHit, FireResult, Projectile};
+use cubik::quadoctree::CollisionObj;
+use cubik::health::{Health, HealthMessage, DamageEvent, DamageSource, DamageType, choose_spawn_point};
 use support::msg::AppMessage;
 use std::time::{Instant, Duration};
 use std::thread::sleep;
 use std::collections::HashMap;
 use crate::support::constants::APP_ID;
 use cubik::map::GameMap;
+use cubik::script::{ScriptHost, ScriptCommand, ScriptError};
+use cubik::trigger::{TriggerTracker, TriggerOccupant};
+use cubik::ecs::World;
 
 const PORT: u16 = 27020;
+const NPC_ATTACK_DAMAGE: f32 = 10.;
+
+fn print_script_errors(errors: Vec<ScriptError>) {
+	for error in errors {
+		println!("script error: {}", error);
+	}
+}
 
 fn main() {
 	let mut server_container: ServerContainer<AppMessage> = ServerContainer::new(PORT, 10).unwrap();
@@ -19,9 +32,24 @@ fn main() {
 
 	let mut last_status_update = Instant::now();
 	let mut player_map: HashMap<u8, Player> = HashMap::new();
+	let mut weapon_map: HashMap<u8, Weapon> = HashMap::new();
+	let mut projectiles: Vec<Projectile<u8>> = Vec::new();
+	let mut health_map: HashMap<u8, Health> = HashMap::new();
 
 	let map = GameMap::load_map("models/map2", APP_ID, None, None, true).unwrap();
 
+	let spawn_points = map.spawn_points();
+
+	let mut npc = Npc::new([3.5, 1.5, 3.5], [-0.28, 0.275, 0.0], [0.44, 0.275, 0.08]);
+	npc.body.config = map.player_config;
+	npc.patrol_points = vec![[3.5, 0., 3.5], [-3.5, 0., 3.5], [-3.5, 0., -3.5], [3.5, 0., -3.5]];
+
+	let mut world = World::new();
+	let mut trigger_tracker = TriggerTracker::new();
+	let mut script_host = ScriptHost::new(Some(&map));
+	print_script_errors(script_host.load_map_scripts(&map, APP_ID));
+	println!("loaded {} map script(s)", script_host.script_count());
+
 	let mut last_time = Instant::now();
 
 	loop {
@@ -29,24 +57,165 @@ fn main() {
 
 		let current_pids = server_container.pids();
 		player_map.retain(|&k, _| current_pids.contains(&k));
+		weapon_map.retain(|&k, _| current_pids.contains(&k));
+		health_map.retain(|&k, _| current_pids.contains(&k));
+		let mut weapon_requests: Vec<(u8, WeaponMessage)> = Vec::new();
+		let mut damage_events: Vec<(u8, DamageEvent)> = Vec::new();
 
 		for pid in current_pids {
-			let player = player_map.entry(pid)
-				.or_insert(Player::new([0., 1.5, 0.], PlayerControlType::MultiplayerServer, [-0.28, 0.275, 0.0], [0.44, 0.275, 0.08]));
+			if !player_map.contains_key(&pid) {
+				let occupied: Vec<[f32; 3]> = player_map.values().map(|player| player.camera.position).collect();
+				let spawn = choose_spawn_point(&spawn_points, &occupied).unwrap_or([0., 1.5, 0.]);
+				let mut player = Player::new(spawn, PlayerControlType::MultiplayerServer, [-0.28, 0.275, 0.0], [0.44, 0.275, 0.08]);
+				player.config = map.player_config;
+				let _ = server_container.send_to(pid, AppMessage::PlayerChange {
+					msg: player.config_message(),
+					player_id: pid
+				});
+				player_map.insert(pid, player);
+				weapon_map.insert(pid, Weapon::new(Default::default(), 48));
+				health_map.insert(pid, Health::new(Default::default()));
+			}
+			let player = player_map.get_mut(&pid).unwrap();
 			if let Ok(msgs) = server_container.get_msgs(pid) {
 				for msg in msgs {
-					if let AppMessage::PlayerChange { msg, .. } = msg {
-						player.update(0., Some(map.quadoctree.as_ref().unwrap()), None, Some(msg));
+					match msg {
+						AppMessage::PlayerChange { msg, .. } => {
+							player.update(0., map.collision_index(), None, Some(msg));
+						},
+						AppMessage::WeaponChange { msg, .. } => {
+							if !health_map[&pid].is_dead() {
+								weapon_requests.push((pid, msg));
+							}
+						},
+						_ => ()
 					}
 				}
 				
 			}
-			if let Some(msg) = player.update(last_time.elapsed().as_secs_f32(), Some(map.quadoctree.as_ref().unwrap()), None, None) {
+			if let Some(msg) = player.update(last_time.elapsed().as_secs_f32(), map.collision_index(), None, None) {
 				server_container.broadcast(AppMessage::PlayerChange {
 					msg: msg,
 					player_id: pid
 				});
 			}
+			if let Some(event) = health_map.get_mut(&pid).unwrap().fall_damage(player) {
+				damage_events.push((pid, event));
+			}
+		}
+
+		let player_boxes: Vec<(u8, &CollisionObj)> = player_map.iter().map(|(pid, player)| (*pid, &player.player_cube)).collect();
+		let mut hits: Vec<(u8, WeaponHit<u8>)> = Vec::new();
+		for (pid, msg) in weapon_requests {
+			let weapon = weapon_map.get_mut(&pid).unwrap();
+			let player = &player_map[&pid];
+			match msg {
+				WeaponMessage::FireRequest { direction } => {
+					let origin = [player.camera.position[0], player.camera.position[1] + player.camera.height, player.camera.position[2]];
+					match weapon.fire(pid, &origin, &direction, map.collision_index(), &player_boxes) {
+						Some(FireResult::Hitscan(hit)) => hits.extend(hit.map(|hit| (pid, hit))),
+						Some(FireResult::Projectile(projectile)) => projectiles.push(projectile),
+						None => continue
+					}
+					server_container.broadcast(AppMessage::WeaponChange {
+						msg: WeaponMessage::Fired { origin: origin, direction: direction },
+						player_id: pid
+					});
+					let _ = server_container.send_to(pid, AppMessage::WeaponChange { msg: weapon.ammo_message(), player_id: pid });
+				},
+				WeaponMessage::ReloadRequest => {
+					if weapon.reload() {
+						server_container.broadcast(AppMessage::WeaponChange { msg: WeaponMessage::Reloading, player_id: pid });
+					}
+				},
+				_ => ()
+			}
+		}
+		for projectile in projectiles.iter_mut() {
+			let owner = projectile.owner;
+			hits.extend(projectile.update(last_time.elapsed().as_secs_f32(), map.collision_index(), &player_boxes).map(|hit| (owner, hit)));
+		}
+		projectiles.retain(|projectile| !projectile.is_expired());
+		for (shooter, hit) in hits {
+			if let Some(target) = hit.target {
+				damage_events.push((target, DamageEvent {
+					source: DamageSource::Player(shooter),
+					damage_type: DamageType::Weapon,
+					amount: hit.damage
+				}));
+			}
+			server_container.broadcast(AppMessage::WeaponChange {
+				msg: WeaponMessage::Impact { point: hit.point, normal: hit.normal, hit_target: hit.target.is_some() },
+				player_id: 0
+			});
+		}
+		for (pid, weapon) in weapon_map.iter_mut() {
+			if weapon.update(last_time.elapsed().as_secs_f32()) {
+				let _ = server_container.send_to(*pid, AppMessage::WeaponChange { msg: weapon.ammo_message(), player_id: *pid });
+			}
+		}
+
+		let targets: Vec<NpcTarget> = player_map.iter()
+			.filter(|(pid, _)| !health_map[pid].is_dead())
+			.map(|(pid, player)| NpcTarget { id: *pid as u32, position: player.camera.position })
+			.collect();
+		if let Some(msg) = npc.update(last_time.elapsed().as_secs_f32(), map.collision_index(), map.navmesh.as_ref(), &targets) {
+			server_container.broadcast(AppMessage::NpcChange {
+				msg: msg,
+				npc_id: 0
+			});
+		}
+		for event in npc.take_events() {
+			if let NpcEvent::Attack { target } = event {
+				damage_events.push((target as u8, DamageEvent {
+					source: DamageSource::Npc(0),
+					damage_type: DamageType::Melee,
+					amount: NPC_ATTACK_DAMAGE
+				}));
+			}
+		}
+
+		for (pid, event) in damage_events {
+			let health = health_map.get_mut(&pid).unwrap();
+			if let Some(result) = health.apply_damage(event) {
+				if result.killed {
+					println!("pid {} was killed by {:?}", pid, event.source);
+				}
+				server_container.broadcast(AppMessage::HealthChange {
+					msg: health.damage_message(event),
+					player_id: pid
+				});
+			}
+		}
+
+		for (pid, health) in health_map.iter_mut() {
+			if health.update(last_time.elapsed().as_secs_f32()) {
+				let occupied: Vec<[f32; 3]> = player_map.iter().filter(|(other, _)| *other != pid)
+					.map(|(_, player)| player.camera.position).collect();
+				let player = player_map.get_mut(pid).unwrap();
+				let spawn = choose_spawn_point(&spawn_points, &occupied).unwrap_or(player.start_position);
+				player.respawn_at(spawn);
+				health.reset();
+				server_container.broadcast(AppMessage::HealthChange {
+					msg: HealthMessage::Respawn { position: spawn, health: health.health, armor: health.armor },
+					player_id: *pid
+				});
+			}
+		}
+
+		let trigger_events = trigger_tracker.update(&map.triggers,
+			player_map.iter().map(|(pid, player)| (TriggerOccupant::Player(*pid), player.camera.position)));
+		print_script_errors(script_host.handle_trigger_events(&trigger_events));
+		print_script_errors(script_host.update(last_time.elapsed().as_secs_f32()));
+		for command in script_host.apply_to_world(&mut world) {
+			match command {
+				ScriptCommand::Chat { text } => server_container.broadcast(AppMessage::Chat {
+					text: text,
+					sender: None
+				}),
+				ScriptCommand::PlaySound { name, .. } => println!("script played sound {}", name),
+				_ => ()
+			}
 		}
 
 		if last_status_update.elapsed().as_secs_f32() > 5. {
@@ -8,13 +8,12 @@ use cubik::camera::perspective_matrix;
 use cubik::input::{InputListener, process_input_event, center_cursor};
 
 use cubik::skybox::Skybox;
-use cubik::animation::ObjAnimation;
 use cubik::player::{Player, PlayerControlType};
 
 use ui::{MainMenu, MainMenuAction};
 use support::constants::APP_ID;
 use cubik::audio::{buffer_sound, get_sound_stream, play_sound_from_file};
-use cubik::map::GameMap;
+use cubik::debug_draw::CollisionDebugRenderer;
 
 use cubik::container::RenderContainer;
 
@@ -43,10 +42,10 @@ fn main() {
 	play_sound_from_file(&sound_stream, "./audio/ding.wav", APP_ID).unwrap();
 	player.walking_sound = Some(buffer_sound("./audio/running.wav", APP_ID).unwrap());
 	
-	let map = GameMap::load_map("models/map2", APP_ID, Some(&ctr.display), Some(&mut ctr.textures),
-		true).unwrap();
+	let map = ctr.assets.load_map(&ctr.display, "models/map2", APP_ID, true).unwrap();
+	player.config = map.player_config;
 
-	let wolf_anim = ObjAnimation::load_wavefront("models/wolfrunning", APP_ID, &ctr.display, &mut ctr.textures, 0.041).unwrap();
+	let wolf_anim = ctr.assets.load_animation(&ctr.display, "models/wolfrunning", APP_ID, 0.041).unwrap();
 
 	let skybox = Skybox::new(&ctr.display, "skybox1", APP_ID, 512, 50.).unwrap();
 
@@ -56,6 +55,9 @@ fn main() {
 
 	let mut displace = 0.0f32;
 
+	// F3 toggles collision wireframes
+	let mut collision_debug = CollisionDebugRenderer::new(&ctr.display);
+
 	let mut main_menu = MainMenu::new(&ctr.display).unwrap();
 	main_menu.enabled = false;
 
@@ -63,7 +65,7 @@ fn main() {
 	let mut last_frame_time = std::time::Instant::now();
 
 	event_loop.run(move |ev, _, control_flow| {
-		let listeners: Vec<&mut dyn InputListener> = vec![&mut main_menu, &mut player];
+		let listeners: Vec<&mut dyn InputListener> = vec![&mut main_menu, &mut collision_debug, &mut player];
 		*control_flow = glutin::event_loop::ControlFlow::Poll;
 		match ev {
 			glutin::event::Event::WindowEvent { event, .. } => match event {
@@ -100,7 +102,7 @@ fn main() {
 
 		displace += time_delta;
 
-		player.update(time_delta, Some(map.quadoctree.as_ref().unwrap()), Some(&sound_stream), None);
+		player.update(time_delta, map.collision_index(), Some(&sound_stream), None);
 
 		let mut target = ctr.display.draw();
 
@@ -111,7 +113,7 @@ fn main() {
 			lights: lights_arr,
 			light_count: map.lights.len(),
 			params: &ctr.params,
-			textures: &ctr.textures
+			textures: &ctr.assets.textures
 		};
 
 		if main_menu.enabled {
@@ -142,6 +144,8 @@ fn main() {
 			}
 
 			skybox.draw(&mut target, &env_info, &ctr.skybox_program);
+
+			collision_debug.draw(&mut target, &ctr.display, &env_info, map.collision_index(), Some(&player)).unwrap();
 		}
 
 		target.finish().unwrap();
@@ -1,8 +1,14 @@
 use serde::{Serialize, Deserialize};
 use cubik::player::PlayerControlMessage;
+use cubik::npc::NpcMessage;
+use cubik::weapon::WeaponMessage;
+use cubik::health::HealthMessage;
 
 #[derive(Serialize, Deserialize)]
 pub enum AppMessage {
 	Chat { text: String, sender: Option<u8> },
-	PlayerChange { player_id: u8, msg: PlayerControlMessage }
+	PlayerChange { player_id: u8, msg: PlayerControlMessage },
+	NpcChange { npc_id: u8, msg: NpcMessage },
+	WeaponChange { player_id: u8, msg: WeaponMessage },
+	HealthChange { player_id: u8, msg: HealthMessage }
 }
@@ -1,11 +1,12 @@
 use std::io;
-use std::fs;
+use std::path::Path;
 use glium::{Display, texture::Texture2d};
 use std::collections::{HashMap, BTreeMap};
 use crate::draw::ObjDef;
 use crate::wavefront::{WavefrontLoadError, load_obj};
+use crate::mesh::upload_meshes;
 use derive_more::{Error, From};
-use crate::assets::find_asset;
+use crate::assets::{asset_exists, list_assets};
 
 #[derive(Debug, derive_more::Display, Error, From)]
 pub enum ObjAnimationError {
@@ -20,21 +21,15 @@ pub struct ObjAnimation {
 
 impl ObjAnimation {
 	pub fn load_wavefront(name: &str, app_id: &str, display: &Display, textures: &mut HashMap<String, Texture2d>, keyframe_time: f32) -> Result<ObjAnimation, ObjAnimationError> {
-		let animation_path = find_asset(name, app_id);
-
-		let mut keyframe_files: Vec<String> = Vec::new();
-
-		for entry in fs::read_dir(&animation_path)? {
-			let path = entry?.path();
-			if path.is_dir() { continue; }
-
-			if let Some(ext) = path.extension() {
-				if ext != "obj" { continue; }
-
-				keyframe_files.push(path.to_str().unwrap().to_string());
-			}
+		if !asset_exists(name, app_id) {
+			return Err(io::Error::new(io::ErrorKind::NotFound, format!("animation {} not found", name)).into());
 		}
 
+		let mut keyframe_files: Vec<String> = list_assets(name, app_id).into_iter()
+			.filter(|entry| Path::new(entry).extension().map(|ext| ext == "obj").unwrap_or(false))
+			.map(|entry| Path::new(name).join(entry).to_str().unwrap().to_string())
+			.collect();
+
 		keyframe_files.sort();
 		let mut result = ObjAnimation {
 			keyframes: Vec::with_capacity(keyframe_files.len()),
@@ -42,8 +37,8 @@ impl ObjAnimation {
 		};
 
 		for keyframe_file in keyframe_files {
-			let obj = load_obj(keyframe_file.as_str(), app_id, Some(display), Some(textures), &[1., 1., 1.], None, None, None)?;
-			result.keyframes.push(obj);
+			let meshes = load_obj(keyframe_file.as_str(), app_id, Some(display), Some(textures), &[1., 1., 1.], Default::default())?;
+			result.keyframes.push(upload_meshes(display, &meshes, true));
 		}
 
 		Ok(result)
@@ -0,0 +1,329 @@
+use std::rc::Rc;
+use std::ops::Deref;
+use std::hash::Hash;
+use std::path::Path;
+use std::collections::{HashMap, HashSet, BTreeMap, VecDeque};
+use glium::{Display, texture::Texture2d};
+use derive_more::{Error, From};
+use crate::draw::{ObjDef, MtlInfo};
+use crate::mesh::upload_meshes;
+use crate::wavefront::{WavefrontLoadError, load_obj};
+use crate::gltf::{GltfLoadError, load_gltf};
+use crate::animation::{ObjAnimation, ObjAnimationError};
+use crate::fonts::{LoadedFont, FontError};
+use crate::audio::{SoundData, AudioError, buffer_sound};
+use crate::textures::{self, TextureLoadError, create_texture_map};
+use crate::map::{GameMap, GameMapError};
+
+// uploaded objects of a model, by object name
+pub type Model = BTreeMap<String, ObjDef>;
+
+#[derive(Debug, derive_more::Display, Error, From)]
+pub enum AssetLoadError {
+	WavefrontLoadError(WavefrontLoadError),
+	GltfLoadError(GltfLoadError),
+	ObjAnimationError(ObjAnimationError),
+	FontError(FontError),
+	AudioError(AudioError),
+	TextureLoadError(TextureLoadError),
+	GameMapError(GameMapError)
+}
+
+// Shared reference to a cached asset. The asset stays loaded while a handle to it exists.
+pub struct Handle<T> {
+	asset: Rc<T>
+}
+
+impl<T> Handle<T> {
+	pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+		Rc::ptr_eq(&a.asset, &b.asset)
+	}
+}
+
+impl<T> Clone for Handle<T> {
+	fn clone(&self) -> Self {
+		Self { asset: self.asset.clone() }
+	}
+}
+
+impl<T> Deref for Handle<T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		&self.asset
+	}
+}
+
+struct Entry<T> {
+	asset: Rc<T>,
+	// names of the textures drawn by the asset, which are kept as long as the asset is
+	textures: Vec<String>
+}
+
+struct Cache<K, T> {
+	entries: HashMap<K, Entry<T>>
+}
+
+impl<K: Eq + Hash, T> Cache<K, T> {
+	fn new() -> Self {
+		Self { entries: HashMap::new() }
+	}
+
+	fn get(&self, key: &K) -> Option<Handle<T>> {
+		self.entries.get(key).map(|entry| Handle { asset: entry.asset.clone() })
+	}
+
+	fn insert(&mut self, key: K, asset: T, textures: Vec<String>) -> Handle<T> {
+		let asset = Rc::new(asset);
+		self.entries.insert(key, Entry { asset: asset.clone(), textures: textures });
+		Handle { asset: asset }
+	}
+
+	// Drops the assets only referenced by the cache, returning how many were dropped.
+	fn remove_unused(&mut self) -> usize {
+		let count = self.entries.len();
+		self.entries.retain(|_, entry| Rc::strong_count(&entry.asset) > 1);
+		count - self.entries.len()
+	}
+
+	fn textures(&self) -> impl Iterator<Item = &String> {
+		self.entries.values().flat_map(|entry| entry.textures.iter())
+	}
+}
+
+#[derive(Clone, Debug)]
+pub enum AssetRequest {
+	Model(String),
+	Animation(String, f32),
+	Texture(String),
+	Font(String, f32),
+	Sound(String),
+	// path without extension, and whether to create collision
+	Map(String, bool)
+}
+
+// Handle to what load_next loaded. The asset stays cached only as long as the handle is kept.
+pub enum LoadedAsset {
+	Model(Handle<Model>),
+	Animation(Handle<ObjAnimation>),
+	Texture(Handle<String>),
+	Font(Handle<LoadedFont>),
+	Sound(Handle<SoundData>),
+	Map(Handle<GameMap>)
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LoadProgress {
+	pub loaded: usize,
+	pub total: usize
+}
+
+impl LoadProgress {
+	pub fn fraction(&self) -> f32 {
+		if self.total == 0 { 1. } else { self.loaded as f32 / self.total as f32 }
+	}
+
+	pub fn is_done(&self) -> bool {
+		self.loaded >= self.total
+	}
+}
+
+fn material_textures(material: &MtlInfo) -> impl Iterator<Item = &String> {
+	vec![&material.diffuse_texture, &material.specular_texture, &material.normal_texture,
+		&material.alpha_texture, &material.emissive_texture].into_iter().flatten()
+}
+
+fn model_textures<'a>(defs: impl Iterator<Item = &'a ObjDef>) -> Vec<String> {
+	let mut result: Vec<String> = defs
+		.flat_map(|def| def.material.iter().chain(def.submeshes.iter().map(|submesh| &submesh.material)))
+		.flat_map(material_textures)
+		.cloned()
+		.collect();
+	result.sort();
+	result.dedup();
+	result
+}
+
+// Loads each asset once per path and hands out handles to the cached copy. Textures are kept by
+// name in the textures map, which is what EnvDrawInfo and the model loaders use.
+pub struct AssetManager {
+	// Loading into this map around the manager may reuse textures of managed assets, which
+	// unload_unused does not know about and may remove. Maps go through load_map for this reason.
+	pub textures: HashMap<String, Texture2d>,
+	// textures added to the map by this manager, the only ones unload_unused may remove
+	managed_textures: HashSet<String>,
+
+	models: Cache<String, Model>,
+	animations: Cache<(String, u32), ObjAnimation>,
+	texture_names: Cache<String, String>,
+	fonts: Cache<(String, u32), LoadedFont>,
+	sounds: Cache<String, SoundData>,
+	maps: Cache<(String, bool), GameMap>,
+
+	queue: VecDeque<AssetRequest>,
+	progress: LoadProgress
+}
+
+impl AssetManager {
+	pub fn new(display: &Display) -> Result<Self, TextureLoadError> {
+		Ok(Self {
+			textures: create_texture_map(display)?,
+			..Self::headless()
+		})
+	}
+
+	// For servers and tests, which only load sounds. The textures map starts out empty.
+	pub fn headless() -> Self {
+		Self {
+			textures: HashMap::new(),
+			managed_textures: HashSet::new(),
+			models: Cache::new(),
+			animations: Cache::new(),
+			texture_names: Cache::new(),
+			fonts: Cache::new(),
+			sounds: Cache::new(),
+			maps: Cache::new(),
+			queue: VecDeque::new(),
+			progress: Default::default()
+		}
+	}
+
+	// Remembers the textures that a load added to the map.
+	fn track_new_textures(&mut self, previous: &HashSet<String>) {
+		for name in self.textures.keys() {
+			if !previous.contains(name) {
+				self.managed_textures.insert(name.clone());
+			}
+		}
+	}
+
+	fn texture_names(&self) -> HashSet<String> {
+		self.textures.keys().cloned().collect()
+	}
+
+	// Loads an OBJ, baked or glTF model at unit scale, uploading its objects into shared buffers.
+	pub fn load_model(&mut self, display: &Display, path: &str, app_id: &str) -> Result<Handle<Model>, AssetLoadError> {
+		if let Some(handle) = self.models.get(&path.to_string()) {
+			return Ok(handle);
+		}
+		let previous = self.texture_names();
+		let meshes = if path.ends_with(".gltf") || path.ends_with(".glb") {
+			load_gltf(path, app_id, Some(display), Some(&mut self.textures), &[1., 1., 1.], Default::default())?.meshes
+		} else {
+			load_obj(path, app_id, Some(display), Some(&mut self.textures), &[1., 1., 1.], Default::default())?
+		};
+		self.track_new_textures(&previous);
+		let model = upload_meshes(display, &meshes, true);
+		let textures = model_textures(model.values());
+		Ok(self.models.insert(path.to_string(), model, textures))
+	}
+
+	pub fn load_animation(&mut self, display: &Display, path: &str, app_id: &str, keyframe_time: f32) -> Result<Handle<ObjAnimation>, AssetLoadError> {
+		let key = (path.to_string(), keyframe_time.to_bits());
+		if let Some(handle) = self.animations.get(&key) {
+			return Ok(handle);
+		}
+		let previous = self.texture_names();
+		let animation = ObjAnimation::load_wavefront(path, app_id, display, &mut self.textures, keyframe_time)?;
+		self.track_new_textures(&previous);
+		let textures = model_textures(animation.keyframes.iter().flat_map(|keyframe| keyframe.values()));
+		Ok(self.animations.insert(key, animation, textures))
+	}
+
+	// The handle holds the name of the texture in the textures map, which is the path.
+	pub fn load_texture(&mut self, display: &Display, path: &str, app_id: &str) -> Result<Handle<String>, AssetLoadError> {
+		if let Some(handle) = self.texture_names.get(&path.to_string()) {
+			return Ok(handle);
+		}
+		if !self.textures.contains_key(path) {
+			let texture = textures::load_asset_texture(display, Path::new(path), app_id, true)?;
+			self.textures.insert(path.to_string(), texture);
+			self.managed_textures.insert(path.to_string());
+		}
+		Ok(self.texture_names.insert(path.to_string(), path.to_string(), vec![path.to_string()]))
+	}
+
+	pub fn load_font(&mut self, display: &Display, path: &str, app_id: &str, font_size: f32) -> Result<Handle<LoadedFont>, AssetLoadError> {
+		let key = (path.to_string(), font_size.to_bits());
+		if let Some(handle) = self.fonts.get(&key) {
+			return Ok(handle);
+		}
+		let font = LoadedFont::load(display, path, app_id, font_size)?;
+		Ok(self.fonts.insert(key, font, Vec::new()))
+	}
+
+	pub fn load_sound(&mut self, path: &str, app_id: &str) -> Result<Handle<SoundData>, AssetLoadError> {
+		if let Some(handle) = self.sounds.get(&path.to_string()) {
+			return Ok(handle);
+		}
+		let sound = buffer_sound(path, app_id)?;
+		Ok(self.sounds.insert(path.to_string(), sound, Vec::new()))
+	}
+
+	// Loads the map with GameMap::load_map, keeping the textures of its objects while the handle is held.
+	pub fn load_map(&mut self, display: &Display, path: &str, app_id: &str, create_collision: bool) -> Result<Handle<GameMap>, AssetLoadError> {
+		let key = (path.to_string(), create_collision);
+		if let Some(handle) = self.maps.get(&key) {
+			return Ok(handle);
+		}
+		let previous = self.texture_names();
+		let map = GameMap::load_map(path, app_id, Some(display), Some(&mut self.textures), create_collision)?;
+		self.track_new_textures(&previous);
+		let textures = model_textures(map.objects.values());
+		Ok(self.maps.insert(key, map, textures))
+	}
+
+	// Drops the assets without handles outside of the manager, along with the textures that
+	// only they used. Returns the number of assets dropped, not counting textures.
+	pub fn unload_unused(&mut self) -> usize {
+		let count = self.models.remove_unused() + self.animations.remove_unused() + self.texture_names.remove_unused()
+			+ self.fonts.remove_unused() + self.sounds.remove_unused() + self.maps.remove_unused();
+
+		let used: HashSet<String> = self.models.textures()
+			.chain(self.animations.textures())
+			.chain(self.texture_names.textures())
+			.chain(self.maps.textures())
+			.cloned()
+			.collect();
+		let unused: Vec<String> = self.managed_textures.difference(&used).cloned().collect();
+		for name in unused {
+			self.managed_textures.remove(&name);
+			if name != textures::WHITE && name != textures::CHECKERBOARD {
+				self.textures.remove(&name);
+			}
+		}
+		count
+	}
+
+	// Adds a request to be loaded by load_next. The load_ functions return the cached asset
+	// without touching the disk while the handle returned by load_next is kept.
+	pub fn queue(&mut self, request: AssetRequest) {
+		if self.queue.is_empty() {
+			self.progress = Default::default();
+		}
+		self.queue.push_back(request);
+		self.progress.total += 1;
+	}
+
+	// Loads the next queued request, so a loading screen can be drawn in between, and returns
+	// its handle, or None once the queue is empty. A request that fails to load still counts as loaded.
+	pub fn load_next(&mut self, display: &Display, app_id: &str) -> Result<Option<LoadedAsset>, AssetLoadError> {
+		let request = match self.queue.pop_front() {
+			Some(request) => request,
+			None => return Ok(None)
+		};
+		self.progress.loaded += 1;
+		Ok(Some(match request {
+			AssetRequest::Model(path) => LoadedAsset::Model(self.load_model(display, &path, app_id)?),
+			AssetRequest::Animation(path, keyframe_time) => LoadedAsset::Animation(self.load_animation(display, &path, app_id, keyframe_time)?),
+			AssetRequest::Texture(path) => LoadedAsset::Texture(self.load_texture(display, &path, app_id)?),
+			AssetRequest::Font(path, font_size) => LoadedAsset::Font(self.load_font(display, &path, app_id, font_size)?),
+			AssetRequest::Sound(path) => LoadedAsset::Sound(self.load_sound(&path, app_id)?),
+			AssetRequest::Map(path, create_collision) => LoadedAsset::Map(self.load_map(display, &path, app_id, create_collision)?)
+		}))
+	}
+
+	pub fn progress(&self) -> LoadProgress {
+		self.progress
+	}
+}
@@ -1,5 +1,13 @@
-use std::path::{Path, PathBuf};
+use std::io::{self, Read, Cursor, BufReader};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf, Component};
+use std::collections::{HashMap, BTreeSet};
 use std::env::current_exe;
+use std::sync::{Mutex, RwLock, OnceLock};
+use zip::ZipArchive;
+
+// archive mounted by the default search order if it is next to the binary
+pub const RELEASE_ARCHIVE: &str = "assets.zip";
 
 struct Prefix {
 	path: &'static str,
@@ -16,27 +24,255 @@ const PREFIXES: [Prefix; 6] = [
 	Prefix { path: "../Resources", append_app_id: false, relative_to_binary: true }
 ];
 
-pub fn find_asset(path: &str, app_id: &str) -> PathBuf {
-	let path = Path::new(path);
+static VFS: OnceLock<RwLock<Vfs>> = OnceLock::new();
 
-	if !path.exists() {
-		for prefix in &PREFIXES {
-			let mut new_path = if prefix.relative_to_binary {
-				let mut p = current_exe().unwrap();
-				p.pop();
-				p.join(prefix.path)
-			} else {
-				Path::new(prefix.path).to_path_buf()
-			};
-			if prefix.append_app_id {
-				new_path.push(app_id);
+fn binary_dir() -> Option<PathBuf> {
+	let mut path = current_exe().ok()?;
+	path.pop();
+	Some(path)
+}
+
+// Turns a relative path into the forward slash form used by archive and memory mounts.
+// Returns None for absolute paths and paths leaving the root of the mount.
+fn normalize_path(path: &Path) -> Option<String> {
+	let mut parts: Vec<&str> = Vec::new();
+	for component in path.components() {
+		match component {
+			Component::Normal(part) => parts.push(part.to_str()?),
+			Component::ParentDir => { parts.pop()?; },
+			Component::CurDir => (),
+			Component::RootDir | Component::Prefix(_) => return None
+		}
+	}
+	Some(parts.join("/"))
+}
+
+// Names of the entries directly inside the directory, given the normalized paths of all files.
+fn list_entries<'a>(paths: impl Iterator<Item = &'a String>, dir: &str) -> BTreeSet<String> {
+	let prefix = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
+	paths.filter_map(|path| path.strip_prefix(prefix.as_str()))
+		.filter_map(|rest| rest.split('/').next())
+		.filter(|name| !name.is_empty())
+		.map(|name| name.to_string())
+		.collect()
+}
+
+pub struct Archive {
+	zip: Mutex<ZipArchive<BufReader<File>>>,
+	file_names: Vec<String>
+}
+
+impl Archive {
+	pub fn open(path: &Path) -> io::Result<Self> {
+		let zip = ZipArchive::new(BufReader::new(File::open(path)?))
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		let file_names = zip.file_names().map(|name| name.to_string()).collect();
+		Ok(Self {
+			zip: Mutex::new(zip),
+			file_names: file_names
+		})
+	}
+
+	fn read(&self, path: &str) -> Option<io::Result<Vec<u8>>> {
+		let mut zip = self.zip.lock().unwrap();
+		let mut file = zip.by_name(path).ok()?;
+		let mut data = Vec::with_capacity(file.size() as usize);
+		Some(file.read_to_end(&mut data).map(|_| data))
+	}
+}
+
+pub enum Mount {
+	// a directory on disk, relative paths are relative to the working directory
+	Directory { path: PathBuf, append_app_id: bool, relative_to_binary: bool },
+	// a zip archive, paths are relative to its root
+	Archive(Archive),
+	// files kept in memory, mainly for tests
+	Memory(HashMap<String, Vec<u8>>)
+}
+
+impl Mount {
+	pub fn directory(path: &Path) -> Self {
+		Mount::Directory { path: path.to_path_buf(), append_app_id: false, relative_to_binary: false }
+	}
+
+	pub fn archive(path: &Path) -> io::Result<Self> {
+		Ok(Mount::Archive(Archive::open(path)?))
+	}
+
+	pub fn memory(files: HashMap<String, Vec<u8>>) -> Self {
+		Mount::Memory(files.into_iter().map(|(path, data)| (normalize_path(Path::new(&path)).unwrap_or(path), data)).collect())
+	}
+
+	fn directory_path(&self, path: &Path, app_id: &str) -> Option<PathBuf> {
+		match self {
+			Mount::Directory { path: dir, append_app_id, relative_to_binary } => {
+				let mut result = if *relative_to_binary { binary_dir()?.join(dir) } else { dir.clone() };
+				if *append_app_id {
+					result.push(app_id);
+				}
+				result.push(path);
+				Some(result)
+			},
+			_ => None
+		}
+	}
+
+	fn exists(&self, path: &Path, app_id: &str) -> bool {
+		match self {
+			Mount::Directory { .. } => self.directory_path(path, app_id).map(|path| path.exists()).unwrap_or(false),
+			Mount::Archive(archive) => normalize_path(path).map(|path| {
+				let dir = format!("{}/", path);
+				archive.file_names.iter().any(|name| *name == path || name.starts_with(&dir))
+			}).unwrap_or(false),
+			Mount::Memory(files) => normalize_path(path).map(|path| {
+				let dir = format!("{}/", path);
+				files.keys().any(|name| *name == path || name.starts_with(&dir))
+			}).unwrap_or(false)
+		}
+	}
+
+	fn read(&self, path: &Path, app_id: &str) -> Option<io::Result<Vec<u8>>> {
+		match self {
+			Mount::Directory { .. } => {
+				let path = self.directory_path(path, app_id)?;
+				if !path.is_file() {
+					return None;
+				}
+				Some(fs::read(path))
+			},
+			Mount::Archive(archive) => archive.read(&normalize_path(path)?),
+			Mount::Memory(files) => files.get(&normalize_path(path)?).map(|data| Ok(data.clone()))
+		}
+	}
+
+	fn list(&self, dir: &Path, app_id: &str) -> BTreeSet<String> {
+		match self {
+			Mount::Directory { .. } => self.directory_path(dir, app_id)
+				.and_then(|dir| fs::read_dir(dir).ok())
+				.map(|entries| entries.filter_map(|entry| entry.ok()?.file_name().into_string().ok()).collect())
+				.unwrap_or_default(),
+			Mount::Archive(archive) => normalize_path(dir)
+				.map(|dir| list_entries(archive.file_names.iter(), &dir))
+				.unwrap_or_default(),
+			Mount::Memory(files) => normalize_path(dir)
+				.map(|dir| list_entries(files.keys(), &dir))
+				.unwrap_or_default()
+		}
+	}
+}
+
+// Mounts searched in order for every asset. The first mount containing a path wins.
+pub struct Vfs {
+	pub mounts: Vec<Mount>
+}
+
+impl Default for Vfs {
+	// The working directory, a release archive next to the binary, then the usual asset directories.
+	fn default() -> Self {
+		let mut result = Self::new();
+		result.mount(Mount::directory(Path::new("")));
+		if let Some(binary_dir) = binary_dir() {
+			for archive_path in [binary_dir.join(RELEASE_ARCHIVE), binary_dir.join("../Resources").join(RELEASE_ARCHIVE)].iter() {
+				if archive_path.exists() {
+					match Mount::archive(archive_path) {
+						Ok(mount) => result.mount(mount),
+						Err(e) => eprintln!("warning: could not open asset archive {}: {}", archive_path.display(), e)
+					}
+				}
 			}
-			new_path.push(path);
-			if new_path.exists() {
-				return new_path;
+		}
+		for prefix in &PREFIXES {
+			result.mount(Mount::Directory {
+				path: PathBuf::from(prefix.path),
+				append_app_id: prefix.append_app_id,
+				relative_to_binary: prefix.relative_to_binary
+			});
+		}
+		result
+	}
+}
+
+impl Vfs {
+	pub fn new() -> Self {
+		Self { mounts: Vec::new() }
+	}
+
+	// Adds a mount searched after the existing ones.
+	pub fn mount(&mut self, mount: Mount) {
+		self.mounts.push(mount);
+	}
+
+	// Adds a mount searched before the existing ones.
+	pub fn mount_first(&mut self, mount: Mount) {
+		self.mounts.insert(0, mount);
+	}
+
+	pub fn exists(&self, path: &Path, app_id: &str) -> bool {
+		self.mounts.iter().any(|mount| mount.exists(path, app_id))
+	}
+
+	pub fn read(&self, path: &Path, app_id: &str) -> io::Result<Vec<u8>> {
+		self.mounts.iter()
+			.find_map(|mount| mount.read(path, app_id))
+			.unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::NotFound, format!("asset {} not found", path.display()))))
+	}
+
+	// Names of the files and directories in the directory, merged from all mounts.
+	pub fn list(&self, dir: &Path, app_id: &str) -> BTreeSet<String> {
+		self.mounts.iter().flat_map(|mount| mount.list(dir, app_id)).collect()
+	}
+
+	// The path on disk, if the asset is in a directory mount.
+	pub fn real_path(&self, path: &Path, app_id: &str) -> Option<PathBuf> {
+		for mount in self.mounts.iter() {
+			if mount.exists(path, app_id) {
+				return mount.directory_path(path, app_id);
 			}
 		}
+		None
 	}
+}
 
-	path.to_path_buf()
+fn vfs() -> &'static RwLock<Vfs> {
+	VFS.get_or_init(|| RwLock::new(Default::default()))
+}
+
+// Replaces the mounts used by all asset loaders.
+pub fn set_vfs(new_vfs: Vfs) {
+	*vfs().write().unwrap() = new_vfs;
+}
+
+// Allows changing the mounts in place, e.g. vfs_mut(|vfs| vfs.mount_first(mount)).
+pub fn vfs_mut<R, F: FnOnce(&mut Vfs) -> R>(f: F) -> R {
+	f(&mut vfs().write().unwrap())
+}
+
+pub fn asset_exists<P: AsRef<Path>>(path: P, app_id: &str) -> bool {
+	vfs().read().unwrap().exists(path.as_ref(), app_id)
+}
+
+pub fn read_asset<P: AsRef<Path>>(path: P, app_id: &str) -> io::Result<Vec<u8>> {
+	vfs().read().unwrap().read(path.as_ref(), app_id)
+}
+
+pub fn read_asset_to_string<P: AsRef<Path>>(path: P, app_id: &str) -> io::Result<String> {
+	String::from_utf8(read_asset(path, app_id)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn open_asset<P: AsRef<Path>>(path: P, app_id: &str) -> io::Result<Cursor<Vec<u8>>> {
+	Ok(Cursor::new(read_asset(path, app_id)?))
+}
+
+pub fn list_assets<P: AsRef<Path>>(dir: P, app_id: &str) -> BTreeSet<String> {
+	vfs().read().unwrap().list(dir.as_ref(), app_id)
+}
+
+// The path on disk, or None if the asset is missing or only in an archive or memory mount.
+pub fn asset_path<P: AsRef<Path>>(path: P, app_id: &str) -> Option<PathBuf> {
+	vfs().read().unwrap().real_path(path.as_ref(), app_id)
+}
+
+// Returns the path on disk of the asset, or the path itself if it is not in a directory mount.
+pub fn find_asset(path: &str, app_id: &str) -> PathBuf {
+	vfs().read().unwrap().real_path(Path::new(path), app_id).unwrap_or_else(|| PathBuf::from(path))
 }
@@ -1,9 +1,8 @@
-use std::fs::File;
-use std::io::{BufReader, Cursor, Read};
+use std::io::Cursor;
 use std::io;
 use rodio::{Source, Sink, decoder::{LoopedDecoder, Decoder, DecoderError}, OutputStream, OutputStreamHandle, StreamError, PlayError};
 use derive_more::{Display, From, Error};
-use crate::assets::find_asset;
+use crate::assets::{read_asset, open_asset};
 
 pub type SoundData = Vec<u8>;
 pub type SoundStream = (OutputStream, OutputStreamHandle);
@@ -17,10 +16,7 @@ pub enum AudioError {
 }
 
 pub fn buffer_sound(filename: &str, app_id: &str) -> Result<SoundData, AudioError> {
-	let mut file = File::open(find_asset(filename, app_id))?;
-	let mut data = Vec::new();
-	file.read_to_end(&mut data)?;
-	Ok(data)
+	Ok(read_asset(filename, app_id)?)
 }
 
 pub fn get_sound_stream() -> Result<SoundStream, AudioError> {
@@ -32,11 +28,8 @@ pub fn sound_decoder_from_data(data: &SoundData) -> Result<Decoder<Cursor<Vec<u8
 	Ok(Decoder::new(cursor)?)
 }
 
-pub fn sound_decoder_from_file(filename: &str, app_id: &str) -> Result<Decoder<BufReader<File>>, AudioError> {
-	let file = File::open(find_asset(filename, app_id))?;
-	let reader = BufReader::new(file);
-
-	Ok(Decoder::new(reader)?)
+pub fn sound_decoder_from_file(filename: &str, app_id: &str) -> Result<Decoder<Cursor<Vec<u8>>>, AudioError> {
+	Ok(Decoder::new(open_asset(filename, app_id)?)?)
 }
 
 pub fn sound_decoder_from_data_looped(data: &SoundData) -> Result<LoopedDecoder<Cursor<Vec<u8>>>, AudioError> {
@@ -44,10 +37,8 @@ pub fn sound_decoder_from_data_looped(data: &SoundData) -> Result<LoopedDecoder<
 	Ok(Decoder::new_looped(cursor)?)
 }
 
-pub fn sound_decoder_from_file_looped(filename: &str, app_id: &str) -> Result<LoopedDecoder<BufReader<File>>, AudioError> {
-	let file = File::open(find_asset(filename, app_id))?;
-	let reader = BufReader::new(file);
-	Ok(Decoder::new_looped(reader)?)
+pub fn sound_decoder_from_file_looped(filename: &str, app_id: &str) -> Result<LoopedDecoder<Cursor<Vec<u8>>>, AudioError> {
+	Ok(Decoder::new_looped(open_asset(filename, app_id)?)?)
 }
 
 pub fn play_sound_from_data(stream: &SoundStream, data: &SoundData) -> Result<(), AudioError> {
@@ -58,10 +49,7 @@ pub fn play_sound_from_data(stream: &SoundStream, data: &SoundData) -> Result<()
 }
 
 pub fn play_sound_from_file(stream: &SoundStream, filename: &str, app_id: &str) -> Result<(), AudioError> {
-	let file = File::open(find_asset(filename, app_id))?;
-	let reader = BufReader::new(file);
-	
-	let decoder = Decoder::new(reader)?;
+	let decoder = Decoder::new(open_asset(filename, app_id)?)?;
 	stream.1.play_raw(decoder.convert_samples())?;
 	Ok(())
 }
@@ -0,0 +1,156 @@
+use std::io::{self, Read, BufReader, BufWriter};
+use std::fs::{self, File};
+use std::path::Path;
+use std::ops::Range;
+use std::collections::HashSet;
+use serde::{Serialize, Deserialize};
+use derive_more::{Display, Error, From};
+use crate::draw::{Vertex, MtlInfo};
+use crate::wavefront::{COLLISION_PREFIX, LIGHT_PREFIX, TERRAIN_PREFIX, MISC_PREFIX, TRIGGER_PREFIX, NAV_PREFIX};
+
+const BAKED_MAGIC: [u8; 4] = *b"CBKM";
+const BAKED_VERSION: u32 = 1;
+pub const BAKED_EXTENSION: &str = "cbm";
+
+const KNOWN_PREFIXES: [&str; 6] = [COLLISION_PREFIX, LIGHT_PREFIX, TERRAIN_PREFIX, MISC_PREFIX, TRIGGER_PREFIX, NAV_PREFIX];
+
+#[derive(Debug, Display, Error, From)]
+pub enum BakedModelError {
+	IOError(io::Error),
+	SerializeError(bincode::Error),
+	#[from(ignore)]
+	FormatError { msg: &'static str }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BakedSubMesh {
+	pub index_range: Range<usize>,
+	// None for parts drawn with the default material
+	pub material: Option<MtlInfo>
+}
+
+// An object as parsed from the OBJ, before it is sorted by name prefix.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BakedObject {
+	pub name: String,
+	pub vertices: Vec<Vertex>,
+	pub indices: Vec<u32>,
+	pub submeshes: Vec<BakedSubMesh>
+}
+
+// Pre-parsed model, which load_obj reads instead of the OBJ text if the file has the .cbm extension.
+// Texture names are relative to the directory of the file, like in the MTL.
+#[derive(Default, Serialize, Deserialize)]
+pub struct BakedModel {
+	// contents of the map meta TOML, used by load_map if there is no TOML next to the model
+	pub meta: Option<String>,
+	pub objects: Vec<BakedObject>
+}
+
+impl BakedModel {
+	fn read_header(reader: &mut impl Read) -> Result<Option<String>, BakedModelError> {
+		let mut magic = [0u8; 4];
+		reader.read_exact(&mut magic)?;
+		if magic != BAKED_MAGIC {
+			return Err(BakedModelError::FormatError { msg: "Not a baked model file" });
+		}
+		let version: u32 = bincode::deserialize_from(&mut *reader)?;
+		if version != BAKED_VERSION {
+			return Err(BakedModelError::FormatError { msg: "Baked model was created by an incompatible version, convert it again" });
+		}
+		Ok(bincode::deserialize_from(&mut *reader)?)
+	}
+
+	pub fn read(reader: &mut impl Read) -> Result<Self, BakedModelError> {
+		let meta = Self::read_header(reader)?;
+		Ok(Self {
+			meta: meta,
+			objects: bincode::deserialize_from(reader)?
+		})
+	}
+
+	pub fn load(path: &Path) -> Result<Self, BakedModelError> {
+		Self::read(&mut BufReader::new(File::open(path)?))
+	}
+
+	// Reads only the meta, which comes before the objects.
+	pub fn read_meta(reader: &mut impl Read) -> Result<Option<String>, BakedModelError> {
+		Self::read_header(reader)
+	}
+
+	pub fn save(&self, path: &Path) -> Result<(), BakedModelError> {
+		let temp_path = path.with_extension(format!("{}.tmp", BAKED_EXTENSION));
+		{
+			let mut writer = BufWriter::new(File::create(&temp_path)?);
+			io::Write::write_all(&mut writer, &BAKED_MAGIC)?;
+			bincode::serialize_into(&mut writer, &BAKED_VERSION)?;
+			bincode::serialize_into(&mut writer, &self.meta)?;
+			bincode::serialize_into(&mut writer, &self.objects)?;
+		}
+		fs::rename(&temp_path, path)?;
+		Ok(())
+	}
+
+	// Looks for mistakes that the loaders silently accept: misspelled name prefixes, missing
+	// textures, point objects without vertices and lights in the meta without a matching object.
+	pub fn check(&self, model_dir: &Path) -> Vec<String> {
+		let mut problems = Vec::new();
+		let mut names = HashSet::new();
+		let mut missing_textures = HashSet::new();
+
+		for object in self.objects.iter() {
+			let name = object.name.trim();
+			if !names.insert(name) {
+				problems.push(format!("object {} appears more than once, only the last one is kept", name));
+			}
+			if let Some(underscore) = name.find('_') {
+				let prefix = &name[..=underscore];
+				if !KNOWN_PREFIXES.contains(&prefix) {
+					if let Some(known) = KNOWN_PREFIXES.iter().find(|known| edit_distance(&prefix.to_lowercase(), known) <= 2) {
+						problems.push(format!("object {} has prefix {}, did you mean {}?", name, prefix, known));
+					}
+				}
+			}
+			if (name.starts_with(LIGHT_PREFIX) || name.starts_with(MISC_PREFIX)) && object.vertices.is_empty() {
+				problems.push(format!("object {} has no vertices to take its position from", name));
+			}
+			if (name.starts_with(COLLISION_PREFIX) || name.starts_with(TRIGGER_PREFIX) || name.starts_with(NAV_PREFIX)) && object.indices.is_empty() {
+				problems.push(format!("object {} has no faces", name));
+			}
+			for material in object.submeshes.iter().filter_map(|submesh| submesh.material.as_ref()) {
+				let textures = [&material.diffuse_texture, &material.specular_texture, &material.normal_texture,
+					&material.alpha_texture, &material.emissive_texture];
+				for texture in textures.iter().filter_map(|texture| texture.as_ref()) {
+					if !model_dir.join(texture).exists() && missing_textures.insert(texture.clone()) {
+						problems.push(format!("texture {} used by object {} does not exist", texture, name));
+					}
+				}
+			}
+		}
+
+		let meta = self.meta.as_ref().and_then(|meta| meta.parse::<toml::Value>().ok());
+		if let Some(lights) = meta.as_ref().and_then(|meta| meta.get("lights")).and_then(|lights| lights.as_table()) {
+			for light in lights.keys() {
+				if !names.contains(light.as_str()) {
+					problems.push(format!("[lights.{}] in the meta does not match any object", light));
+				}
+			}
+		}
+		problems
+	}
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut row: Vec<usize> = (0..=b.len()).collect();
+	for (i, a_char) in a.chars().enumerate() {
+		let mut prev = row[0];
+		row[0] = i + 1;
+		for j in 0..b.len() {
+			let substitution = prev + if a_char == b[j] { 0 } else { 1 };
+			prev = row[j + 1];
+			row[j + 1] = substitution.min(row[j] + 1).min(prev + 1);
+		}
+	}
+	row[b.len()]
+}
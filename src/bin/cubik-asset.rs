@@ -0,0 +1,90 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use cubik::baked::{BakedModel, BAKED_EXTENSION};
+use cubik::wavefront::bake_obj;
+use cubik::assets::{Vfs, Mount, set_vfs};
+use cubik::mesh::Mesh;
+use cubik::quadoctree::BoundingBox;
+
+const USAGE: &str = "usage: cubik-asset <command> <model path>
+
+commands:
+	convert <model.obj> [output.cbm]  bake an OBJ, its materials and map meta TOML into a .cbm file
+	bounds <model.obj|model.cbm>      print the bounds of each object and of the whole model
+	check <model.obj|model.cbm>       check object name prefixes, textures and meta lights";
+
+fn fail(msg: &str) -> ! {
+	eprintln!("{}", msg);
+	exit(1);
+}
+
+// Reads an OBJ along with the map meta next to it, or an already baked model.
+// Only the directory of the model is mounted, so its materials and textures are not
+// picked up from the asset directories of the working directory by accident.
+fn read_model(path: &Path, model_dir: &Path) -> BakedModel {
+	if path.extension().map(|extension| extension == BAKED_EXTENSION).unwrap_or(false) {
+		return BakedModel::load(path).unwrap_or_else(|e| fail(&format!("could not load {}: {}", path.display(), e)));
+	}
+	let mut vfs = Vfs::new();
+	vfs.mount(Mount::directory(model_dir));
+	set_vfs(vfs);
+	let file_name = path.file_name().and_then(|name| name.to_str())
+		.unwrap_or_else(|| fail(&format!("{} is not a model file", path.display())));
+	let mut model = bake_obj(file_name, "cubik")
+		.unwrap_or_else(|e| fail(&format!("could not load {}: {}", path.display(), e)));
+	let meta_path = path.with_extension("toml");
+	if let Ok(meta) = fs::read_to_string(&meta_path) {
+		if let Err(e) = meta.parse::<toml::Value>() {
+			fail(&format!("{}: {}", meta_path.display(), e));
+		}
+		model.meta = Some(meta);
+	}
+	model
+}
+
+fn print_bounds(name: &str, bounds: &BoundingBox) {
+	println!("{}: min {:?} max {:?}", name, bounds.start_pos, bounds.end_pos);
+}
+
+fn main() {
+	let args: Vec<String> = env::args().collect();
+	if args.len() < 3 {
+		fail(USAGE);
+	}
+	let path = PathBuf::from(&args[2]);
+	let model_dir = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+	let model = read_model(&path, &model_dir);
+
+	match args[1].as_str() {
+		"convert" => {
+			let output = args.get(3).map(PathBuf::from).unwrap_or_else(|| path.with_extension(BAKED_EXTENSION));
+			for problem in model.check(&model_dir) {
+				eprintln!("warning: {}", problem);
+			}
+			model.save(&output).unwrap_or_else(|e| fail(&format!("could not write {}: {}", output.display(), e)));
+			println!("wrote {} objects to {}{}", model.objects.len(), output.display(),
+				if model.meta.is_some() { ", including map meta" } else { "" });
+		},
+		"bounds" => {
+			let mut all_vertices = Vec::new();
+			for object in model.objects.iter() {
+				print_bounds(object.name.trim(), &Mesh::compute_bounds(&object.vertices));
+				all_vertices.extend_from_slice(&object.vertices);
+			}
+			print_bounds("total", &Mesh::compute_bounds(&all_vertices));
+		},
+		"check" => {
+			let problems = model.check(&model_dir);
+			for problem in problems.iter() {
+				println!("{}", problem);
+			}
+			if !problems.is_empty() {
+				exit(1);
+			}
+			println!("no problems found in {} objects", model.objects.len());
+		},
+		_ => fail(USAGE)
+	}
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::quadoctree::{QuadOctreeError, BoundingBox, CollisionObj, ray_intersects_bbox};
+use crate::collider::{CollisionFilter, CollisionTransform, InstanceId};
+use crate::spatial::SpatialIndex;
+
+pub const DEFAULT_LEAF_SIZE: usize = 4;
+const SAH_BIN_COUNT: usize = 12;
+
+#[derive(Serialize, Deserialize)]
+struct BvhNode {
+	bbox: BoundingBox,
+	// leaf nodes reference items[start..start + count], inner nodes have a count of 0
+	start: usize,
+	count: usize,
+	// the left child always directly follows its parent
+	right_child: usize
+}
+
+// Bounding volume hierarchy built with binned surface area heuristic splits.
+// Inserts and removals mark the tree as dirty; queries fall back to a linear
+// scan until optimize() rebuilds it. Moved instances are refitted in place.
+#[derive(Serialize, Deserialize)]
+pub struct Bvh {
+	items: Vec<(CollisionObj, CollisionFilter)>,
+	bounds: Vec<BoundingBox>,
+	nodes: Vec<BvhNode>,
+	// item index of each instance
+	instance_slots: HashMap<InstanceId, usize>,
+	leaf_size: usize,
+	dirty: bool
+}
+
+impl Bvh {
+	pub fn new(leaf_size: usize) -> Self {
+		Self {
+			items: Vec::new(),
+			bounds: Vec::new(),
+			nodes: Vec::new(),
+			instance_slots: HashMap::new(),
+			leaf_size: leaf_size.max(1),
+			dirty: false
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.items.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.items.is_empty()
+	}
+
+	pub fn rebuild(&mut self) {
+		self.nodes.clear();
+		self.dirty = false;
+		if self.items.is_empty() {
+			return;
+		}
+
+		let centers: Vec<[f32; 3]> = self.bounds.iter().map(|b| b.center()).collect();
+		let mut order: Vec<usize> = (0..self.items.len()).collect();
+		self.build_node(&mut order, 0, &centers);
+
+		let mut items: Vec<Option<(CollisionObj, CollisionFilter)>> = self.items.drain(..).map(Some).collect();
+		self.items = order.iter().map(|i| items[*i].take().unwrap()).collect();
+		self.bounds = order.iter().map(|i| self.bounds[*i]).collect();
+		self.index_instances();
+	}
+
+	fn index_instances(&mut self) {
+		self.instance_slots.clear();
+		for (i, (item, _)) in self.items.iter().enumerate() {
+			if let CollisionObj::Instance(_, _, id) = item {
+				self.instance_slots.insert(*id, i);
+			}
+		}
+	}
+
+	// Updates the boxes on the path from the root to the leaf holding the item after its bounds changed.
+	fn refit(&mut self, item: usize) {
+		let mut path = Vec::new();
+		let mut node_index = 0;
+		while self.nodes[node_index].count == 0 {
+			path.push(node_index);
+			let right_child = self.nodes[node_index].right_child;
+			node_index = if item < self.nodes[right_child].start { node_index + 1 } else { right_child };
+		}
+
+		let (start, count) = (self.nodes[node_index].start, self.nodes[node_index].count);
+		self.nodes[node_index].bbox = self.bounds[start..(start + count)].iter()
+			.fold(BoundingBox::empty(), |acc, bounds| acc.union(bounds));
+		for node_index in path.into_iter().rev() {
+			let right_child = self.nodes[node_index].right_child;
+			self.nodes[node_index].bbox = self.nodes[node_index + 1].bbox.union(&self.nodes[right_child].bbox);
+		}
+	}
+
+	fn build_node(&mut self, order: &mut [usize], start: usize, centers: &[[f32; 3]]) -> usize {
+		let node_index = self.nodes.len();
+		let bbox = order.iter().fold(BoundingBox::empty(), |acc, i| acc.union(&self.bounds[*i]));
+		self.nodes.push(BvhNode { bbox: bbox, start: start, count: order.len(), right_child: 0 });
+
+		if order.len() <= self.leaf_size {
+			return node_index;
+		}
+
+		let split = match self.find_split(order, centers) {
+			Some(split) => split,
+			None => return node_index
+		};
+
+		// partition the items so that everything left of the split plane comes first
+		let mut mid = 0;
+		for i in 0..order.len() {
+			if centers[order[i]][split.0] < split.1 {
+				order.swap(i, mid);
+				mid += 1;
+			}
+		}
+		if mid == 0 || mid == order.len() {
+			return node_index;
+		}
+
+		self.nodes[node_index].count = 0;
+		let (left, right) = order.split_at_mut(mid);
+		self.build_node(left, start, centers);
+		let right_child = self.build_node(right, start + mid, centers);
+		self.nodes[node_index].right_child = right_child;
+		node_index
+	}
+
+	// Returns the axis and position of the cheapest split plane.
+	fn find_split(&self, order: &[usize], centers: &[[f32; 3]]) -> Option<(usize, f32)> {
+		let mut center_bounds = BoundingBox::empty();
+		for i in order {
+			center_bounds.add_point(&centers[*i]);
+		}
+
+		let mut best: Option<(usize, f32, f32)> = None;
+		for axis in 0..3 {
+			let min = center_bounds.start_pos[axis];
+			let extent = center_bounds.end_pos[axis] - min;
+			if extent <= f32::EPSILON {
+				continue;
+			}
+
+			let bin_of = |center: &[f32; 3]| (((center[axis] - min) / extent * SAH_BIN_COUNT as f32) as usize).min(SAH_BIN_COUNT - 1);
+			let mut bin_counts = [0usize; SAH_BIN_COUNT];
+			let mut bin_bounds = [BoundingBox::empty(); SAH_BIN_COUNT];
+			for i in order {
+				let bin = bin_of(&centers[*i]);
+				bin_counts[bin] += 1;
+				bin_bounds[bin] = bin_bounds[bin].union(&self.bounds[*i]);
+			}
+
+			for split_bin in 1..SAH_BIN_COUNT {
+				let (mut left_bbox, mut right_bbox) = (BoundingBox::empty(), BoundingBox::empty());
+				let (mut left_count, mut right_count) = (0, 0);
+				for bin in 0..SAH_BIN_COUNT {
+					if bin < split_bin {
+						left_bbox = left_bbox.union(&bin_bounds[bin]);
+						left_count += bin_counts[bin];
+					} else {
+						right_bbox = right_bbox.union(&bin_bounds[bin]);
+						right_count += bin_counts[bin];
+					}
+				}
+				if left_count == 0 || right_count == 0 {
+					continue;
+				}
+				let cost = left_bbox.surface_area() * left_count as f32 + right_bbox.surface_area() * right_count as f32;
+				if best.map(|b| cost < b.2).unwrap_or(true) {
+					best = Some((axis, min + extent * split_bin as f32 / SAH_BIN_COUNT as f32, cost));
+				}
+			}
+		}
+
+		best.map(|(axis, position, _)| (axis, position))
+	}
+
+	fn traverse<N, L>(&self, node_check: N, mut leaf_func: L) -> bool
+		where N: Fn(&BoundingBox) -> bool, L: FnMut(usize) -> bool {
+		if self.dirty {
+			return (0..self.items.len()).any(|i| node_check(&self.bounds[i]) && leaf_func(i));
+		}
+		if self.nodes.is_empty() {
+			return false;
+		}
+
+		let mut stack = vec![0];
+		while let Some(node_index) = stack.pop() {
+			let node = &self.nodes[node_index];
+			if !node_check(&node.bbox) {
+				continue;
+			}
+			if node.count == 0 {
+				stack.push(node.right_child);
+				stack.push(node_index + 1);
+				continue;
+			}
+			for i in node.start..(node.start + node.count) {
+				if node_check(&self.bounds[i]) && leaf_func(i) {
+					return true;
+				}
+			}
+		}
+		false
+	}
+}
+
+impl SpatialIndex for Bvh {
+	fn insert(&mut self, obj: CollisionObj, filter: CollisionFilter) -> Result<(), QuadOctreeError> {
+		if let CollisionObj::Instance(_, _, id) = &obj {
+			self.instance_slots.insert(*id, self.items.len());
+		}
+		self.bounds.push(obj.bounds());
+		self.items.push((obj, filter));
+		self.dirty = true;
+		Ok(())
+	}
+
+	fn traverse_bbox(&self, bbox: &BoundingBox, filter: &CollisionFilter, check_func: &mut dyn FnMut(&CollisionObj) -> bool) -> bool {
+		self.traverse(|node_bbox| node_bbox.intersects(bbox), |i| {
+			let (item, item_filter) = &self.items[i];
+			filter.collides_with(item_filter) && check_func(item)
+		})
+	}
+
+	fn traverse_ray(&self, origin: &[f32; 3], direction: &[f32; 3], max_distance: f32, filter: &CollisionFilter,
+		check_func: &mut dyn FnMut(&CollisionObj)) {
+		self.traverse(|node_bbox| ray_intersects_bbox(node_bbox, origin, direction, max_distance), |i| {
+			let (item, item_filter) = &self.items[i];
+			if filter.collides_with(item_filter) {
+				check_func(item);
+			}
+			false
+		});
+	}
+
+	fn remove_items(&mut self, predicate: &dyn Fn(&CollisionObj) -> bool) -> Vec<(CollisionObj, CollisionFilter)> {
+		let mut result = Vec::new();
+		let mut i = 0;
+		while i < self.items.len() {
+			if predicate(&self.items[i].0) {
+				result.push(self.items.swap_remove(i));
+				self.bounds.swap_remove(i);
+			} else {
+				i += 1;
+			}
+		}
+		if !result.is_empty() {
+			self.dirty = true;
+			self.index_instances();
+		}
+		result
+	}
+
+	fn move_instance(&mut self, id: InstanceId, transform: CollisionTransform) -> Result<bool, QuadOctreeError> {
+		let i = match self.instance_slots.get(&id) {
+			Some(i) => *i,
+			None => return Ok(false)
+		};
+		if let CollisionObj::Instance(_, item_transform, _) = &mut self.items[i].0 {
+			*item_transform = transform;
+		}
+		self.bounds[i] = self.items[i].0.bounds();
+		if !self.dirty {
+			self.refit(i);
+		}
+		Ok(true)
+	}
+
+	fn visit_items(&self, visit_func: &mut dyn FnMut(&CollisionObj, &CollisionFilter)) {
+		for (item, item_filter) in &self.items {
+			visit_func(item, item_filter);
+		}
+	}
+
+	fn visit_bounds(&self, visit_func: &mut dyn FnMut(&BoundingBox)) {
+		for node in &self.nodes {
+			visit_func(&node.bbox);
+		}
+	}
+
+	fn optimize(&mut self) {
+		if self.dirty {
+			self.rebuild();
+		}
+	}
+}
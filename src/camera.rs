@@ -48,6 +48,29 @@ impl Camera {
 			[p[0], p[1], p[2], 1.0]
 		]
 	}
+
+	// Model matrix that keeps an object at a fixed offset in view space, such as
+	// a first person weapon model.
+	pub fn view_model_matrix(&self, offset: &[f32; 3], scale: f32) -> [[f32; 4]; 4] {
+		let s = normalize_vector(&cross_product(&UP, &self.direction));
+
+		let u = cross_product(&self.direction, &s);
+
+		let d = self.direction;
+
+		let p = [
+			self.position[0] + s[0] * offset[0] + u[0] * offset[1] + d[0] * offset[2],
+			self.position[1] + self.height + s[1] * offset[0] + u[1] * offset[1] + d[1] * offset[2],
+			self.position[2] + s[2] * offset[0] + u[2] * offset[1] + d[2] * offset[2]
+		];
+
+		[
+			[s[0] * scale, s[1] * scale, s[2] * scale, 0.0],
+			[u[0] * scale, u[1] * scale, u[2] * scale, 0.0],
+			[d[0] * scale, d[1] * scale, d[2] * scale, 0.0],
+			[p[0], p[1], p[2], 1.0]
+		]
+	}
 }
 
 pub fn perspective_matrix(target: &mut Frame) -> [[f32; 4]; 4] {
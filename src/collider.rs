@@ -0,0 +1,87 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde::{Serialize, Deserialize};
+use crate::draw::ObjDrawInfo;
+use crate::math::{transform_point, invert_matrix3, normalize_vector};
+
+pub const LAYER_WORLD: u32 = 1;
+pub const LAYER_PLAYER: u32 = 1 << 1;
+pub const LAYER_NPC: u32 = 1 << 2;
+pub const LAYER_PROJECTILE: u32 = 1 << 3;
+pub const LAYER_ALL: u32 = u32::MAX;
+
+static NEXT_INSTANCE_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollisionFilter {
+	pub layer: u32,
+	pub mask: u32
+}
+
+impl Default for CollisionFilter {
+	fn default() -> Self {
+		Self {
+			layer: LAYER_WORLD,
+			mask: LAYER_ALL
+		}
+	}
+}
+
+impl CollisionFilter {
+	pub fn new(layer: u32, mask: u32) -> Self {
+		Self {
+			layer: layer,
+			mask: mask
+		}
+	}
+
+	pub fn collides_with(&self, other: &CollisionFilter) -> bool {
+		(self.mask & other.layer) != 0 && (other.mask & self.layer) != 0
+	}
+}
+
+pub type InstanceId = u64;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CollisionTransform {
+	pub model_mat: [[f32; 4]; 4],
+	normal_mat: [[f32; 3]; 3]
+}
+
+impl CollisionTransform {
+	pub fn new(model_mat: [[f32; 4]; 4]) -> Self {
+		let upper = [
+			[model_mat[0][0], model_mat[1][0], model_mat[2][0]],
+			[model_mat[0][1], model_mat[1][1], model_mat[2][1]],
+			[model_mat[0][2], model_mat[1][2], model_mat[2][2]]
+		];
+		Self {
+			model_mat: model_mat,
+			normal_mat: invert_matrix3(&upper).unwrap_or([[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]])
+		}
+	}
+
+	pub fn transform_point(&self, point: &[f32; 3]) -> [f32; 3] {
+		transform_point(&self.model_mat, point)
+	}
+
+	pub fn transform_normal(&self, normal: &[f32; 3]) -> [f32; 3] {
+		let mut result = [0., 0., 0.0f32];
+		for r in 0..3 {
+			for c in 0..3 {
+				result[r] += self.normal_mat[c][r] * normal[c];
+			}
+		}
+		normalize_vector(&result)
+	}
+}
+
+impl From<&ObjDrawInfo> for CollisionTransform {
+	fn from(info: &ObjDrawInfo) -> Self {
+		Self::new(info.model_mat.unwrap_or_else(|| info.compute_matrix()))
+	}
+}
+
+pub fn next_instance_id() -> InstanceId {
+	NEXT_INSTANCE_ID.fetch_add(1, Ordering::Relaxed)
+}
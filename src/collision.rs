@@ -1,12 +1,25 @@
-use crate::quadoctree::{QuadOctreeNode, CollisionObj, traverse_quadoctree};
-use crate::math::{dot_product, cross_product, add_vector};
+use crate::quadoctree::{CollisionObj, BoundingBox};
+use crate::collider::CollisionFilter;
+use crate::spatial::SpatialIndex;
+use crate::math::{dot_product, cross_product, add_vector, normalize_vector, vector_length, mult_vector};
 use crate::draw::Vertex;
+use crate::cube::INDICES;
 
 const EPSILON: f32 = 0.000001;
 const MAX_POLY_COLLIDE: usize = 4;
+// how far below the query point terrain triangles are searched for
+const TERRAIN_QUERY_DEPTH: f32 = 1.;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+	pub point: [f32; 3],
+	pub normal: [f32; 3],
+	pub distance: f32
+}
 
 pub struct CollisionResult {
 	pub triangle: Option<[f32; 3]>,
+	pub triangle_normal: Option<[f32; 3]>,
 	pub polygons: Vec<[f32; 3]>
 }
 
@@ -43,6 +56,16 @@ fn moller_trumbore(triangle: &[[f32; 3]; 3], ray_origin: &[f32; 3], ray_directio
 	Some(add_vector(ray_origin, ray_direction, t))
 }
 
+pub fn triangle_normal(triangle: &[[f32; 3]; 3]) -> [f32; 3] {
+	let normal = normalize_vector(&cross_product(&add_vector(&triangle[1], &triangle[0], -1.),
+		&add_vector(&triangle[2], &triangle[0], -1.)));
+	if normal[1] < 0. {
+		[-normal[0], -normal[1], -normal[2]]
+	} else {
+		normal
+	}
+}
+
 fn sat_axis_projection(vertices: &[Vertex], axis: &[f32; 3]) -> (f32, f32) {
 	let mut result = (f32::MAX, f32::MIN);
 	for vertex in vertices {
@@ -90,19 +113,27 @@ fn sat_polypoly(a_vertices: &[Vertex], a_center: &[f32; 3], b_vertices: &[Vertex
 	Some([min_axis[0] * min_range_diff, min_axis[1] * min_range_diff, min_axis[2] * min_range_diff])
 }
 
-pub fn check_player_collision(tree: &QuadOctreeNode, point: &[f32; 3], player_box: &CollisionObj) -> CollisionResult {
+pub fn check_player_collision(tree: &dyn SpatialIndex, point: &[f32; 3], player_box: &CollisionObj, filter: &CollisionFilter) -> CollisionResult {
 	let mut result = CollisionResult {
 		triangle: None,
+		triangle_normal: None,
 		polygons: Vec::with_capacity(MAX_POLY_COLLIDE)
 	};
 
-	traverse_quadoctree(tree, point, &mut |obj: &CollisionObj| -> bool {
+	let mut query_bbox = player_box.bounds();
+	query_bbox.add_point(&[point[0], point[1] + 0.25, point[2]]);
+	query_bbox.add_point(&[point[0], point[1] - TERRAIN_QUERY_DEPTH, point[2]]);
+
+	tree.traverse_bbox(&query_bbox, filter, &mut |obj: &CollisionObj| -> bool {
 		if let CollisionObj::Polygon(p_vertices, p_center) = player_box {
-			match obj {
+			match obj.world().as_ref() {
 				CollisionObj::Triangle(triangle) => {
 					if result.triangle.is_none() {
 						let point = [point[0], point[1] + 0.25, point[2]];
-						result.triangle = moller_trumbore(&triangle, &point, &[0., -1., 0.]);
+						result.triangle = moller_trumbore(triangle, &point, &[0., -1., 0.]);
+						if result.triangle.is_some() {
+							result.triangle_normal = Some(triangle_normal(triangle));
+						}
 					}
 				},
 				CollisionObj::Polygon(o_vertices, o_center) => {
@@ -112,7 +143,8 @@ pub fn check_player_collision(tree: &QuadOctreeNode, point: &[f32; 3], player_bo
 							result.polygons.push(vector);
 						}
 					}
-				}
+				},
+				CollisionObj::Instance(..) => unreachable!()
 			}
 		};
 
@@ -121,3 +153,197 @@ pub fn check_player_collision(tree: &QuadOctreeNode, point: &[f32; 3], player_bo
 
 	result
 }
+
+fn ray_triangle_hit(triangle: &[[f32; 3]; 3], origin: &[f32; 3], direction: &[f32; 3]) -> Option<RayHit> {
+	let point = moller_trumbore(triangle, origin, direction)?;
+	let mut normal = normalize_vector(&cross_product(&add_vector(&triangle[1], &triangle[0], -1.),
+		&add_vector(&triangle[2], &triangle[0], -1.)));
+	if dot_product(&normal, direction) > 0. {
+		normal = [-normal[0], -normal[1], -normal[2]];
+	}
+	Some(RayHit {
+		point: point,
+		normal: normal,
+		distance: vector_length(&add_vector(&point, origin, -1.))
+	})
+}
+
+pub fn ray_collision_obj(obj: &CollisionObj, origin: &[f32; 3], direction: &[f32; 3]) -> Option<RayHit> {
+	let mut closest: Option<RayHit> = None;
+	let mut check_triangle = |triangle: &[[f32; 3]; 3]| {
+		if let Some(hit) = ray_triangle_hit(triangle, origin, direction) {
+			if closest.map(|c| hit.distance < c.distance).unwrap_or(true) {
+				closest = Some(hit);
+			}
+		}
+	};
+	match obj.world().as_ref() {
+		CollisionObj::Triangle(triangle) => check_triangle(triangle),
+		CollisionObj::Polygon(vertices, ..) => {
+			// polygons from map meshes store their vertices as a triangle list
+			for chunk in vertices.chunks(3).filter(|c| c.len() == 3) {
+				check_triangle(&[chunk[0].position, chunk[1].position, chunk[2].position]);
+			}
+		},
+		CollisionObj::Instance(..) => unreachable!()
+	}
+	closest
+}
+
+// Ray test against a box made by generate_cube_collideobj, such as a player box.
+pub fn ray_cube(cube: &CollisionObj, origin: &[f32; 3], direction: &[f32; 3]) -> Option<RayHit> {
+	let mut closest: Option<RayHit> = None;
+	if let CollisionObj::Polygon(vertices, ..) = cube {
+		let direction = normalize_vector(direction);
+		for tri in &INDICES {
+			let triangle = [vertices[tri[0] as usize].position, vertices[tri[1] as usize].position, vertices[tri[2] as usize].position];
+			if let Some(hit) = ray_triangle_hit(&triangle, origin, &direction) {
+				if closest.map(|c| hit.distance < c.distance).unwrap_or(true) {
+					closest = Some(hit);
+				}
+			}
+		}
+	}
+	closest
+}
+
+pub fn raycast(tree: &dyn SpatialIndex, origin: &[f32; 3], direction: &[f32; 3], max_distance: f32, filter: &CollisionFilter) -> Option<RayHit> {
+	let direction = normalize_vector(direction);
+	let mut closest: Option<RayHit> = None;
+
+	tree.traverse_ray(origin, &direction, max_distance, filter, &mut |obj: &CollisionObj| {
+		if let Some(hit) = ray_collision_obj(obj, origin, &direction) {
+			if hit.distance <= max_distance && closest.map(|c| hit.distance < c.distance).unwrap_or(true) {
+				closest = Some(hit);
+			}
+		}
+	});
+
+	closest
+}
+
+pub fn line_of_sight(tree: &dyn SpatialIndex, from: &[f32; 3], to: &[f32; 3], filter: &CollisionFilter) -> bool {
+	let diff = add_vector(to, from, -1.);
+	let distance = vector_length(&diff);
+	if distance < EPSILON {
+		return true;
+	}
+	raycast(tree, from, &diff, distance, filter).is_none()
+}
+
+fn project_points(points: &[[f32; 3]], axis: &[f32; 3]) -> (f32, f32) {
+	points.iter().fold((f32::MAX, f32::MIN), |(min, max), point| {
+		let projection = dot_product(axis, point);
+		(min.min(projection), max.max(projection))
+	})
+}
+
+// Continuous separating axis test of a box moving by motion against a static triangle.
+// Returns the fraction of the motion at first contact and the contact normal, facing against the motion.
+// Boxes that already overlap the triangle are not reported, so that they can move out of it.
+fn sweep_box_triangle(box_points: &[[f32; 3]], box_axes: &[[f32; 3]], triangle: &[[f32; 3]; 3],
+	motion: &[f32; 3]) -> Option<(f32, [f32; 3])> {
+	let edges = [
+		add_vector(&triangle[1], &triangle[0], -1.),
+		add_vector(&triangle[2], &triangle[1], -1.),
+		add_vector(&triangle[0], &triangle[2], -1.)
+	];
+	let mut axes: Vec<[f32; 3]> = box_axes.to_vec();
+	axes.push(cross_product(&edges[0], &edges[1]));
+	for box_axis in box_axes {
+		for edge in &edges {
+			axes.push(cross_product(box_axis, edge));
+		}
+	}
+
+	let mut enter = f32::MIN;
+	let mut exit = f32::MAX;
+	let mut normal = [0., 0., 0.0f32];
+	for axis in axes.iter() {
+		if vector_length(axis) < EPSILON {
+			continue;
+		}
+		let axis = normalize_vector(axis);
+		let (box_min, box_max) = project_points(box_points, &axis);
+		let (tri_min, tri_max) = project_points(triangle, &axis);
+		let speed = dot_product(motion, &axis);
+		if speed.abs() < EPSILON {
+			if box_max < tri_min || box_min > tri_max {
+				return None;
+			}
+			continue;
+		}
+		let (t0, t1) = ((tri_min - box_max) / speed, (tri_max - box_min) / speed);
+		let (axis_enter, axis_exit) = (t0.min(t1), t0.max(t1));
+		if axis_enter > enter {
+			enter = axis_enter;
+			normal = if speed > 0. { mult_vector(&axis, -1.) } else { axis };
+		}
+		exit = exit.min(axis_exit);
+		if enter > exit || enter > 1. || exit < 0. {
+			return None;
+		}
+	}
+	if enter < 0. {
+		return None;
+	}
+	Some((enter, normal))
+}
+
+// Finds the first contact when moving the player box, made by generate_cube_collideobj, by the motion vector.
+// Returns the fraction of the motion that can be travelled and the contact normal.
+pub fn sweep_player(tree: &dyn SpatialIndex, player_box: &CollisionObj, motion: &[f32; 3], filter: &CollisionFilter) -> Option<(f32, [f32; 3])> {
+	if vector_length(motion) < EPSILON {
+		return None;
+	}
+	let p_vertices = match player_box {
+		CollisionObj::Polygon(p_vertices, ..) => p_vertices,
+		_ => return None
+	};
+
+	let mut box_points: Vec<[f32; 3]> = Vec::with_capacity(8);
+	for vertex in p_vertices {
+		if !box_points.contains(&vertex.position) {
+			box_points.push(vertex.position);
+		}
+	}
+	// face normals of the box, which are also its edge directions
+	let mut box_axes: Vec<[f32; 3]> = Vec::with_capacity(3);
+	for tri in &INDICES {
+		let a = p_vertices[tri[0] as usize].position;
+		let normal = normalize_vector(&cross_product(&add_vector(&p_vertices[tri[1] as usize].position, &a, -1.),
+			&add_vector(&p_vertices[tri[2] as usize].position, &a, -1.)));
+		if !box_axes.iter().any(|axis| dot_product(axis, &normal).abs() > 0.999) {
+			box_axes.push(normal);
+		}
+	}
+
+	let mut swept_bbox = BoundingBox::empty();
+	for point in &box_points {
+		swept_bbox.add_point(point);
+		swept_bbox.add_point(&add_vector(point, motion, 1.));
+	}
+
+	let mut closest: Option<(f32, [f32; 3])> = None;
+	let mut check_triangle = |triangle: &[[f32; 3]; 3]| {
+		if let Some(hit) = sweep_box_triangle(&box_points, &box_axes, triangle, motion) {
+			if closest.map(|c| hit.0 < c.0).unwrap_or(true) {
+				closest = Some(hit);
+			}
+		}
+	};
+	tree.traverse_bbox(&swept_bbox, filter, &mut |obj: &CollisionObj| {
+		match obj.world().as_ref() {
+			CollisionObj::Triangle(triangle) => check_triangle(triangle),
+			CollisionObj::Polygon(vertices, ..) => {
+				for chunk in vertices.chunks(3).filter(|c| c.len() == 3) {
+					check_triangle(&[chunk[0].position, chunk[1].position, chunk[2].position]);
+				}
+			},
+			CollisionObj::Instance(..) => unreachable!()
+		}
+		false
+	});
+
+	closest
+}
@@ -1,7 +1,7 @@
 use glium::glutin::{self, event_loop::EventLoop, window::WindowBuilder, dpi::PhysicalSize};
-use glium::{Display, Program, DrawParameters, texture::Texture2d};
-use crate::textures::create_texture_map;
 use std::collections::HashMap;
+use glium::{Display, Program, DrawParameters, texture::Texture2d};
+use crate::asset_manager::AssetManager;
 use crate::shaders;
 
 #[cfg(windows)]
@@ -16,7 +16,8 @@ pub struct RenderContainer<'a> {
 	
 	pub params: DrawParameters<'a>,
 
-	pub textures: HashMap<String, Texture2d>
+	// also holds the texture map used for drawing
+	pub assets: AssetManager
 }
 
 impl RenderContainer<'_> {
@@ -45,7 +46,7 @@ impl RenderContainer<'_> {
 		let skybox_program = shaders::skybox_program(&display);
 		let ui_program = shaders::ui_program(&display);
 
-		let textures = create_texture_map(&display).unwrap();
+		let assets = AssetManager::new(&display).unwrap();
 
 		let result = Self {
 			display: display,
@@ -62,13 +63,24 @@ impl RenderContainer<'_> {
 				backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockwise,
 				..Default::default()
 			},
-			textures: textures
+			assets: assets
 		};
 
 		result.update_size_and_mode(width, height, fullscreen);
 		result
 	}
 
+	// The texture map moved into the asset manager.
+	#[deprecated(note = "use assets.textures")]
+	pub fn textures(&self) -> &HashMap<String, Texture2d> {
+		&self.assets.textures
+	}
+
+	#[deprecated(note = "use assets.textures")]
+	pub fn textures_mut(&mut self) -> &mut HashMap<String, Texture2d> {
+		&mut self.assets.textures
+	}
+
 	pub fn update_size_and_mode(&self, width: usize, height: usize, fullscreen: bool) {
 		let gl_window = self.display.gl_window();
 		let window = gl_window.window();
@@ -45,7 +45,7 @@ pub fn generate_cube_vertices(pre_pos: &[f32; 3], post_pos: &[f32; 3], dim: &[f3
 	]
 }
 
-const INDICES: [[u32; 3]; 12] = [
+pub(crate) const INDICES: [[u32; 3]; 12] = [
 	// back face
 	[0, 1, 2],
 	[1, 3, 2],
@@ -0,0 +1,268 @@
+use glium::{Display, Frame, Surface, VertexBuffer, Program, DrawError, index::{NoIndices, PrimitiveType}, vertex::BufferCreationError};
+use derive_more::{Error, From};
+use glium::glutin::event::{VirtualKeyCode, ElementState, MouseButton};
+use crate::quadoctree::{BoundingBox, CollisionObj};
+use crate::collider::{CollisionFilter, LAYER_ALL};
+use crate::spatial::SpatialIndex;
+use crate::collision::{CollisionResult, check_player_collision};
+use crate::player::Player;
+use crate::draw::EnvDrawInfo;
+use crate::input::InputListener;
+use crate::math::add_vector;
+use crate::cube::INDICES;
+use crate::shaders;
+
+const NODE_COLOR: [f32; 3] = [0.2, 0.4, 1.];
+const POLYGON_COLOR: [f32; 3] = [0., 1., 0.];
+const TRIANGLE_COLOR: [f32; 3] = [1., 1., 0.];
+const PLAYER_COLOR: [f32; 3] = [1., 0., 1.];
+const CONTACT_COLOR: [f32; 3] = [1., 0., 0.];
+// penetration vectors are usually tiny, so they are scaled up to be visible
+const PENETRATION_SCALE: f32 = 10.;
+const CONTACT_MARKER_SIZE: f32 = 0.05;
+
+#[derive(Copy, Clone, Debug)]
+pub struct DebugVertex {
+	pub position: [f32; 3],
+	pub color: [f32; 3]
+}
+
+implement_vertex!(DebugVertex, position, color);
+
+#[derive(Debug, derive_more::Display, Error, From)]
+pub enum DebugDrawError {
+	BufferCreationError(BufferCreationError),
+	DrawError(DrawError)
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct CollisionDebugOptions {
+	pub node_bounds: bool,
+	pub colliders: bool,
+	pub player_cube: bool,
+	pub contacts: bool,
+	// only colliders within this distance of the player are drawn, if set
+	pub radius: Option<f32>
+}
+
+impl Default for CollisionDebugOptions {
+	fn default() -> Self {
+		Self {
+			node_bounds: false,
+			colliders: true,
+			player_cube: true,
+			contacts: true,
+			radius: Some(5.)
+		}
+	}
+}
+
+// Line list geometry, two vertices per line.
+#[derive(Default)]
+pub struct DebugLines {
+	pub vertices: Vec<DebugVertex>
+}
+
+impl DebugLines {
+	pub fn new() -> Self {
+		Default::default()
+	}
+
+	pub fn line_count(&self) -> usize {
+		self.vertices.len() / 2
+	}
+
+	pub fn add_line(&mut self, start: &[f32; 3], end: &[f32; 3], color: &[f32; 3]) {
+		self.vertices.push(DebugVertex { position: *start, color: *color });
+		self.vertices.push(DebugVertex { position: *end, color: *color });
+	}
+
+	pub fn add_triangle(&mut self, triangle: &[[f32; 3]; 3], color: &[f32; 3]) {
+		self.add_line(&triangle[0], &triangle[1], color);
+		self.add_line(&triangle[1], &triangle[2], color);
+		self.add_line(&triangle[2], &triangle[0], color);
+	}
+
+	pub fn add_bbox(&mut self, bbox: &BoundingBox, color: &[f32; 3]) {
+		let (s, e) = (bbox.start_pos, bbox.end_pos);
+		let corners = [
+			[s[0], s[1], s[2]], [e[0], s[1], s[2]], [e[0], s[1], e[2]], [s[0], s[1], e[2]],
+			[s[0], e[1], s[2]], [e[0], e[1], s[2]], [e[0], e[1], e[2]], [s[0], e[1], e[2]]
+		];
+		for i in 0..4 {
+			self.add_line(&corners[i], &corners[(i + 1) % 4], color);
+			self.add_line(&corners[i + 4], &corners[(i + 1) % 4 + 4], color);
+			self.add_line(&corners[i], &corners[i + 4], color);
+		}
+	}
+
+	// Expects polygons to be triangle lists, as built from collision meshes.
+	pub fn add_collision_obj(&mut self, obj: &CollisionObj, color: &[f32; 3]) {
+		match obj.world().as_ref() {
+			CollisionObj::Triangle(triangle) => self.add_triangle(triangle, color),
+			CollisionObj::Polygon(vertices, ..) => {
+				for chunk in vertices.chunks(3).filter(|c| c.len() == 3) {
+					self.add_triangle(&[chunk[0].position, chunk[1].position, chunk[2].position], color);
+				}
+			},
+			CollisionObj::Instance(..) => unreachable!()
+		}
+	}
+
+	// Draws a cube polygon made by generate_cube_collideobj, whose vertices are not a triangle list.
+	pub fn add_cube(&mut self, cube: &CollisionObj, color: &[f32; 3]) {
+		if let CollisionObj::Polygon(vertices, ..) = cube {
+			for tri in &INDICES {
+				self.add_triangle(&[vertices[tri[0] as usize].position, vertices[tri[1] as usize].position,
+					vertices[tri[2] as usize].position], color);
+			}
+		}
+	}
+
+	pub fn add_marker(&mut self, point: &[f32; 3], size: f32, color: &[f32; 3]) {
+		for axis in 0..3 {
+			let mut start = *point;
+			let mut end = *point;
+			start[axis] -= size;
+			end[axis] += size;
+			self.add_line(&start, &end, color);
+		}
+	}
+
+	pub fn add_collision_result(&mut self, origin: &[f32; 3], result: &CollisionResult) {
+		for vector in &result.polygons {
+			self.add_line(origin, &add_vector(origin, vector, PENETRATION_SCALE), &CONTACT_COLOR);
+		}
+		if let Some(point) = result.triangle.as_ref() {
+			self.add_marker(point, CONTACT_MARKER_SIZE, &CONTACT_COLOR);
+			if let Some(normal) = result.triangle_normal.as_ref() {
+				self.add_line(point, &add_vector(point, normal, CONTACT_MARKER_SIZE * 4.), &CONTACT_COLOR);
+			}
+		}
+	}
+
+	pub fn add_index(&mut self, index: &dyn SpatialIndex, region: Option<&BoundingBox>, options: &CollisionDebugOptions) {
+		if options.node_bounds {
+			index.visit_bounds(&mut |bbox| {
+				if region.map(|r| r.intersects(bbox)).unwrap_or(true) {
+					self.add_bbox(bbox, &NODE_COLOR);
+				}
+			});
+		}
+		if options.colliders {
+			let mut add_obj = |obj: &CollisionObj| {
+				let color = match obj {
+					CollisionObj::Triangle(..) => &TRIANGLE_COLOR,
+					_ => &POLYGON_COLOR
+				};
+				self.add_collision_obj(obj, color);
+			};
+			match region {
+				Some(region) => {
+					// every layer is shown, whatever the masks of the colliders
+					index.traverse_bbox(region, &CollisionFilter::new(LAYER_ALL, LAYER_ALL), &mut |obj| {
+						if obj.bounds().intersects(region) {
+							add_obj(obj);
+						}
+						false
+					});
+				},
+				None => index.visit_items(&mut |obj, _| add_obj(obj))
+			}
+		}
+	}
+
+	pub fn add_player(&mut self, player: &Player, index: Option<&dyn SpatialIndex>, options: &CollisionDebugOptions) {
+		let bounds = player.player_cube.bounds();
+		if options.player_cube {
+			self.add_cube(&player.player_cube, &PLAYER_COLOR);
+		}
+		if options.contacts {
+			if let Some(index) = index {
+				let result = check_player_collision(index, &player.camera.position, &player.player_cube, &player.collision_filter);
+				self.add_collision_result(&bounds.center(), &result);
+			}
+		}
+	}
+}
+
+pub fn collision_debug_lines(index: Option<&dyn SpatialIndex>, player: Option<&Player>, options: &CollisionDebugOptions) -> DebugLines {
+	let mut result = DebugLines::new();
+	let region = match (player, options.radius) {
+		(Some(player), Some(radius)) => {
+			let position = player.camera.position;
+			Some(BoundingBox {
+				start_pos: add_vector(&position, &[radius; 3], -1.),
+				end_pos: add_vector(&position, &[radius; 3], 1.)
+			})
+		},
+		_ => None
+	};
+	if let Some(index) = index {
+		result.add_index(index, region.as_ref(), options);
+	}
+	if let Some(player) = player {
+		result.add_player(player, index, options);
+	}
+	result
+}
+
+pub struct CollisionDebugRenderer {
+	pub enabled: bool,
+	pub options: CollisionDebugOptions,
+	pub toggle_key: VirtualKeyCode,
+	program: Program,
+	// reused between frames, and only replaced when the lines no longer fit
+	vertex_buffer: Option<VertexBuffer<DebugVertex>>
+}
+
+impl CollisionDebugRenderer {
+	pub fn new(display: &Display) -> Self {
+		Self {
+			enabled: false,
+			options: Default::default(),
+			toggle_key: VirtualKeyCode::F3,
+			program: shaders::debug_program(display),
+			vertex_buffer: None
+		}
+	}
+
+	pub fn draw(&mut self, target: &mut Frame, display: &Display, env_info: &EnvDrawInfo, index: Option<&dyn SpatialIndex>,
+		player: Option<&Player>) -> Result<(), DebugDrawError> {
+		if !self.enabled { return Ok(()); }
+		let lines = collision_debug_lines(index, player, &self.options);
+		self.draw_lines(target, display, env_info, &lines)
+	}
+
+	pub fn draw_lines(&mut self, target: &mut Frame, display: &Display, env_info: &EnvDrawInfo, lines: &DebugLines) -> Result<(), DebugDrawError> {
+		let count = lines.vertices.len();
+		if count == 0 { return Ok(()); }
+		if self.vertex_buffer.as_ref().map(|buffer| buffer.len() < count).unwrap_or(true) {
+			self.vertex_buffer = Some(VertexBuffer::empty_dynamic(display, count.next_power_of_two())?);
+		}
+		let vertex_buffer = self.vertex_buffer.as_ref().unwrap().slice(0..count).unwrap();
+		vertex_buffer.write(&lines.vertices);
+		let uniforms = uniform! {
+			view: env_info.view_mat,
+			perspective: env_info.perspective_mat
+		};
+		target.draw(vertex_buffer, NoIndices(PrimitiveType::LinesList), &self.program, &uniforms, env_info.params)?;
+		Ok(())
+	}
+}
+
+impl InputListener for CollisionDebugRenderer {
+	fn handle_key_ev(&mut self, key: Option<VirtualKeyCode>, pressed: bool) -> bool {
+		if pressed && key == Some(self.toggle_key) {
+			self.enabled = !self.enabled;
+			return true;
+		}
+		false
+	}
+
+	fn handle_char_ev(&mut self, _ch: char) -> bool { false }
+
+	fn handle_mouse_pos_ev(&mut self, _pos: (f32, f32), _display: &Display) -> bool { false }
+
+	fn handle_mouse_ev(&mut self, _button: MouseButton, _state: ElementState) -> bool { false }
+}
@@ -1,13 +1,15 @@
 use std::collections::HashMap;
+use std::rc::Rc;
+use std::ops::Range;
 use glium::{Display, Frame, Surface, DrawParameters, Program,
-	VertexBuffer, IndexBuffer, texture::{Texture2d, SrgbTexture2d}, uniforms::{Uniforms, UniformValue}};
+	VertexBuffer, IndexBuffer, index::IndexBufferSlice, texture::{Texture2d, SrgbTexture2d}, uniforms::{Uniforms, UniformValue}};
 use crate::math::{mult_matrix, mult_matrix3};
 use crate::textures;
-use serde::Deserialize;
+use serde::{Serialize, Deserialize};
 
 pub const MAX_LIGHTS: usize = 24;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Vertex {
 	pub position: [f32; 3],
 	pub normal: [f32; 3],
@@ -16,17 +18,41 @@ pub struct Vertex {
 
 implement_vertex!(Vertex, position, normal, texcoords);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct MtlInfo {
 	pub diffuse_texture: Option<String>,
-	pub color: [f32; 3]
+	pub specular_texture: Option<String>,
+	// parsed from map_Bump/bump/norm, not used by the built-in shaders yet
+	pub normal_texture: Option<String>,
+	pub alpha_texture: Option<String>,
+	pub emissive_texture: Option<String>,
+	// diffuse color
+	pub color: [f32; 3],
+	pub ambient_color: [f32; 3],
+	pub specular_color: [f32; 3],
+	pub emissive_color: [f32; 3],
+	// specular exponent
+	pub shininess: f32,
+	pub opacity: f32,
+	// MTL illumination model, specular highlights are only drawn from model 2 onwards
+	pub illumination_model: u32
 }
 
 impl Default for MtlInfo {
 	fn default() -> Self {
 		Self {
 			color: [1., 1., 1.],
-			diffuse_texture: None
+			ambient_color: [1., 1., 1.],
+			specular_color: [0., 0., 0.],
+			emissive_color: [0., 0., 0.],
+			shininess: 0.,
+			opacity: 1.,
+			illumination_model: 2,
+			diffuse_texture: None,
+			specular_texture: None,
+			normal_texture: None,
+			alpha_texture: None,
+			emissive_texture: None
 		}
 	}
 }
@@ -40,6 +66,7 @@ pub struct EnvDrawInfo<'a> {
 	pub textures: &'a HashMap<String, Texture2d>
 }
 
+#[derive(Copy, Clone)]
 pub struct ObjDrawInfo {
 	pub position: [f32; 3],
 	pub rotation: [f32; 3],
@@ -73,14 +100,33 @@ pub struct UIDrawInfo {
 	pub translate_after_scale: bool
 }
 
+// Part of an object drawn with a single material.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SubMesh {
+	pub index_range: Range<usize>,
+	pub material: MtlInfo
+}
+
+#[derive(Clone)]
 pub struct ObjDef {
 	pub position: [f32; 3],
-	pub vertices: VertexBuffer<Vertex>,
-	pub indices: IndexBuffer<u32>,
-	pub material: Option<MtlInfo>
+	// buffers may be shared with other objects loaded from the same file
+	pub vertices: Rc<VertexBuffer<Vertex>>,
+	pub indices: Rc<IndexBuffer<u32>>,
+	// part of the index buffer used by this object, or all of it if None
+	pub index_range: Option<Range<usize>>,
+	pub material: Option<MtlInfo>,
+	// if empty, the whole object is drawn with the material above
+	pub submeshes: Vec<SubMesh>
+}
+
+impl ObjDef {
+	pub fn index_slice(&self) -> IndexBufferSlice<'_, u32> {
+		self.indices.slice(self.index_range.clone().unwrap_or(0..self.indices.len())).unwrap()
+	}
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Light {
 	pub position: [f32; 3],
@@ -107,10 +153,18 @@ struct BasicDrawUniforms<'a> {
 	view: [[f32; 4]; 4],
 	perspective: [[f32; 4]; 4],
 	mtl_color: [f32; 3],
+	ambient_color: [f32; 3],
+	specular_color: [f32; 3],
+	emissive_color: [f32; 3],
+	shininess: f32,
+	opacity: f32,
 	obj_color: [f32; 3],
 	texcoord_displacement: [f32; 2],
 	min_text_val: [f32; 4],
-	tex: &'a Texture2d
+	tex: &'a Texture2d,
+	specular_tex: &'a Texture2d,
+	alpha_tex: &'a Texture2d,
+	emissive_tex: &'a Texture2d
 }
 
 impl Uniforms for BasicDrawUniforms<'_> {
@@ -126,15 +180,27 @@ impl Uniforms for BasicDrawUniforms<'_> {
 		func("view", UniformValue::Mat4(self.view));
 		func("perspective", UniformValue::Mat4(self.perspective));
 		func("mtl_color", UniformValue::Vec3(self.mtl_color));
+		func("ambient_color", UniformValue::Vec3(self.ambient_color));
+		func("specular_color", UniformValue::Vec3(self.specular_color));
+		func("emissive_color", UniformValue::Vec3(self.emissive_color));
+		func("shininess", UniformValue::Float(self.shininess));
+		func("opacity", UniformValue::Float(self.opacity));
 		func("obj_color", UniformValue::Vec3(self.obj_color));
 		func("texcoord_displacement", UniformValue::Vec2(self.texcoord_displacement));
 		func("tex", UniformValue::Texture2d(self.tex, None));
+		func("specular_tex", UniformValue::Texture2d(self.specular_tex, None));
+		func("alpha_tex", UniformValue::Texture2d(self.alpha_tex, None));
+		func("emissive_tex", UniformValue::Texture2d(self.emissive_tex, None));
 		func("min_text_val", UniformValue::Vec4(self.min_text_val));
 	}
 }
 
 impl ObjDrawInfo {
 	pub fn generate_matrix(&mut self) {
+		self.model_mat = Some(self.compute_matrix());
+	}
+
+	pub fn compute_matrix(&self) -> [[f32; 4]; 4] {
 		let rotation_matrix = [
 			[
 				self.rotation[2].cos() * self.rotation[1].cos(),
@@ -175,7 +241,7 @@ impl ObjDrawInfo {
 			[self.position[0], self.position[1], self.position[2], 1.0f32]
 		];
 
-		self.model_mat = Some(mult_matrix(&mult_matrix(&rotation_matrix, &scale_matrix), &translate_matrix));
+		mult_matrix(&mult_matrix(&rotation_matrix, &scale_matrix), &translate_matrix)
 	}
 }
 
@@ -221,30 +287,66 @@ impl UIDrawInfo {
 
 pub fn load_data_to_gpu(display: &Display, vertices: &[Vertex], indices: &[u32]) -> ObjDef {
 	ObjDef {
-		vertices: glium::VertexBuffer::new(display, &vertices).unwrap(),
-		indices: glium::IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList, &indices).unwrap(),
+		vertices: Rc::new(glium::VertexBuffer::new(display, &vertices).unwrap()),
+		indices: Rc::new(glium::IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList, &indices).unwrap()),
+		index_range: None,
 		position: vertices[0].position,
-		material: None
+		material: None,
+		submeshes: Vec::new()
 	}
 }
 
+// Uploads the data once, returning an object for each index range that draws only that part of the buffers.
+pub fn load_shared_data_to_gpu(display: &Display, vertices: &[Vertex], indices: &[u32], index_ranges: &[Range<usize>]) -> Vec<ObjDef> {
+	let vertex_buffer = Rc::new(glium::VertexBuffer::new(display, vertices).unwrap());
+	let index_buffer = Rc::new(glium::IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList, indices).unwrap());
+	index_ranges.iter().map(|range| ObjDef {
+		vertices: vertex_buffer.clone(),
+		indices: index_buffer.clone(),
+		index_range: Some(range.clone()),
+		position: vertices[indices[range.start] as usize].position,
+		material: None,
+		submeshes: Vec::new()
+	}).collect()
+}
+
 pub fn basic_render(target: &mut Frame, env_info: &EnvDrawInfo, obj_info: &ObjDrawInfo, obj_def: &ObjDef,
 	program: &Program, texcoord_displacement: Option<[f32; 2]>) {
+	if obj_def.submeshes.is_empty() {
+		let default_material = MtlInfo::default();
+		render_part(target, env_info, obj_info, &obj_def.vertices, obj_def.index_slice(),
+			obj_def.material.as_ref().unwrap_or(&default_material), program, texcoord_displacement);
+	}
+	for submesh in obj_def.submeshes.iter() {
+		render_part(target, env_info, obj_info, &obj_def.vertices, obj_def.indices.slice(submesh.index_range.clone()).unwrap(),
+			&submesh.material, program, texcoord_displacement);
+	}
+}
+
+fn render_part(target: &mut Frame, env_info: &EnvDrawInfo, obj_info: &ObjDrawInfo, vertices: &VertexBuffer<Vertex>,
+	indices: IndexBufferSlice<u32>, material: &MtlInfo, program: &Program, texcoord_displacement: Option<[f32; 2]>) {
+	let texture = |name: &Option<String>| env_info.textures.get(name.as_deref().unwrap_or(textures::WHITE)).unwrap();
 	let uniforms = BasicDrawUniforms {
 		model: *obj_info.model_mat.as_ref().unwrap(),
 		view: env_info.view_mat,
 		perspective: env_info.perspective_mat,
 		lights: env_info.lights,
 		light_count: env_info.light_count as i32,
-		mtl_color: obj_def.material.as_ref().unwrap().color,
+		mtl_color: material.color,
+		ambient_color: material.ambient_color,
+		specular_color: if material.illumination_model >= 2 { material.specular_color } else { [0., 0., 0.] },
+		emissive_color: material.emissive_color,
+		shininess: material.shininess,
+		opacity: material.opacity,
 		obj_color: obj_info.color,
 		texcoord_displacement: texcoord_displacement.unwrap_or([0., 0.]),
-		min_text_val: if obj_def.material.as_ref().unwrap().diffuse_texture.is_none() { [1., 1., 1., 1.0f32] } else { [0., 0., 0., 0.0f32] },
-		tex: env_info.textures.get(
-			obj_def.material.as_ref().unwrap().diffuse_texture.as_ref().unwrap_or(&textures::WHITE.to_string())
-		).unwrap()
+		min_text_val: if material.diffuse_texture.is_none() { [1., 1., 1., 1.0f32] } else { [0., 0., 0., 0.0f32] },
+		tex: texture(&material.diffuse_texture),
+		specular_tex: texture(&material.specular_texture),
+		alpha_tex: texture(&material.alpha_texture),
+		emissive_tex: texture(&material.emissive_texture)
 	};
-	target.draw(&obj_def.vertices, &obj_def.indices, program, &uniforms, env_info.params).unwrap();
+	target.draw(vertices, indices, program, &uniforms, env_info.params).unwrap();
 }
 
 pub fn ui_draw(target: &mut Frame, obj_def: &ObjDef, ui_draw_info: &UIDrawInfo, program: &Program, texture: &SrgbTexture2d) {
@@ -261,5 +363,5 @@ pub fn ui_draw(target: &mut Frame, obj_def: &ObjDef, ui_draw_info: &UIDrawInfo,
 		clip_planes_bitmask: 1,
 		..Default::default()
 	};
-	target.draw(&obj_def.vertices, &obj_def.indices, program, &uniforms, &params).unwrap();
+	target.draw(&*obj_def.vertices, obj_def.index_slice(), program, &uniforms, &params).unwrap();
 }
@@ -0,0 +1,454 @@
+use std::any::{Any, TypeId};
+use std::cell::{RefCell, Ref, RefMut};
+use std::collections::{HashMap, BTreeMap};
+use std::rc::Rc;
+use std::sync::Arc;
+use glium::{Frame, Program};
+use serde::{Serialize, Deserialize};
+use crate::draw::{basic_render, ObjDef, ObjDrawInfo, EnvDrawInfo};
+use crate::quadoctree::{CollisionObj, QuadOctreeError};
+use crate::collider::{CollisionFilter, InstanceId};
+use crate::spatial::{SpatialIndex, add_instance_to_index, move_instance_in_index, remove_instance_from_index};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Entity {
+	pub index: u32,
+	pub generation: u32
+}
+
+// Components are stored densely by entity index, so iteration follows entity order.
+pub struct Storage<T> {
+	items: Vec<Option<(u32, T)>>,
+	count: usize
+}
+
+impl<T> Storage<T> {
+	fn new() -> Self {
+		Self {
+			items: Vec::new(),
+			count: 0
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.count
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.count == 0
+	}
+
+	pub fn contains(&self, entity: Entity) -> bool {
+		self.get(entity).is_some()
+	}
+
+	pub fn get(&self, entity: Entity) -> Option<&T> {
+		match self.items.get(entity.index as usize) {
+			Some(Some((generation, item))) if *generation == entity.generation => Some(item),
+			_ => None
+		}
+	}
+
+	pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+		match self.items.get_mut(entity.index as usize) {
+			Some(Some((generation, item))) if *generation == entity.generation => Some(item),
+			_ => None
+		}
+	}
+
+	fn insert(&mut self, entity: Entity, item: T) -> Option<T> {
+		let index = entity.index as usize;
+		if self.items.len() <= index {
+			self.items.resize_with(index + 1, || None);
+		}
+		let previous = self.items[index].replace((entity.generation, item));
+		match previous {
+			Some((generation, item)) if generation == entity.generation => Some(item),
+			Some(_) => None,
+			None => {
+				self.count += 1;
+				None
+			}
+		}
+	}
+
+	fn remove(&mut self, entity: Entity) -> Option<T> {
+		if !self.contains(entity) {
+			return None;
+		}
+		self.count -= 1;
+		self.items[entity.index as usize].take().map(|(_, item)| item)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+		self.items.iter().enumerate().filter_map(|(index, item)| item.as_ref()
+			.map(|(generation, item)| (Entity { index: index as u32, generation: *generation }, item)))
+	}
+
+	pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+		self.items.iter_mut().enumerate().filter_map(|(index, item)| item.as_mut()
+			.map(|(generation, item)| (Entity { index: index as u32, generation: *generation }, item)))
+	}
+}
+
+trait AnyStorage {
+	fn remove_entity(&mut self, entity: Entity);
+	fn as_any(&self) -> &dyn Any;
+	fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+	fn remove_entity(&mut self, entity: Entity) {
+		self.remove(entity);
+	}
+
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+}
+
+// Entities with typed component storages and resources. Each storage sits in its own
+// RefCell, so several component types can be borrowed at once as long as no single
+// type is borrowed mutably twice.
+#[derive(Default)]
+pub struct World {
+	generations: Vec<u32>,
+	alive: Vec<bool>,
+	free: Vec<u32>,
+	storages: HashMap<TypeId, RefCell<Box<dyn AnyStorage>>>,
+	resources: HashMap<TypeId, Box<dyn Any>>
+}
+
+impl World {
+	pub fn new() -> Self {
+		Default::default()
+	}
+
+	pub fn spawn(&mut self) -> Entity {
+		if let Some(index) = self.free.pop() {
+			self.alive[index as usize] = true;
+			return Entity { index: index, generation: self.generations[index as usize] };
+		}
+		self.generations.push(0);
+		self.alive.push(true);
+		Entity { index: (self.generations.len() - 1) as u32, generation: 0 }
+	}
+
+	pub fn despawn(&mut self, entity: Entity) -> bool {
+		if !self.is_alive(entity) {
+			return false;
+		}
+		for storage in self.storages.values() {
+			storage.borrow_mut().remove_entity(entity);
+		}
+		let index = entity.index as usize;
+		self.alive[index] = false;
+		self.generations[index] += 1;
+		self.free.push(entity.index);
+		true
+	}
+
+	pub fn is_alive(&self, entity: Entity) -> bool {
+		let index = entity.index as usize;
+		index < self.alive.len() && self.alive[index] && self.generations[index] == entity.generation
+	}
+
+	pub fn entities(&self) -> Vec<Entity> {
+		(0..self.alive.len()).filter(|i| self.alive[*i])
+			.map(|i| Entity { index: i as u32, generation: self.generations[i] }).collect()
+	}
+
+	pub fn register<T: 'static>(&mut self) {
+		self.storages.entry(TypeId::of::<T>()).or_insert_with(|| RefCell::new(Box::new(Storage::<T>::new())));
+	}
+
+	// Adds or replaces a component, returning the previous one. Dead entities are ignored.
+	pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T> {
+		if !self.is_alive(entity) {
+			return None;
+		}
+		self.register::<T>();
+		self.storage_mut::<T>().insert(entity, component)
+	}
+
+	pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+		self.storages.get(&TypeId::of::<T>())?;
+		self.storage_mut::<T>().remove(entity)
+	}
+
+	pub fn has<T: 'static>(&self, entity: Entity) -> bool {
+		self.storages.contains_key(&TypeId::of::<T>()) && self.storage::<T>().contains(entity)
+	}
+
+	// Panics if the component type was never registered or inserted, or is borrowed mutably.
+	pub fn storage<T: 'static>(&self) -> Ref<'_, Storage<T>> {
+		let storage = self.storages.get(&TypeId::of::<T>()).expect("component type not registered");
+		Ref::map(storage.borrow(), |s| s.as_any().downcast_ref::<Storage<T>>().unwrap())
+	}
+
+	// Panics if the component type was never registered or inserted, or is already borrowed.
+	pub fn storage_mut<T: 'static>(&self) -> RefMut<'_, Storage<T>> {
+		let storage = self.storages.get(&TypeId::of::<T>()).expect("component type not registered");
+		RefMut::map(storage.borrow_mut(), |s| s.as_any_mut().downcast_mut::<Storage<T>>().unwrap())
+	}
+
+	pub fn query<A: 'static>(&self) -> Vec<Entity> {
+		if !self.storages.contains_key(&TypeId::of::<A>()) {
+			return Vec::new();
+		}
+		self.storage::<A>().iter().map(|(entity, _)| entity).collect()
+	}
+
+	pub fn query2<A: 'static, B: 'static>(&self) -> Vec<Entity> {
+		if !self.storages.contains_key(&TypeId::of::<B>()) {
+			return Vec::new();
+		}
+		let b = self.storage::<B>();
+		self.query::<A>().into_iter().filter(|entity| b.contains(*entity)).collect()
+	}
+
+	// Calls the function for every entity that has both components. A and B must differ.
+	pub fn each2_mut<A: 'static, B: 'static, F>(&self, mut func: F) where F: FnMut(Entity, &mut A, &B) {
+		let entities = self.query2::<A, B>();
+		if entities.is_empty() {
+			return;
+		}
+		let mut a = self.storage_mut::<A>();
+		let b = self.storage::<B>();
+		for entity in entities {
+			func(entity, a.get_mut(entity).unwrap(), b.get(entity).unwrap());
+		}
+	}
+
+	pub fn insert_resource<T: 'static>(&mut self, resource: T) {
+		self.resources.insert(TypeId::of::<T>(), Box::new(resource));
+	}
+
+	pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+		self.resources.remove(&TypeId::of::<T>()).map(|r| *r.downcast::<T>().unwrap())
+	}
+
+	pub fn resource<T: 'static>(&self) -> Option<&T> {
+		self.resources.get(&TypeId::of::<T>()).and_then(|r| r.downcast_ref::<T>())
+	}
+
+	pub fn resource_mut<T: 'static>(&mut self) -> Option<&mut T> {
+		self.resources.get_mut(&TypeId::of::<T>()).and_then(|r| r.downcast_mut::<T>())
+	}
+}
+
+pub type System = Box<dyn FnMut(&mut World, f32)>;
+
+// Systems run in the order they were added.
+#[derive(Default)]
+pub struct Schedule {
+	systems: Vec<(String, System)>
+}
+
+impl Schedule {
+	pub fn new() -> Self {
+		Default::default()
+	}
+
+	pub fn add_system<F>(&mut self, name: &str, system: F) where F: FnMut(&mut World, f32) + 'static {
+		self.systems.push((name.to_string(), Box::new(system)));
+	}
+
+	pub fn remove_system(&mut self, name: &str) -> bool {
+		let len = self.systems.len();
+		self.systems.retain(|(system_name, _)| system_name != name);
+		len != self.systems.len()
+	}
+
+	pub fn system_names(&self) -> Vec<&str> {
+		self.systems.iter().map(|(name, _)| name.as_str()).collect()
+	}
+
+	pub fn run(&mut self, world: &mut World, time_delta: f32) {
+		for (_, system) in self.systems.iter_mut() {
+			system(world, time_delta);
+		}
+	}
+}
+
+// Built-in components
+
+pub type Transform = ObjDrawInfo;
+
+pub struct Renderable {
+	pub model: Rc<BTreeMap<String, ObjDef>>
+}
+
+pub struct Collider {
+	pub shape: Arc<CollisionObj>,
+	pub filter: CollisionFilter
+}
+
+impl Collider {
+	pub fn new(shape: Arc<CollisionObj>, filter: CollisionFilter) -> Self {
+		Self {
+			shape: shape,
+			filter: filter
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Replicated {
+	pub net_id: u32
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum EntityMessage {
+	Transform { net_id: u32, position: [f32; 3], rotation: [f32; 3], scale: [f32; 3] },
+	Despawn { net_id: u32 }
+}
+
+// Instance in the collision index and the transform it was added with, per entity.
+// Kept by sync_colliders as a resource, so that it outlives despawned entities.
+#[derive(Default)]
+struct IndexedColliders {
+	instances: HashMap<Entity, (InstanceId, [[f32; 4]; 4])>
+}
+
+// Net id and last transform sent, per replicated entity. Kept by replication_messages
+// as a resource, so that it outlives despawned entities.
+#[derive(Default)]
+struct ReplicatedState {
+	sent: HashMap<Entity, (u32, [f32; 3], [f32; 3], [f32; 3])>
+}
+
+// Adds entities with a collider and transform to the index, moves the ones whose
+// transform changed since the last call, and removes the instances of entities that
+// were despawned or lost either component.
+pub fn sync_colliders(world: &mut World, index: &mut dyn SpatialIndex) -> Result<(), QuadOctreeError> {
+	let mut indexed = world.remove_resource::<IndexedColliders>().unwrap_or_default();
+	let result = sync_indexed_colliders(world, index, &mut indexed);
+	world.insert_resource(indexed);
+	result
+}
+
+fn sync_indexed_colliders(world: &World, index: &mut dyn SpatialIndex,
+	indexed: &mut IndexedColliders) -> Result<(), QuadOctreeError> {
+	indexed.instances.retain(|entity, (id, _)| {
+		if world.has::<Collider>(*entity) && world.has::<Transform>(*entity) {
+			return true;
+		}
+		remove_instance_from_index(index, *id);
+		false
+	});
+	let entities = world.query2::<Collider, Transform>();
+	if entities.is_empty() {
+		return Ok(());
+	}
+	let colliders = world.storage::<Collider>();
+	let transforms = world.storage::<Transform>();
+	for entity in entities {
+		let collider = colliders.get(entity).unwrap();
+		let transform = transforms.get(entity).unwrap();
+		let matrix = transform.model_mat.unwrap_or_else(|| transform.compute_matrix());
+		let id = match indexed.instances.get(&entity) {
+			None => add_instance_to_index(index, &collider.shape, transform, collider.filter)?,
+			Some((id, previous)) if *previous != matrix => {
+				move_instance_in_index(index, *id, transform.into())?;
+				*id
+			},
+			Some((id, _)) => *id
+		};
+		indexed.instances.insert(entity, (id, matrix));
+	}
+	Ok(())
+}
+
+// Returns transform updates for replicated entities that moved since the last call,
+// and despawns for the ones that were despawned or are no longer replicated.
+pub fn replication_messages(world: &mut World) -> Vec<EntityMessage> {
+	let mut state = world.remove_resource::<ReplicatedState>().unwrap_or_default();
+	let mut result = Vec::new();
+	state.sent.retain(|entity, (net_id, ..)| {
+		if world.has::<Replicated>(*entity) {
+			return true;
+		}
+		result.push(EntityMessage::Despawn { net_id: *net_id });
+		false
+	});
+	let entities = world.query2::<Replicated, Transform>();
+	if !entities.is_empty() {
+		let replicated = world.storage::<Replicated>();
+		let transforms = world.storage::<Transform>();
+		for entity in entities {
+			let net_id = replicated.get(entity).unwrap().net_id;
+			let transform = transforms.get(entity).unwrap();
+			let current = (net_id, transform.position, transform.rotation, transform.scale);
+			if state.sent.get(&entity) != Some(&current) {
+				state.sent.insert(entity, current);
+				result.push(EntityMessage::Transform {
+					net_id: net_id,
+					position: transform.position,
+					rotation: transform.rotation,
+					scale: transform.scale
+				});
+			}
+		}
+	}
+	world.insert_resource(state);
+	result
+}
+
+pub fn find_replicated(world: &World, net_id: u32) -> Option<Entity> {
+	if !world.storages.contains_key(&TypeId::of::<Replicated>()) {
+		return None;
+	}
+	world.storage::<Replicated>().iter().find(|(_, r)| r.net_id == net_id).map(|(entity, _)| entity)
+}
+
+// Applies a replication message on the client, spawning the entity if it is new.
+// Returns the affected entity.
+pub fn apply_replication(world: &mut World, msg: &EntityMessage) -> Option<Entity> {
+	match *msg {
+		EntityMessage::Transform { net_id, position, rotation, scale } => {
+			let entity = find_replicated(world, net_id).unwrap_or_else(|| {
+				let entity = world.spawn();
+				world.insert(entity, Replicated { net_id: net_id });
+				entity
+			});
+			let mut transform = Transform {
+				position: position,
+				rotation: rotation,
+				scale: scale,
+				..Default::default()
+			};
+			transform.generate_matrix();
+			world.insert(entity, transform);
+			Some(entity)
+		},
+		EntityMessage::Despawn { net_id } => {
+			let entity = find_replicated(world, net_id)?;
+			world.despawn(entity);
+			Some(entity)
+		}
+	}
+}
+
+pub fn draw_renderables(world: &World, target: &mut Frame, env_info: &EnvDrawInfo, program: &Program) {
+	if world.query2::<Renderable, Transform>().is_empty() {
+		return;
+	}
+	let renderables = world.storage::<Renderable>();
+	let transforms = world.storage::<Transform>();
+	for (entity, renderable) in renderables.iter() {
+		if let Some(transform) = transforms.get(entity) {
+			let mut draw_info = *transform;
+			if draw_info.model_mat.is_none() {
+				draw_info.generate_matrix();
+			}
+			for def in renderable.model.values() {
+				basic_render(target, env_info, &draw_info, def, program, None);
+			}
+		}
+	}
+}
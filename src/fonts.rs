@@ -1,6 +1,4 @@
-use std::fs::File;
 use std::io;
-use std::io::Read;
 use std::collections::HashMap;
 use std::hash::Hasher;
 use std::collections::hash_map::DefaultHasher;
@@ -9,7 +7,7 @@ use derive_more::{Error, From};
 use crate::draw::{Vertex, load_data_to_gpu, ObjDef};
 use crate::draw::{UIDrawInfo, ui_draw};
 use glium::{Display, Frame, texture::{SrgbTexture2d, RawImage2d, TextureCreationError}};
-use crate::assets::find_asset;
+use crate::assets::read_asset;
 
 pub enum TextAlign {
 	Left,
@@ -48,9 +46,7 @@ pub enum FontError {
 
 impl LoadedFont {
 	pub fn load(display: &Display, filename: &str, app_id: &str, font_size: f32) -> Result<Self, FontError> {
-		let mut f = File::open(find_asset(filename, app_id))?;
-		let mut f_contents = Vec::new();
-		f.read_to_end(&mut f_contents)?;
+		let f_contents = read_asset(filename, app_id)?;
 
 		let font = Font::try_from_vec(f_contents).ok_or(FontError::LoadError)?;
 		let scale = Scale::uniform(font_size);
@@ -0,0 +1,574 @@
+use std::io;
+use std::collections::{HashMap, HashSet, BTreeMap};
+use std::path::Path;
+use glium::{Display, texture::Texture2d};
+use derive_more::{Error, From};
+use ::gltf::{Gltf, Node, Primitive};
+use ::gltf::mesh::Mode;
+use ::gltf::material::AlphaMode;
+use ::gltf::animation::util::ReadOutputs;
+use ::gltf::scene::Transform;
+use crate::draw::{Vertex, MtlInfo};
+use crate::wavefront::{WavefrontLoadError, MeshBuilder, LoadTargets, process_obj};
+use crate::mesh::Mesh;
+use crate::textures::{load_asset_texture, load_texture_from_memory, load_named_texture, TextureLoadError};
+use crate::assets::read_asset;
+use crate::math::{mult_matrix, transform_point, invert_matrix3, normalize_vector, cross_product, add_vector, vector_length};
+
+const IDENTITY: [[f32; 4]; 4] = [[1., 0., 0., 0.], [0., 1., 0., 0.], [0., 0., 1., 0.], [0., 0., 0., 1.]];
+
+#[derive(Debug, derive_more::Display, Error, From)]
+pub enum GltfLoadError {
+	#[from(ignore)]
+	FormatError { msg: &'static str },
+	IOError(io::Error),
+	GltfError(::gltf::Error),
+	Base64Error(base64::DecodeError),
+	TextureLoadError(TextureLoadError),
+	// from sorting objects into collision meshes, lights and the like
+	WavefrontLoadError(WavefrontLoadError)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NodeTransform {
+	pub translation: [f32; 3],
+	// quaternion as x, y, z, w
+	pub rotation: [f32; 4],
+	pub scale: [f32; 3]
+}
+
+impl NodeTransform {
+	pub fn matrix(&self) -> [[f32; 4]; 4] {
+		Transform::Decomposed { translation: self.translation, rotation: self.rotation, scale: self.scale }.matrix()
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfNode {
+	pub name: String,
+	pub parent: Option<usize>,
+	pub children: Vec<usize>,
+	// rest pose, relative to the parent
+	pub transform: NodeTransform,
+	// rest pose, relative to the scene root
+	pub global_matrix: [[f32; 4]; 4],
+	// name of the entry in GltfModel::meshes created from the mesh of this node
+	pub object: Option<String>,
+	pub skin: Option<usize>
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfSkin {
+	pub name: String,
+	// node indices
+	pub joints: Vec<usize>,
+	pub inverse_bind_matrices: Vec<[[f32; 4]; 4]>,
+	pub skeleton: Option<usize>
+}
+
+// Joint indices refer to GltfSkin::joints of the skin of the object.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct VertexSkin {
+	pub joints: [u16; 4],
+	pub weights: [f32; 4]
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interpolation {
+	Linear,
+	Step,
+	CubicSpline
+}
+
+// Cubic spline channels hold an in-tangent, value and out-tangent per keyframe.
+#[derive(Debug, Clone)]
+pub enum ChannelValues {
+	Translation(Vec<[f32; 3]>),
+	Rotation(Vec<[f32; 4]>),
+	Scale(Vec<[f32; 3]>),
+	MorphWeights(Vec<f32>)
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfChannel {
+	pub node: usize,
+	pub interpolation: Interpolation,
+	pub times: Vec<f32>,
+	pub values: ChannelValues
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfAnimation {
+	pub name: String,
+	pub channels: Vec<GltfChannel>,
+	pub duration: f32
+}
+
+pub struct GltfModel {
+	pub meshes: BTreeMap<String, Mesh>,
+	pub materials: Vec<MtlInfo>,
+	pub nodes: Vec<GltfNode>,
+	pub skins: Vec<GltfSkin>,
+	// joints and weights of skinned meshes, in the same order as the vertices of the mesh
+	pub vertex_skins: BTreeMap<String, Vec<VertexSkin>>,
+	pub animations: Vec<GltfAnimation>
+}
+
+// Finds the keyframes around the time, and how far along between them the time is.
+fn keyframe_at(times: &[f32], time: f32) -> (usize, usize, f32) {
+	let next = times.partition_point(|t| *t <= time);
+	if next == 0 {
+		(0, 0, 0.)
+	} else if next >= times.len() {
+		(times.len() - 1, times.len() - 1, 0.)
+	} else {
+		let (start, end) = (times[next - 1], times[next]);
+		(next - 1, next, ((time - start) / (end - start).max(f32::EPSILON)).clamp(0., 1.))
+	}
+}
+
+fn sample_values<const N: usize>(values: &[[f32; N]], times: &[f32], interpolation: Interpolation, time: f32) -> [f32; N] {
+	let (prev, next, factor) = keyframe_at(times, time);
+	let mut result = [0.; N];
+	match interpolation {
+		Interpolation::Step => return values[prev],
+		Interpolation::Linear => {
+			for i in 0..N {
+				result[i] = values[prev][i] + (values[next][i] - values[prev][i]) * factor;
+			}
+		},
+		Interpolation::CubicSpline => {
+			let delta = times[next] - times[prev];
+			let (t, t2, t3) = (factor, factor * factor, factor * factor * factor);
+			for i in 0..N {
+				let start = values[prev * 3 + 1][i];
+				let start_tangent = values[prev * 3 + 2][i] * delta;
+				let end = values[next * 3 + 1][i];
+				let end_tangent = values[next * 3][i] * delta;
+				result[i] = (2. * t3 - 3. * t2 + 1.) * start + (t3 - 2. * t2 + t) * start_tangent
+					+ (-2. * t3 + 3. * t2) * end + (t3 - t2) * end_tangent;
+			}
+		}
+	}
+	result
+}
+
+fn normalize_quaternion(q: [f32; 4]) -> [f32; 4] {
+	let length = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt().max(f32::EPSILON);
+	[q[0] / length, q[1] / length, q[2] / length, q[3] / length]
+}
+
+fn slerp(a: [f32; 4], b: [f32; 4], factor: f32) -> [f32; 4] {
+	let mut dot = a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3];
+	// take the short way around
+	let b = if dot < 0. {
+		dot = -dot;
+		[-b[0], -b[1], -b[2], -b[3]]
+	} else { b };
+	if dot > 0.9995 {
+		return normalize_quaternion([a[0] + (b[0] - a[0]) * factor, a[1] + (b[1] - a[1]) * factor,
+			a[2] + (b[2] - a[2]) * factor, a[3] + (b[3] - a[3]) * factor]);
+	}
+	let angle = dot.acos();
+	let (wa, wb) = (((1. - factor) * angle).sin() / angle.sin(), (factor * angle).sin() / angle.sin());
+	[a[0] * wa + b[0] * wb, a[1] * wa + b[1] * wb, a[2] * wa + b[2] * wb, a[3] * wa + b[3] * wb]
+}
+
+impl GltfChannel {
+	// Morph target weights are not applied, since meshes have no morph targets.
+	fn apply(&self, time: f32, transforms: &mut [NodeTransform]) {
+		if self.times.is_empty() {
+			return;
+		}
+		let transform = match transforms.get_mut(self.node) {
+			Some(transform) => transform,
+			None => return
+		};
+		match &self.values {
+			ChannelValues::Translation(values) => transform.translation = sample_values(values, &self.times, self.interpolation, time),
+			ChannelValues::Scale(values) => transform.scale = sample_values(values, &self.times, self.interpolation, time),
+			ChannelValues::Rotation(values) => transform.rotation = match self.interpolation {
+				Interpolation::Linear => {
+					let (prev, next, factor) = keyframe_at(&self.times, time);
+					slerp(values[prev], values[next], factor)
+				},
+				_ => normalize_quaternion(sample_values(values, &self.times, self.interpolation, time))
+			},
+			ChannelValues::MorphWeights(_) => ()
+		}
+	}
+}
+
+impl GltfAnimation {
+	// Poses the node transforms at the time, which wraps around at the end of the animation.
+	pub fn sample(&self, time: f32, transforms: &mut [NodeTransform]) {
+		let time = if self.duration > 0. { time.rem_euclid(self.duration) } else { 0. };
+		for channel in self.channels.iter() {
+			channel.apply(time, transforms);
+		}
+	}
+}
+
+impl GltfModel {
+	pub fn rest_transforms(&self) -> Vec<NodeTransform> {
+		self.nodes.iter().map(|node| node.transform).collect()
+	}
+
+	// Matrices relative to the scene root for the given local node transforms.
+	pub fn global_matrices(&self, transforms: &[NodeTransform]) -> Vec<[[f32; 4]; 4]> {
+		let mut result = vec![IDENTITY; self.nodes.len()];
+		let mut stack: Vec<usize> = (0..self.nodes.len()).filter(|i| self.nodes[*i].parent.is_none()).collect();
+		while let Some(i) = stack.pop() {
+			let local = transforms[i].matrix();
+			result[i] = match self.nodes[i].parent {
+				Some(parent) => mult_matrix(&local, &result[parent]),
+				None => local
+			};
+			stack.extend_from_slice(&self.nodes[i].children);
+		}
+		result
+	}
+
+	pub fn animate(&self, animation: usize, time: f32) -> Vec<[[f32; 4]; 4]> {
+		let mut transforms = self.rest_transforms();
+		if let Some(animation) = self.animations.get(animation) {
+			animation.sample(time, &mut transforms);
+		}
+		self.global_matrices(&transforms)
+	}
+
+	pub fn find_animation(&self, name: &str) -> Option<usize> {
+		self.animations.iter().position(|animation| animation.name == name)
+	}
+
+	// Matrices that move skinned vertices from the bind pose to the pose of the global matrices.
+	pub fn joint_matrices(&self, skin: usize, global_matrices: &[[[f32; 4]; 4]]) -> Vec<[[f32; 4]; 4]> {
+		let skin = &self.skins[skin];
+		skin.joints.iter().enumerate().map(|(i, joint)| {
+			let inverse_bind = skin.inverse_bind_matrices.get(i).unwrap_or(&IDENTITY);
+			mult_matrix(inverse_bind, &global_matrices[*joint])
+		}).collect()
+	}
+}
+
+// Only base64 data URIs are supported.
+fn decode_data_uri(uri: &str) -> Result<Vec<u8>, GltfLoadError> {
+	let mut split = uri.splitn(2, ',');
+	let header = split.next().unwrap_or("");
+	let data = split.next().ok_or(GltfLoadError::FormatError { msg: "Data URI has no data" })?;
+	if !header.ends_with(";base64") {
+		return Err(GltfLoadError::FormatError { msg: "Data URI is not base64 encoded" });
+	}
+	Ok(base64::decode(data)?)
+}
+
+fn load_buffers(gltf: &Gltf, parent_dir: &Path, app_id: &str) -> Result<Vec<Vec<u8>>, GltfLoadError> {
+	gltf.buffers().map(|buffer| match buffer.source() {
+		::gltf::buffer::Source::Bin => gltf.blob.clone()
+			.ok_or(GltfLoadError::FormatError { msg: "Buffer refers to missing GLB binary chunk" }),
+		::gltf::buffer::Source::Uri(uri) if uri.starts_with("data:") => decode_data_uri(uri),
+		::gltf::buffer::Source::Uri(uri) => Ok(read_asset(parent_dir.join(uri), app_id)?)
+	}).collect()
+}
+
+// Embedded images are named after the model file and the image index.
+fn load_image(display: &Display, textures: &mut HashMap<String, Texture2d>, image: ::gltf::Image,
+	buffers: &[Vec<u8>], parent_dir: &Path, app_id: &str, filename: &str) -> Result<String, GltfLoadError> {
+	let embedded_name = format!("{}#image{}", filename, image.index());
+	Ok(match image.source() {
+		::gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+			let path = parent_dir.join(uri);
+			load_named_texture(display, textures, uri, || load_asset_texture(display, &path, app_id, true))?
+		},
+		::gltf::image::Source::Uri { uri, .. } => {
+			let data = decode_data_uri(uri)?;
+			load_named_texture(display, textures, &embedded_name, || load_texture_from_memory(display, &data, true))?
+		},
+		::gltf::image::Source::View { view, .. } => {
+			let data = buffers.get(view.buffer().index())
+				.and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+				.ok_or(GltfLoadError::FormatError { msg: "Image buffer view is out of bounds" })?;
+			load_named_texture(display, textures, &embedded_name, || load_texture_from_memory(display, data, true))?
+		}
+	})
+}
+
+// The metallic-roughness model is approximated with the Phong parameters of MtlInfo.
+fn load_material(display: Option<&Display>, textures: Option<&mut HashMap<String, Texture2d>>, material: &::gltf::Material,
+	buffers: &[Vec<u8>], parent_dir: &Path, app_id: &str, filename: &str) -> Result<MtlInfo, GltfLoadError> {
+	let pbr = material.pbr_metallic_roughness();
+	let base = pbr.base_color_factor();
+	let metallic = pbr.metallic_factor();
+	let mut result = MtlInfo {
+		color: [base[0], base[1], base[2]],
+		specular_color: [
+			0.04 + (base[0] - 0.04) * metallic,
+			0.04 + (base[1] - 0.04) * metallic,
+			0.04 + (base[2] - 0.04) * metallic
+		],
+		emissive_color: material.emissive_factor(),
+		shininess: ((1. - pbr.roughness_factor()) * 256.).max(1.),
+		opacity: if material.alpha_mode() == AlphaMode::Opaque { 1. } else { base[3] },
+		..Default::default()
+	};
+
+	if let (Some(display), Some(textures)) = (display, textures) {
+		let mut load = |texture: ::gltf::Texture| load_image(display, textures, texture.source(), buffers, parent_dir, app_id, filename);
+		result.diffuse_texture = pbr.base_color_texture().map(|info| load(info.texture())).transpose()?;
+		result.emissive_texture = material.emissive_texture().map(|info| load(info.texture())).transpose()?;
+		result.normal_texture = material.normal_texture().map(|info| load(info.texture())).transpose()?;
+	}
+	Ok(result)
+}
+
+fn primitive_triangles(mode: Mode, indices: &[u32]) -> Vec<[u32; 3]> {
+	match mode {
+		Mode::Triangles => indices.chunks(3).filter(|c| c.len() == 3).map(|c| [c[0], c[1], c[2]]).collect(),
+		// every other strip triangle is flipped to keep the winding consistent
+		Mode::TriangleStrip => (0..indices.len().saturating_sub(2)).map(|i| if i % 2 == 0 {
+			[indices[i], indices[i + 1], indices[i + 2]]
+		} else {
+			[indices[i + 1], indices[i], indices[i + 2]]
+		}).collect(),
+		Mode::TriangleFan => (1..indices.len().saturating_sub(1)).map(|i| [indices[0], indices[i], indices[i + 1]]).collect(),
+		// points and lines have no surface to draw or collide with
+		_ => Vec::new()
+	}
+}
+
+// Appends the primitive to the mesh with the transform baked in. The triangle winding is reversed
+// and texcoords are flipped to match the conventions of the Wavefront loader.
+fn add_primitive(primitive: &Primitive, buffers: &[Vec<u8>], transform: &[[f32; 4]; 4], scale: &[f32; 3],
+	mesh: &mut MeshBuilder, skin: &mut Vec<VertexSkin>) -> Result<(), GltfLoadError> {
+	let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| data.as_slice()));
+	let positions: Vec<[f32; 3]> = reader.read_positions()
+		.ok_or(GltfLoadError::FormatError { msg: "Primitive has no positions" })?.collect();
+	let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|normals| normals.collect());
+	let texcoords: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|texcoords| texcoords.into_f32().collect());
+	let joints: Option<Vec<[u16; 4]>> = reader.read_joints(0).map(|joints| joints.into_u16().collect());
+	let weights: Option<Vec<[f32; 4]>> = reader.read_weights(0).map(|weights| weights.into_f32().collect());
+	let indices: Vec<u32> = match reader.read_indices() {
+		Some(indices) => indices.into_u32().collect(),
+		None => (0..positions.len() as u32).collect()
+	};
+
+	let triangles = primitive_triangles(primitive.mode(), &indices);
+	if triangles.iter().flatten().any(|i| *i as usize >= positions.len()) {
+		return Err(GltfLoadError::FormatError { msg: "Primitive index is out of bounds" });
+	}
+
+	let linear = [
+		[transform[0][0], transform[0][1], transform[0][2]],
+		[transform[1][0], transform[1][1], transform[1][2]],
+		[transform[2][0], transform[2][1], transform[2][2]]
+	];
+	// the inverse transpose keeps normals perpendicular under non-uniform scaling
+	let normal_matrix = invert_matrix3(&linear);
+	let transform_normal = |normal: &[f32; 3]| match normal_matrix {
+		Some(m) => normalize_vector(&[
+			m[0][0] * normal[0] + m[0][1] * normal[1] + m[0][2] * normal[2],
+			m[1][0] * normal[0] + m[1][1] * normal[1] + m[1][2] * normal[2],
+			m[2][0] * normal[0] + m[2][1] * normal[1] + m[2][2] * normal[2]
+		]),
+		None => *normal
+	};
+	let vertex = |i: usize, normal: [f32; 3]| {
+		let position = transform_point(transform, &positions[i]);
+		Vertex {
+			position: [position[0] * scale[0], position[1] * scale[1], position[2] * scale[2]],
+			normal: normal,
+			texcoords: texcoords.as_ref().map(|texcoords| [texcoords[i][0], 1. - texcoords[i][1]]).unwrap_or([0., 0.])
+		}
+	};
+	let vertex_skin = |i: usize| VertexSkin {
+		joints: joints.as_ref().and_then(|joints| joints.get(i).copied()).unwrap_or_default(),
+		weights: weights.as_ref().and_then(|weights| weights.get(i).copied()).unwrap_or_default()
+	};
+
+	match normals {
+		Some(normals) => {
+			let base = mesh.vertices.len() as u32;
+			for i in 0..positions.len() {
+				mesh.vertices.push(vertex(i, transform_normal(normals.get(i).unwrap_or(&[0., 1., 0.]))));
+				skin.push(vertex_skin(i));
+			}
+			for [a, b, c] in triangles {
+				mesh.indices.extend_from_slice(&[base + c, base + b, base + a]);
+			}
+		},
+		None => {
+			// flat shading, every corner gets its own vertex
+			for [a, b, c] in triangles {
+				let (a, b, c) = (a as usize, b as usize, c as usize);
+				let face_normal = cross_product(&add_vector(&positions[b], &positions[a], -1.), &add_vector(&positions[c], &positions[a], -1.));
+				let normal = if vector_length(&face_normal) > 0. { transform_normal(&normalize_vector(&face_normal)) } else { [0., 1., 0.] };
+				for i in [c, b, a].iter() {
+					mesh.indices.push(mesh.vertices.len() as u32);
+					mesh.vertices.push(vertex(*i, normal));
+					skin.push(vertex_skin(*i));
+				}
+			}
+		}
+	}
+	Ok(())
+}
+
+fn unique_name(name: String, used: &mut HashSet<String>) -> String {
+	let mut result = name.clone();
+	let mut counter = 1;
+	while used.contains(&result) {
+		result = format!("{}.{}", name, counter);
+		counter += 1;
+	}
+	used.insert(result.clone());
+	result
+}
+
+// Loads the default scene of a glTF or GLB file. Node names take the place of OBJ object names,
+// so the same prefixes sort nodes into collision meshes, lights, triggers and the like.
+// Nodes without meshes are placed as a single point, which is enough for lights and misc_ objects.
+pub fn load_gltf(filename: &str, app_id: &str, display: Option<&Display>, mut textures: Option<&mut HashMap<String, Texture2d>>,
+	scale: &[f32; 3], mut targets: LoadTargets) -> Result<GltfModel, GltfLoadError> {
+	let parent_dir = Path::new(filename).parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+	let gltf = Gltf::from_slice(&read_asset(filename, app_id)?)?;
+	let buffers = load_buffers(&gltf, &parent_dir, app_id)?;
+
+	let materials = gltf.materials()
+		.map(|material| load_material(display, textures.as_deref_mut(), &material, &buffers, &parent_dir, app_id, filename))
+		.collect::<Result<Vec<MtlInfo>, GltfLoadError>>()?;
+
+	let mut nodes: Vec<GltfNode> = gltf.nodes().map(|node| {
+		let (translation, rotation, scale) = node.transform().decomposed();
+		GltfNode {
+			name: node.name().map(|name| name.to_string()).unwrap_or_else(|| format!("node{}", node.index())),
+			parent: None,
+			children: node.children().map(|child| child.index()).collect(),
+			transform: NodeTransform { translation: translation, rotation: rotation, scale: scale },
+			global_matrix: IDENTITY,
+			object: None,
+			skin: node.skin().map(|skin| skin.index())
+		}
+	}).collect();
+	for i in 0..nodes.len() {
+		for child in nodes[i].children.clone() {
+			nodes[child].parent = Some(i);
+		}
+	}
+
+	let roots: Vec<Node> = match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+		Some(scene) => scene.nodes().collect(),
+		None => gltf.nodes().filter(|node| nodes[node.index()].parent.is_none()).collect()
+	};
+
+	let mut mesh: MeshBuilder = Default::default();
+	let mut meshes: BTreeMap<String, Mesh> = BTreeMap::new();
+	let mut vertex_skins = BTreeMap::new();
+	let mut used_names = HashSet::new();
+
+	let mut stack: Vec<(Node, [[f32; 4]; 4])> = roots.into_iter().rev().map(|node| (node, IDENTITY)).collect();
+	while let Some((node, parent_matrix)) = stack.pop() {
+		let global_matrix = mult_matrix(&nodes[node.index()].transform.matrix(), &parent_matrix);
+		nodes[node.index()].global_matrix = global_matrix;
+		for child in node.children().collect::<Vec<Node>>().into_iter().rev() {
+			stack.push((child, global_matrix));
+		}
+
+		let name = unique_name(node.name()
+			.or_else(|| node.mesh().and_then(|mesh| mesh.name()))
+			.map(|name| name.to_string())
+			.unwrap_or_else(|| format!("node{}", node.index())), &mut used_names);
+		let mut skin = Vec::new();
+		match node.mesh() {
+			Some(node_mesh) => {
+				// skinned vertices are already in the bind pose, which ignores the node transform
+				let transform = if node.skin().is_some() { IDENTITY } else { global_matrix };
+				for primitive in node_mesh.primitives() {
+					let material = primitive.material().index().and_then(|i| materials.get(i)).cloned();
+					mesh.use_material(material);
+					add_primitive(&primitive, &buffers, &transform, scale, &mut mesh, &mut skin)?;
+				}
+				if mesh.vertices.is_empty() {
+					// drops the materials of the primitives, which would otherwise go to the next object
+					mesh.clear();
+					continue;
+				}
+			},
+			None => {
+				let position = transform_point(&global_matrix, &[0., 0., 0.]);
+				mesh.vertices.push(Vertex {
+					position: [position[0] * scale[0], position[1] * scale[1], position[2] * scale[2]],
+					normal: [0., 1., 0.],
+					texcoords: [0., 0.]
+				});
+			}
+		}
+
+		if node.mesh().is_some() {
+			nodes[node.index()].object = Some(name.clone());
+			if node.skin().is_some() {
+				vertex_skins.insert(name.clone(), skin);
+			}
+		}
+		let mut o_name = Some(name);
+		process_obj(&mut mesh, &mut targets, &mut o_name, &mut meshes)?;
+	}
+
+	let skins = gltf.skins().map(|skin| {
+		let reader = skin.reader(|buffer| buffers.get(buffer.index()).map(|data| data.as_slice()));
+		GltfSkin {
+			name: skin.name().unwrap_or_default().to_string(),
+			joints: skin.joints().map(|joint| joint.index()).collect(),
+			inverse_bind_matrices: reader.read_inverse_bind_matrices().map(|matrices| matrices.collect()).unwrap_or_default(),
+			skeleton: skin.skeleton().map(|node| node.index())
+		}
+	}).collect();
+
+	let animations = gltf.animations().map(|animation| {
+		let channels = animation.channels().map(|channel| {
+			let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(|data| data.as_slice()));
+			let times: Vec<f32> = reader.read_inputs()
+				.ok_or(GltfLoadError::FormatError { msg: "Animation channel has no keyframe times" })?.collect();
+			let values = match reader.read_outputs()
+				.ok_or(GltfLoadError::FormatError { msg: "Animation channel has no keyframe values" })? {
+				ReadOutputs::Translations(values) => ChannelValues::Translation(values.collect()),
+				ReadOutputs::Rotations(values) => ChannelValues::Rotation(values.into_f32().collect()),
+				ReadOutputs::Scales(values) => ChannelValues::Scale(values.collect()),
+				ReadOutputs::MorphTargetWeights(values) => ChannelValues::MorphWeights(values.into_f32().collect())
+			};
+			let interpolation = match channel.sampler().interpolation() {
+				::gltf::animation::Interpolation::Linear => Interpolation::Linear,
+				::gltf::animation::Interpolation::Step => Interpolation::Step,
+				::gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline
+			};
+			let keyframe_len = if interpolation == Interpolation::CubicSpline { times.len() * 3 } else { times.len() };
+			let values_len = match &values {
+				ChannelValues::Translation(values) | ChannelValues::Scale(values) => values.len(),
+				ChannelValues::Rotation(values) => values.len(),
+				ChannelValues::MorphWeights(_) => keyframe_len
+			};
+			if values_len != keyframe_len {
+				return Err(GltfLoadError::FormatError { msg: "Animation channel has a mismatched number of keyframes" });
+			}
+			Ok(GltfChannel {
+				node: channel.target().node().index(),
+				interpolation: interpolation,
+				times: times,
+				values: values
+			})
+		}).collect::<Result<Vec<GltfChannel>, GltfLoadError>>()?;
+		Ok(GltfAnimation {
+			name: animation.name().unwrap_or_default().to_string(),
+			duration: channels.iter().flat_map(|channel| channel.times.last()).fold(0., |a: f32, b| a.max(*b)),
+			channels: channels
+		})
+	}).collect::<Result<Vec<GltfAnimation>, GltfLoadError>>()?;
+
+	Ok(GltfModel {
+		meshes: meshes,
+		materials: materials,
+		nodes: nodes,
+		skins: skins,
+		vertex_skins: vertex_skins,
+		animations: animations
+	})
+}
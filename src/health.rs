@@ -0,0 +1,196 @@
+use serde::{Serialize, Deserialize};
+use crate::player::Player;
+use crate::math::{add_vector, vector_length};
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum DamageType {
+	Generic,
+	Weapon,
+	Melee,
+	Fall,
+	World
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum DamageSource {
+	World,
+	Player(u8),
+	Npc(u8)
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct DamageEvent {
+	pub source: DamageSource,
+	pub damage_type: DamageType,
+	pub amount: f32
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[serde(default)]
+pub struct HealthConfig {
+	pub max_health: f32,
+	pub max_armor: f32,
+	// fraction of incoming damage taken by armor while any is left
+	pub armor_absorption: f32,
+	pub respawn_time: f32,
+	// damage per unit of landing speed above PlayerConfig::fall_damage_threshold
+	pub fall_damage_scale: f32
+}
+
+impl Default for HealthConfig {
+	fn default() -> Self {
+		Self {
+			max_health: 100.,
+			max_armor: 100.,
+			armor_absorption: 0.66,
+			respawn_time: 3.,
+			fall_damage_scale: 40.
+		}
+	}
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DamageResult {
+	pub health_taken: f32,
+	pub armor_taken: f32,
+	pub killed: bool
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub enum HealthMessage {
+	Damage { event: DamageEvent, health: f32, armor: f32 },
+	Death { event: DamageEvent },
+	Respawn { position: [f32; 3], health: f32, armor: f32 },
+	Status { health: f32, armor: f32 }
+}
+
+pub struct Health {
+	pub config: HealthConfig,
+	pub health: f32,
+	pub armor: f32,
+	pub last_damage: Option<DamageEvent>,
+
+	respawn_time_left: Option<f32>,
+	// player state seen by the last fall_damage call
+	was_grounded: bool,
+	fall_speed: f32
+}
+
+impl Health {
+	pub fn new(config: HealthConfig) -> Self {
+		Self {
+			config: config,
+			health: config.max_health,
+			armor: 0.,
+			last_damage: None,
+			respawn_time_left: None,
+			was_grounded: false,
+			fall_speed: 0.
+		}
+	}
+
+	pub fn is_dead(&self) -> bool {
+		self.health <= 0.
+	}
+
+	pub fn respawn_time_left(&self) -> Option<f32> {
+		self.respawn_time_left
+	}
+
+	// Returns None if already dead, since the dead take no further damage.
+	pub fn apply_damage(&mut self, event: DamageEvent) -> Option<DamageResult> {
+		if self.is_dead() || event.amount <= 0. {
+			return None;
+		}
+		let armor_taken = (event.amount * self.config.armor_absorption).min(self.armor);
+		let health_taken = (event.amount - armor_taken).min(self.health);
+		self.armor -= armor_taken;
+		self.health -= health_taken;
+		self.last_damage = Some(event);
+
+		let killed = self.is_dead();
+		if killed {
+			self.respawn_time_left = Some(self.config.respawn_time);
+		}
+		Some(DamageResult {
+			health_taken: health_taken,
+			armor_taken: armor_taken,
+			killed: killed
+		})
+	}
+
+	pub fn heal(&mut self, amount: f32) {
+		if !self.is_dead() {
+			self.health = (self.health + amount).min(self.config.max_health);
+		}
+	}
+
+	pub fn add_armor(&mut self, amount: f32) {
+		self.armor = (self.armor + amount).min(self.config.max_armor);
+	}
+
+	// Advances the respawn timer, returning true once the entity should respawn.
+	pub fn update(&mut self, time_delta: f32) -> bool {
+		if let Some(time_left) = self.respawn_time_left {
+			let time_left = time_left - time_delta;
+			if time_left <= 0. {
+				self.respawn_time_left = None;
+				return true;
+			}
+			self.respawn_time_left = Some(time_left);
+		}
+		false
+	}
+
+	pub fn reset(&mut self) {
+		self.health = self.config.max_health;
+		self.armor = 0.;
+		self.last_damage = None;
+		self.respawn_time_left = None;
+		self.was_grounded = false;
+		self.fall_speed = 0.;
+	}
+
+	// Call after every player update. Checks whether the player landed in that update,
+	// falling faster than the threshold as of the end of the update before.
+	pub fn fall_damage(&mut self, player: &Player) -> Option<DamageEvent> {
+		let landed = player.is_grounded && !self.was_grounded;
+		let fall_speed = self.fall_speed;
+		self.was_grounded = player.is_grounded;
+		self.fall_speed = -player.velocity[1];
+		if !landed {
+			return None;
+		}
+		let excess = fall_speed - player.config.fall_damage_threshold;
+		if excess <= 0. {
+			return None;
+		}
+		Some(DamageEvent {
+			source: DamageSource::World,
+			damage_type: DamageType::Fall,
+			amount: excess * self.config.fall_damage_scale
+		})
+	}
+
+	pub fn damage_message(&self, event: DamageEvent) -> HealthMessage {
+		if self.is_dead() {
+			HealthMessage::Death { event: event }
+		} else {
+			HealthMessage::Damage { event: event, health: self.health, armor: self.armor }
+		}
+	}
+
+	pub fn status_message(&self) -> HealthMessage {
+		HealthMessage::Status { health: self.health, armor: self.armor }
+	}
+}
+
+// Picks the spawn point furthest away from the closest occupied position,
+// so that players do not respawn on top of each other.
+pub fn choose_spawn_point(spawn_points: &[[f32; 3]], occupied: &[[f32; 3]]) -> Option<[f32; 3]> {
+	let clearance = |point: &[f32; 3]| occupied.iter()
+		.map(|other| vector_length(&add_vector(point, other, -1.)))
+		.fold(f32::MAX, f32::min);
+	spawn_points.iter().copied()
+		.max_by(|a, b| clearance(a).total_cmp(&clearance(b)))
+}
@@ -5,8 +5,15 @@ pub mod cube;
 pub mod math;
 pub mod input;
 pub mod wavefront;
+pub mod gltf;
+pub mod mesh;
+pub mod baked;
+pub mod collider;
 pub mod quadoctree;
+pub mod spatial;
+pub mod bvh;
 pub mod collision;
+pub mod debug_draw;
 pub mod textures;
 pub mod skybox;
 pub mod animation;
@@ -18,11 +25,20 @@ pub mod message;
 pub mod server;
 pub mod client;
 pub mod peer_player;
+pub mod npc;
 pub mod container;
 pub mod assets;
+pub mod asset_manager;
 pub mod map;
+pub mod map_cache;
 pub mod interpolation;
 pub mod fps_count;
+pub mod trigger;
+pub mod weapon;
+pub mod health;
+pub mod ecs;
+pub mod script;
+pub mod navmesh;
 
 #[macro_use]
 pub extern crate glium;
@@ -1,46 +1,114 @@
-use std::io::{self, Read};
-use crate::wavefront::{WavefrontLoadError, load_obj};
-use std::fs::File;
-use crate::quadoctree::{QuadOctreeNode, BoundingBox};
+use std::io;
+use std::fs;
+use crate::wavefront::{WavefrontLoadError, LoadTargets, load_obj};
+use crate::gltf::{GltfLoadError, load_gltf};
+use crate::baked::{BakedModel, BakedModelError, BAKED_EXTENSION};
+use crate::quadoctree::BoundingBox;
+use crate::collider::CollisionFilter;
+use crate::spatial::{CollisionIndices, SpatialIndex, SpatialIndexType};
+use crate::bvh::DEFAULT_LEAF_SIZE;
 use crate::draw::{Light, ObjDef};
-use crate::assets::find_asset;
+use crate::mesh::{Mesh, upload_meshes};
+use crate::assets::{asset_exists, asset_path, read_asset, read_asset_to_string, open_asset};
+use crate::trigger::TriggerVolume;
+use crate::player::PlayerConfig;
+use crate::map_cache::{MapCache, cache_key};
+use crate::navmesh::{NavMesh, NavMeshConfig};
 use std::collections::{HashMap, BTreeMap};
+use std::path::PathBuf;
+use serde::Serialize;
 use glium::{Display, texture::Texture2d};
 use derive_more::{From, Error};
 
 const DEFAULT_TREE_STARTPOS: [f32; 3] = [-50.0f32; 3];
 const DEFAULT_TREE_ENDPOS: [f32; 3] = [50.0f32; 3];
 const DEFAULT_TREE_BUCKET_CAPACITY: usize = 300;
+const SPAWN_PREFIX: &str = "misc_spawn";
+// model file extensions, in order of preference, baked models first since they load fastest
+const MODEL_EXTENSIONS: [&str; 4] = [".cbm", ".obj", ".glb", ".gltf"];
 
 #[derive(From, Error, derive_more::Display, Debug)]
 pub enum GameMapError {
 	WavefrontLoadError(WavefrontLoadError),
+	GltfLoadError(GltfLoadError),
+	BakedModelError(BakedModelError),
 	IOError(io::Error),
-	BadTomlFile,
-	BadLightDesc
+	#[from(ignore)]
+	#[display(fmt = "{}: {}", "path.display()", source)]
+	BadTomlFile { path: PathBuf, source: toml::de::Error },
+	#[from(ignore)]
+	#[display(fmt = "bad description for light {}: {}", name, source)]
+	BadLightDesc { name: String, source: toml::de::Error },
+	#[from(ignore)]
+	#[display(fmt = "bad [player] config: {}", _0)]
+	BadPlayerConfig(toml::de::Error),
+	#[from(ignore)]
+	#[display(fmt = "bad [collision_filters] config: {}", _0)]
+	BadCollisionFilter(toml::de::Error),
+	#[from(ignore)]
+	#[display(fmt = "bad [navmesh] config: {}", _0)]
+	BadNavMeshConfig(toml::de::Error),
+	#[from(ignore)]
+	#[display(fmt = "bad [scripts] files list: {}", _0)]
+	BadScriptList(toml::de::Error),
+	SerializeError(bincode::Error)
+}
+
+#[derive(Serialize)]
+struct CollisionParams {
+	start_pos: [f32; 3],
+	end_pos: [f32; 3],
+	is_octree: bool,
+	bucket_capacity: usize,
+	index_type: SpatialIndexType,
+	leaf_size: usize,
+	#[serde(skip)]
+	cache: bool
+}
+
+impl CollisionParams {
+	fn build(&self) -> CollisionIndices {
+		match self.index_type {
+			SpatialIndexType::QuadOctree => CollisionIndices::new_quadoctree(BoundingBox { start_pos: self.start_pos, end_pos: self.end_pos },
+				self.is_octree, self.bucket_capacity),
+			SpatialIndexType::Bvh => CollisionIndices::new_bvh(self.leaf_size)
+		}
+	}
 }
 
 pub struct GameMap {
-	pub quadoctree: Option<QuadOctreeNode>,
+	pub collision: Option<CollisionIndices>,
 	pub lights: HashMap<String, Light>,
 	pub misc_objs: HashMap<String, [f32; 3]>,
+	pub triggers: HashMap<String, TriggerVolume>,
+	pub nav_triangles: Vec<[[f32; 3]; 3]>,
+	pub navmesh: Option<NavMesh>,
+	// script files listed under [scripts] in the map meta
+	pub scripts: Vec<String>,
+	pub meshes: BTreeMap<String, Mesh>,
+	// uploaded meshes, only loaded with a display
 	pub objects: BTreeMap<String, ObjDef>,
+	pub player_config: PlayerConfig,
 	pub meta: toml::Value
 }
 
 impl GameMap {
-	fn load_meta(path: &str, app_id: &str) -> Result<toml::Value, GameMapError> {
-		let mut file = File::open(find_asset(format!("{}{}", path, ".toml").as_str(), app_id))?;
-		let mut contents = String::new();
-		file.read_to_string(&mut contents)?;
-		Ok(contents.parse::<toml::Value>().map_err(|_| GameMapError::BadTomlFile)?)
+	// Baked models may carry the meta, which is used if there is no TOML file.
+	fn load_meta(path: &str, model_path: &str, app_id: &str) -> Result<toml::Value, GameMapError> {
+		let path = PathBuf::from(format!("{}{}", path, ".toml"));
+		let contents = match read_asset_to_string(&path, app_id) {
+			Ok(contents) => contents,
+			Err(e) if model_path.ends_with(".cbm") => BakedModel::read_meta(&mut open_asset(model_path, app_id)?)?.ok_or(e)?,
+			Err(e) => return Err(e.into())
+		};
+		contents.parse::<toml::Value>().map_err(|e| GameMapError::BadTomlFile { path: path, source: e })
 	}
 
 	fn parse_lights(&mut self) -> Result<(), GameMapError> {
 		if let Some(lights) = self.meta.get("lights") {
 			if let Some(lights_table) = lights.as_table() {
 				for (name, light_info) in lights_table.iter() {
-					let mut light_meta: Light = light_info.clone().try_into().map_err(|_| GameMapError::BadLightDesc)?;
+					let mut light_meta: Light = light_info.clone().try_into().map_err(|e| GameMapError::BadLightDesc { name: name.clone(), source: e })?;
 					if let Some(map_light) = self.lights.get(name) {
 						light_meta.position = map_light.position;
 						self.lights.insert(name.clone(), light_meta);
@@ -52,63 +120,258 @@ impl GameMap {
 		Ok(())
 	}
 
-	fn parse_quadoctree(meta: &toml::Value) -> QuadOctreeNode {
-		let mut start_pos = DEFAULT_TREE_STARTPOS;
-		let mut end_pos = DEFAULT_TREE_ENDPOS;
-		let mut is_octree = false;
-		let mut bucket_capacity = DEFAULT_TREE_BUCKET_CAPACITY;
+	fn parse_player_config(meta: &toml::Value) -> Result<PlayerConfig, GameMapError> {
+		match meta.get("player") {
+			Some(player) => Ok(player.clone().try_into::<PlayerConfig>().map_err(GameMapError::BadPlayerConfig)?.clamped()),
+			None => Ok(Default::default())
+		}
+	}
+
+	fn parse_navmesh_config(meta: &toml::Value) -> Result<Option<NavMeshConfig>, GameMapError> {
+		match meta.get("navmesh") {
+			Some(navmesh) => Ok(Some(navmesh.clone().try_into().map_err(GameMapError::BadNavMeshConfig)?)),
+			None => Ok(None)
+		}
+	}
+
+	fn parse_scripts(meta: &toml::Value) -> Result<Vec<String>, GameMapError> {
+		match meta.get("scripts").and_then(|scripts| scripts.get("files")) {
+			Some(files) => Ok(files.clone().try_into().map_err(GameMapError::BadScriptList)?),
+			None => Ok(Vec::new())
+		}
+	}
+
+	fn parse_collision_filters(meta: &toml::Value) -> Result<HashMap<String, CollisionFilter>, GameMapError> {
+		match meta.get("collision_filters") {
+			Some(filters) => Ok(filters.clone().try_into().map_err(GameMapError::BadCollisionFilter)?),
+			None => Ok(HashMap::new())
+		}
+	}
+
+	fn parse_collision_params(meta: &toml::Value) -> CollisionParams {
+		let mut params = CollisionParams {
+			start_pos: DEFAULT_TREE_STARTPOS,
+			end_pos: DEFAULT_TREE_ENDPOS,
+			is_octree: false,
+			bucket_capacity: DEFAULT_TREE_BUCKET_CAPACITY,
+			index_type: SpatialIndexType::default(),
+			leaf_size: DEFAULT_LEAF_SIZE,
+			cache: false
+		};
 		if let Some(quadoctree_params) = meta.get("quadoctree") {
 			if let Some(start_pos_p) = quadoctree_params.get("start_pos") {
 				if let Ok(start_pos_p) = start_pos_p.clone().try_into::<[f32; 3]>() {
-					start_pos = start_pos_p;
+					params.start_pos = start_pos_p;
 				}
 			}
 			if let Some(end_pos_p) = quadoctree_params.get("end_pos") {
 				if let Ok(end_pos_p) = end_pos_p.clone().try_into::<[f32; 3]>() {
-					end_pos = end_pos_p;
+					params.end_pos = end_pos_p;
 				}
 			}
 			if let Some(is_octree_p) = quadoctree_params.get("is_octree") {
 				if let Some(is_octree_p) = is_octree_p.as_bool() {
-					is_octree = is_octree_p;
+					params.is_octree = is_octree_p;
 				}
 			}
 			if let Some(bucket_capacity_p) = quadoctree_params.get("bucket_capacity") {
 				if let Some(bucket_capacity_p) = bucket_capacity_p.as_integer() {
-					bucket_capacity = bucket_capacity_p as usize;
+					params.bucket_capacity = bucket_capacity_p as usize;
+				}
+			}
+			if let Some(index_type_p) = quadoctree_params.get("index_type") {
+				if let Ok(index_type_p) = index_type_p.clone().try_into::<SpatialIndexType>() {
+					params.index_type = index_type_p;
+				}
+			}
+			if let Some(leaf_size_p) = quadoctree_params.get("leaf_size") {
+				if let Some(leaf_size_p) = leaf_size_p.as_integer() {
+					params.leaf_size = leaf_size_p as usize;
+				}
+			}
+			if let Some(cache_p) = quadoctree_params.get("cache") {
+				if let Some(cache_p) = cache_p.as_bool() {
+					params.cache = cache_p;
 				}
 			}
 		}
-		
-		QuadOctreeNode::new_tree(BoundingBox { start_pos: start_pos, end_pos: end_pos }, is_octree, bucket_capacity)
+		params
+	}
+
+	// Picks the first model file that exists next to the map meta file.
+	fn model_path(path: &str, app_id: &str) -> String {
+		MODEL_EXTENSIONS.iter()
+			.map(|extension| format!("{}{}", path, extension))
+			.filter(|model_path| asset_exists(model_path, app_id))
+			.find(|model_path| !model_path.ends_with(BAKED_EXTENSION) || !Self::is_baked_model_stale(path, app_id))
+			.unwrap_or_else(|| format!("{}{}", path, ".obj"))
+	}
+
+	// A baked model older than the OBJ next to it was not converted again after the OBJ changed.
+	// Models outside directory mounts have no modification times, so they are never stale.
+	fn is_baked_model_stale(path: &str, app_id: &str) -> bool {
+		let modified = |extension: &str| asset_path(format!("{}{}", path, extension), app_id)
+			.and_then(|real_path| fs::metadata(real_path).ok())
+			.and_then(|metadata| metadata.modified().ok());
+		match (modified(".cbm"), modified(".obj")) {
+			(Some(baked), Some(obj)) if obj > baked => {
+				eprintln!("warning: {}.cbm is older than {}.obj, loading the OBJ instead until it is converted again", path, path);
+				true
+			},
+			_ => false
+		}
+	}
+
+	fn load_model(model_path: &str, app_id: &str, display: Option<&Display>, textures: Option<&mut HashMap<String, Texture2d>>,
+		targets: LoadTargets) -> Result<BTreeMap<String, Mesh>, GameMapError> {
+		if model_path.ends_with(".obj") || model_path.ends_with(".cbm") {
+			Ok(load_obj(model_path, app_id, display, textures, &[1., 1., 1.], targets)?)
+		} else {
+			Ok(load_gltf(model_path, app_id, display, textures, &[1., 1., 1.], targets)?.meshes)
+		}
+	}
+
+	// Returns the cache file path and the key that a valid cache must match.
+	// Models in archive or memory mounts have nowhere to keep a cache next to them, so they get None.
+	fn cache_location(obj_path: &str, app_id: &str, params: &CollisionParams,
+		collision_filters: &HashMap<String, CollisionFilter>) -> Result<Option<(PathBuf, u64)>, GameMapError> {
+		let real_path = match asset_path(obj_path, app_id) {
+			Some(real_path) => real_path,
+			None => return Ok(None)
+		};
+		let obj_data = read_asset(obj_path, app_id)?;
+		let filters: BTreeMap<&String, &CollisionFilter> = collision_filters.iter().collect();
+		let params_data = bincode::serialize(&(params, filters))?;
+		Ok(Some((MapCache::path_for(&real_path), cache_key(&obj_data, &params_data))))
 	}
 
 	pub fn load_map(path: &str, app_id: &str, display: Option<&Display>, textures: Option<&mut HashMap<String, Texture2d>>,
-		create_quadoctree: bool) -> Result<GameMap, GameMapError> {
+		create_collision: bool) -> Result<GameMap, GameMapError> {
 		let mut lights: HashMap<String, Light> = HashMap::new();
 		let mut misc_objs: HashMap<String, [f32; 3]> = HashMap::new();
+		let mut triggers: HashMap<String, TriggerVolume> = HashMap::new();
+		let mut nav_triangles: Vec<[[f32; 3]; 3]> = Vec::new();
+
+		let obj_path = Self::model_path(path, app_id);
+		let meta = Self::load_meta(path, obj_path.as_str(), app_id)?;
 
-		let meta = Self::load_meta(path, app_id)?;
+		let player_config = Self::parse_player_config(&meta)?;
+		let collision_filters = Self::parse_collision_filters(&meta)?;
 
-		let mut quadoctree = if create_quadoctree {
-			Some(Self::parse_quadoctree(&meta))
+		let params = if create_collision {
+			Some(Self::parse_collision_params(&meta))
 		} else {
 			None
 		};
+		let cache_location = match params.as_ref() {
+			Some(params) if params.cache => Self::cache_location(obj_path.as_str(), app_id, params, &collision_filters)?,
+			_ => None
+		};
+		// an unreadable or outdated cache is simply rebuilt
+		let cache = cache_location.as_ref().and_then(|(cache_path, key)| MapCache::load(cache_path, *key).ok().flatten());
 
-		let objects = load_obj(format!("{}{}", path, ".obj").as_str(), app_id, display, textures, &[1., 1., 1.],
-			quadoctree.as_mut(), Some(&mut lights), Some(&mut misc_objs))?;
+		let mut meshes;
+		let collision = match cache {
+			Some(cache) if display.is_none() => {
+				lights = cache.lights;
+				misc_objs = cache.misc_objs;
+				triggers = cache.triggers;
+				nav_triangles = cache.nav_triangles;
+				meshes = cache.meshes;
+				Some(cache.collision)
+			},
+			Some(cache) => {
+				// meshes still need to be loaded for rendering, but the collision trees can be reused
+				meshes = Self::load_model(obj_path.as_str(), app_id, display, textures, LoadTargets {
+					lights: Some(&mut lights),
+					misc_objs: Some(&mut misc_objs),
+					triggers: Some(&mut triggers),
+					nav_triangles: Some(&mut nav_triangles),
+					..Default::default()
+				})?;
+				Some(cache.collision)
+			},
+			None => {
+				let mut collision = params.as_ref().map(|params| params.build());
+				meshes = Self::load_model(obj_path.as_str(), app_id, display, textures, LoadTargets {
+					collision_index: collision.as_mut().map(|c| c as &mut dyn SpatialIndex),
+					collision_filters: Some(&collision_filters),
+					lights: Some(&mut lights),
+					misc_objs: Some(&mut misc_objs),
+					triggers: Some(&mut triggers),
+					nav_triangles: Some(&mut nav_triangles)
+				})?;
+
+				match (collision, cache_location) {
+					(Some(mut collision), Some((cache_path, key))) => {
+						collision.optimize();
+						let cache = MapCache {
+							collision: collision,
+							lights: lights,
+							misc_objs: misc_objs,
+							triggers: triggers,
+							nav_triangles: nav_triangles,
+							meshes: meshes
+						};
+						// failing to write the cache (e.g. read-only asset directories) is not fatal
+						let _ = cache.save(&cache_path, key);
+						lights = cache.lights;
+						misc_objs = cache.misc_objs;
+						triggers = cache.triggers;
+						nav_triangles = cache.nav_triangles;
+						meshes = cache.meshes;
+						Some(cache.collision)
+					},
+					(mut collision, _) => {
+						if let Some(collision) = collision.as_mut() {
+							collision.optimize();
+						}
+						collision
+					}
+				}
+			}
+		};
+
+		// only maps with a [navmesh] section get one, since building it is not free
+		let navmesh = match Self::parse_navmesh_config(&meta)? {
+			Some(config) if create_collision => Some(NavMesh::build(collision.as_ref().map(|c| c as &dyn SpatialIndex), &nav_triangles, config)),
+			_ => None
+		};
+
+		let scripts = Self::parse_scripts(&meta)?;
+		let objects = match display {
+			Some(display) => upload_meshes(display, &meshes, true),
+			None => BTreeMap::new()
+		};
 
 		let mut result = Self {
 			lights: lights,
-			quadoctree: quadoctree,
+			collision: collision,
+			meshes: meshes,
 			objects: objects,
+			player_config: player_config,
 			meta: meta,
-			misc_objs: misc_objs
+			misc_objs: misc_objs,
+			triggers: triggers,
+			nav_triangles: nav_triangles,
+			navmesh: navmesh,
+			scripts: scripts
 		};
 
 		result.parse_lights()?;
 
 		Ok(result)
 	}
+
+	pub fn collision_index(&self) -> Option<&dyn SpatialIndex> {
+		self.collision.as_ref().map(|c| c as &dyn SpatialIndex)
+	}
+
+	// Positions of the misc_spawn* objects, ordered by name.
+	pub fn spawn_points(&self) -> Vec<[f32; 3]> {
+		let mut spawns: Vec<(&String, &[f32; 3])> = self.misc_objs.iter()
+			.filter(|(name, _)| name.starts_with(SPAWN_PREFIX)).collect();
+		spawns.sort_by(|a, b| a.0.cmp(b.0));
+		spawns.into_iter().map(|(_, position)| *position).collect()
+	}
 }
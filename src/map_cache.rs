@@ -0,0 +1,81 @@
+use std::io::{self, BufReader, BufWriter};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, BTreeMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde::{Serialize, Deserialize};
+use derive_more::{Display, Error, From};
+use crate::spatial::CollisionIndices;
+use crate::draw::Light;
+use crate::mesh::Mesh;
+use crate::trigger::TriggerVolume;
+
+const CACHE_VERSION: u32 = 4;
+const CACHE_EXTENSION: &str = "collision_cache";
+
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Display, Error, From)]
+pub enum MapCacheError {
+	IOError(io::Error),
+	SerializeError(bincode::Error)
+}
+
+// Everything load_map extracts from the OBJ when no display is present,
+// so that servers can skip parsing the OBJ entirely.
+#[derive(Serialize, Deserialize)]
+pub struct MapCache {
+	pub collision: CollisionIndices,
+	pub lights: HashMap<String, Light>,
+	pub misc_objs: HashMap<String, [f32; 3]>,
+	pub triggers: HashMap<String, TriggerVolume>,
+	pub nav_triangles: Vec<[[f32; 3]; 3]>,
+	pub meshes: BTreeMap<String, Mesh>
+}
+
+impl MapCache {
+	pub fn path_for(obj_path: &Path) -> PathBuf {
+		obj_path.with_extension(CACHE_EXTENSION)
+	}
+
+	// Returns None if the cache was built from a different OBJ file, with different
+	// parameters or by an incompatible version.
+	pub fn load(path: &Path, key: u64) -> Result<Option<MapCache>, MapCacheError> {
+		let mut reader = BufReader::new(File::open(path)?);
+		let (version, cache_key): (u32, u64) = bincode::deserialize_from(&mut reader)?;
+		if version != CACHE_VERSION || cache_key != key {
+			return Ok(None);
+		}
+		Ok(Some(bincode::deserialize_from(&mut reader)?))
+	}
+
+	pub fn save(&self, path: &Path, key: u64) -> Result<(), MapCacheError> {
+		// write to a temporary file first so that a concurrent load never sees a partial cache.
+		// The name is unique to this writer, so processes and threads saving at once do not clobber each other.
+		let temp_id = NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed);
+		let temp_path = path.with_extension(format!("{}.{}.{}.tmp", CACHE_EXTENSION, std::process::id(), temp_id));
+		let result = self.write_to(&temp_path, key).and_then(|_| Ok(fs::rename(&temp_path, path)?));
+		if result.is_err() {
+			let _ = fs::remove_file(&temp_path);
+		}
+		result
+	}
+
+	fn write_to(&self, path: &Path, key: u64) -> Result<(), MapCacheError> {
+		let mut writer = BufWriter::new(File::create(path)?);
+		bincode::serialize_into(&mut writer, &(CACHE_VERSION, key))?;
+		bincode::serialize_into(&mut writer, self)?;
+		Ok(())
+	}
+}
+
+// FNV-1a over the OBJ contents and the serialized build parameters. DefaultHasher
+// is not used since its output may change between Rust releases.
+pub fn cache_key(obj_data: &[u8], params: &[u8]) -> u64 {
+	let mut hash = 0xcbf29ce484222325u64;
+	for byte in obj_data.iter().chain(params.iter()) {
+		hash ^= *byte as u64;
+		hash = hash.wrapping_mul(0x100000001b3);
+	}
+	hash
+}
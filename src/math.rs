@@ -60,3 +60,40 @@ pub fn mult_vector(input: &[f32; 3], factor: f32) -> [f32; 3] {
 		input[2] * factor
 	]
 }
+
+pub fn transform_point(mat: &[[f32; 4]; 4], point: &[f32; 3]) -> [f32; 3] {
+	let mut result = [mat[3][0], mat[3][1], mat[3][2]];
+	for r in 0..3 {
+		for c in 0..3 {
+			result[r] += mat[c][r] * point[c];
+		}
+	}
+	result
+}
+
+pub fn invert_matrix3(m: &[[f32; 3]; 3]) -> Option<[[f32; 3]; 3]> {
+	let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+		- m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+		+ m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+	if det.abs() < f32::EPSILON {
+		return None;
+	}
+	let inv_det = 1. / det;
+	Some([
+		[
+			(m[1][1] * m[2][2] - m[1][2] * m[2][1]) * inv_det,
+			(m[0][2] * m[2][1] - m[0][1] * m[2][2]) * inv_det,
+			(m[0][1] * m[1][2] - m[0][2] * m[1][1]) * inv_det
+		],
+		[
+			(m[1][2] * m[2][0] - m[1][0] * m[2][2]) * inv_det,
+			(m[0][0] * m[2][2] - m[0][2] * m[2][0]) * inv_det,
+			(m[0][2] * m[1][0] - m[0][0] * m[1][2]) * inv_det
+		],
+		[
+			(m[1][0] * m[2][1] - m[1][1] * m[2][0]) * inv_det,
+			(m[0][1] * m[2][0] - m[0][0] * m[2][1]) * inv_det,
+			(m[0][0] * m[1][1] - m[0][1] * m[1][0]) * inv_det
+		]
+	])
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use glium::Display;
+use serde::{Serialize, Deserialize};
+use crate::draw::{ObjDef, SubMesh, Vertex, load_data_to_gpu, load_shared_data_to_gpu};
+use crate::quadoctree::BoundingBox;
+
+// Geometry of an object as returned by the model loaders. It stays on the CPU, so it can be
+// inspected and processed without a display; upload creates an ObjDef for drawing it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mesh {
+	pub vertices: Vec<Vertex>,
+	pub indices: Vec<u32>,
+	// index ranges drawn with each material, covering all indices
+	pub submeshes: Vec<SubMesh>,
+	pub bounds: BoundingBox
+}
+
+impl Mesh {
+	pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, submeshes: Vec<SubMesh>) -> Self {
+		let bounds = Self::compute_bounds(&vertices);
+		Self {
+			vertices: vertices,
+			indices: indices,
+			submeshes: submeshes,
+			bounds: bounds
+		}
+	}
+
+	// An empty box at the origin if there are no vertices.
+	pub fn compute_bounds(vertices: &[Vertex]) -> BoundingBox {
+		let first = vertices.first().map(|vertex| vertex.position).unwrap_or([0., 0., 0.]);
+		let mut bounds = BoundingBox { start_pos: first, end_pos: first };
+		for vertex in vertices.iter() {
+			for i in 0..3 {
+				bounds.start_pos[i] = bounds.start_pos[i].min(vertex.position[i]);
+				bounds.end_pos[i] = bounds.end_pos[i].max(vertex.position[i]);
+			}
+		}
+		bounds
+	}
+
+	// Has to be called after changing vertex positions.
+	pub fn update_bounds(&mut self) {
+		self.bounds = Self::compute_bounds(&self.vertices);
+	}
+
+	pub fn triangle_count(&self) -> usize {
+		self.indices.len() / 3
+	}
+
+	pub fn triangles(&self) -> impl Iterator<Item = [[f32; 3]; 3]> + '_ {
+		self.indices.chunks_exact(3).map(move |tri| [
+			self.vertices[tri[0] as usize].position,
+			self.vertices[tri[1] as usize].position,
+			self.vertices[tri[2] as usize].position
+		])
+	}
+
+	pub fn upload(&self, display: &Display) -> ObjDef {
+		let mut def = load_data_to_gpu(display, &self.vertices, &self.indices);
+		def.material = self.submeshes.first().map(|submesh| submesh.material.clone());
+		def.submeshes = self.submeshes.clone();
+		def
+	}
+}
+
+// Meshes of a model packed into one vertex and index buffer.
+pub struct PackedMeshes {
+	pub vertices: Vec<Vertex>,
+	pub indices: Vec<u32>,
+	// name, index range and submeshes of each mesh, offset into the shared buffers
+	pub objects: Vec<(String, Range<usize>, Vec<SubMesh>)>
+}
+
+pub fn pack_meshes(meshes: &BTreeMap<String, Mesh>) -> PackedMeshes {
+	let mut result = PackedMeshes {
+		vertices: Vec::new(),
+		indices: Vec::new(),
+		objects: Vec::new()
+	};
+	for (name, mesh) in meshes.iter() {
+		let base = result.vertices.len() as u32;
+		let start = result.indices.len();
+		result.vertices.extend_from_slice(&mesh.vertices);
+		result.indices.extend(mesh.indices.iter().map(|i| i + base));
+		let submeshes = mesh.submeshes.iter().map(|submesh| SubMesh {
+			index_range: (submesh.index_range.start + start)..(submesh.index_range.end + start),
+			material: submesh.material.clone()
+		}).collect();
+		result.objects.push((name.clone(), start..result.indices.len(), submeshes));
+	}
+	result
+}
+
+// Uploads the meshes of a model. With shared_buffers, all meshes go into one vertex and index buffer.
+pub fn upload_meshes(display: &Display, meshes: &BTreeMap<String, Mesh>, shared_buffers: bool) -> BTreeMap<String, ObjDef> {
+	if !shared_buffers || meshes.is_empty() {
+		return meshes.iter().map(|(name, mesh)| (name.clone(), mesh.upload(display))).collect();
+	}
+
+	let packed = pack_meshes(meshes);
+	let ranges: Vec<Range<usize>> = packed.objects.iter().map(|(_, range, _)| range.clone()).collect();
+	let defs = load_shared_data_to_gpu(display, &packed.vertices, &packed.indices, &ranges);
+	packed.objects.into_iter().zip(defs).map(|((name, _, submeshes), mut def)| {
+		def.submeshes = submeshes;
+		def.material = def.submeshes.first().map(|submesh| submesh.material.clone());
+		(name, def)
+	}).collect()
+}
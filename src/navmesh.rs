@@ -0,0 +1,340 @@
+use std::collections::{HashMap, BinaryHeap};
+use std::cmp::Ordering;
+use serde::{Serialize, Deserialize};
+use crate::quadoctree::CollisionObj;
+use crate::collider::{CollisionFilter, LAYER_NPC, LAYER_ALL};
+use crate::spatial::SpatialIndex;
+use crate::collision::{check_player_collision, triangle_normal};
+use crate::cube::generate_cube_collideobj;
+use crate::math::{add_vector, mult_vector, vector_length};
+
+// vertices closer than this are treated as the same when connecting polygons
+const WELD_PRECISION: f32 = 1000.;
+// size of the xz grid cells used to look up the polygon under a point
+const GRID_CELL_SIZE: f32 = 1.;
+
+type WeldKey = (i32, i32, i32);
+type GridCell = (i32, i32);
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[serde(default)]
+pub struct NavMeshConfig {
+	pub max_slope_angle: f32,
+	pub agent_radius: f32,
+	pub agent_height: f32,
+	pub step_height: f32
+}
+
+impl Default for NavMeshConfig {
+	fn default() -> Self {
+		Self {
+			max_slope_angle: 0.785,
+			agent_radius: 0.2,
+			agent_height: 0.55,
+			step_height: 0.12
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct NavPolygon {
+	pub vertices: [[f32; 3]; 3],
+	pub center: [f32; 3],
+	// neighbouring polygon index and the shared edge
+	pub neighbours: Vec<(usize, [[f32; 3]; 2])>
+}
+
+#[derive(Debug, Clone)]
+pub struct NavMesh {
+	pub polygons: Vec<NavPolygon>,
+	pub config: NavMeshConfig,
+	// polygons overlapping each grid cell in the xz plane
+	grid: HashMap<GridCell, Vec<usize>>
+}
+
+#[derive(PartialEq)]
+struct OpenNode {
+	cost: f32,
+	polygon: usize
+}
+
+impl Eq for OpenNode {}
+
+impl Ord for OpenNode {
+	fn cmp(&self, other: &Self) -> Ordering {
+		// reversed so that BinaryHeap pops the cheapest node first
+		other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal)
+	}
+}
+
+impl PartialOrd for OpenNode {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+fn weld_key(point: &[f32; 3]) -> WeldKey {
+	(
+		(point[0] * WELD_PRECISION).round() as i32,
+		(point[1] * WELD_PRECISION).round() as i32,
+		(point[2] * WELD_PRECISION).round() as i32
+	)
+}
+
+// Twice the signed area of the triangle in the xz plane.
+fn triarea2(a: &[f32; 3], b: &[f32; 3], c: &[f32; 3]) -> f32 {
+	let (ax, az) = (b[0] - a[0], b[2] - a[2]);
+	let (bx, bz) = (c[0] - a[0], c[2] - a[2]);
+	bx * az - ax * bz
+}
+
+fn same_xz(a: &[f32; 3], b: &[f32; 3]) -> bool {
+	(a[0] - b[0]).abs() < 1. / WELD_PRECISION && (a[2] - b[2]).abs() < 1. / WELD_PRECISION
+}
+
+// Returns the height of the triangle at the xz position of the point, if the point lies within it.
+fn height_at(triangle: &[[f32; 3]; 3], point: &[f32; 3]) -> Option<f32> {
+	let area = triarea2(&triangle[0], &triangle[1], &triangle[2]);
+	if area.abs() < f32::EPSILON {
+		return None;
+	}
+	let w0 = triarea2(&triangle[1], &triangle[2], point) / area;
+	let w1 = triarea2(&triangle[2], &triangle[0], point) / area;
+	let w2 = 1. - w0 - w1;
+	if w0 < -0.0001 || w1 < -0.0001 || w2 < -0.0001 {
+		return None;
+	}
+	Some(w0 * triangle[0][1] + w1 * triangle[1][1] + w2 * triangle[2][1])
+}
+
+fn grid_cell(point: &[f32; 3]) -> GridCell {
+	((point[0] / GRID_CELL_SIZE).floor() as i32, (point[2] / GRID_CELL_SIZE).floor() as i32)
+}
+
+fn distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+	vector_length(&add_vector(a, b, -1.))
+}
+
+impl NavMesh {
+	// Builds the navmesh from walkable surfaces in the collision index: terrain triangles and
+	// the upward facing parts of collision polygons. Hand-authored triangles are always walkable.
+	// Surfaces without room for the agent at any corner are left out, which erodes the mesh
+	// away from walls by at least the agent radius.
+	pub fn build(index: Option<&dyn SpatialIndex>, authored: &[[[f32; 3]; 3]], config: NavMeshConfig) -> Self {
+		let min_normal_y = config.max_slope_angle.cos();
+		let mut triangles: Vec<[[f32; 3]; 3]> = authored.to_vec();
+
+		if let Some(index) = index {
+			let mut candidates = Vec::new();
+			index.visit_items(&mut |obj, _| match obj.world().as_ref() {
+				CollisionObj::Triangle(triangle) => {
+					if triangle_normal(triangle)[1] >= min_normal_y {
+						candidates.push(*triangle);
+					}
+				},
+				CollisionObj::Polygon(vertices, ..) => {
+					for chunk in vertices.chunks(3).filter(|c| c.len() == 3) {
+						if chunk[0].normal[1] >= min_normal_y {
+							candidates.push([chunk[0].position, chunk[1].position, chunk[2].position]);
+						}
+					}
+				},
+				CollisionObj::Instance(..) => unreachable!()
+			});
+			triangles.extend(candidates.into_iter().filter(|triangle| Self::has_clearance(index, triangle, &config)));
+		}
+
+		Self::from_triangles(triangles, config)
+	}
+
+	fn has_clearance(index: &dyn SpatialIndex, triangle: &[[f32; 3]; 3], config: &NavMeshConfig) -> bool {
+		let center = mult_vector(&add_vector(&add_vector(&triangle[0], &triangle[1], 1.), &triangle[2], 1.), 1. / 3.);
+		let half_height = (config.agent_height - config.step_height).max(0.01) / 2.;
+		let filter = CollisionFilter::new(LAYER_NPC, LAYER_ALL);
+		triangle.iter().chain(std::iter::once(&center)).all(|point| {
+			let box_center = [point[0], point[1] + config.step_height + half_height, point[2]];
+			let agent_box = generate_cube_collideobj(&[0., 0., 0.], &box_center,
+				&[config.agent_radius, half_height, config.agent_radius], 0.);
+			let result = check_player_collision(index, &box_center, &agent_box, &filter);
+			result.polygons.iter().all(|p| *p == [0., 0., 0.])
+		})
+	}
+
+	pub fn from_triangles(triangles: Vec<[[f32; 3]; 3]>, config: NavMeshConfig) -> Self {
+		let mut polygons: Vec<NavPolygon> = triangles.iter().map(|triangle| NavPolygon {
+			vertices: *triangle,
+			center: mult_vector(&add_vector(&add_vector(&triangle[0], &triangle[1], 1.), &triangle[2], 1.), 1. / 3.),
+			neighbours: Vec::new()
+		}).collect();
+
+		let mut edges: HashMap<(WeldKey, WeldKey), Vec<usize>> = HashMap::new();
+		for (i, polygon) in polygons.iter().enumerate() {
+			for e in 0..3 {
+				let (a, b) = (weld_key(&polygon.vertices[e]), weld_key(&polygon.vertices[(e + 1) % 3]));
+				edges.entry(if a < b { (a, b) } else { (b, a) }).or_default().push(i);
+			}
+		}
+
+		for shared in edges.values() {
+			for &a in shared {
+				for &b in shared {
+					if a == b { continue; }
+					let edge = Self::shared_edge(&polygons[a], &polygons[b]);
+					if let Some(edge) = edge {
+						polygons[a].neighbours.push((b, edge));
+					}
+				}
+			}
+		}
+
+		let mut grid: HashMap<GridCell, Vec<usize>> = HashMap::new();
+		for (i, polygon) in polygons.iter().enumerate() {
+			let cells: Vec<GridCell> = polygon.vertices.iter().map(grid_cell).collect();
+			let (min_x, max_x) = (cells.iter().map(|c| c.0).min().unwrap(), cells.iter().map(|c| c.0).max().unwrap());
+			let (min_z, max_z) = (cells.iter().map(|c| c.1).min().unwrap(), cells.iter().map(|c| c.1).max().unwrap());
+			for x in min_x..=max_x {
+				for z in min_z..=max_z {
+					grid.entry((x, z)).or_default().push(i);
+				}
+			}
+		}
+
+		Self {
+			polygons: polygons,
+			config: config,
+			grid: grid
+		}
+	}
+
+	fn shared_edge(a: &NavPolygon, b: &NavPolygon) -> Option<[[f32; 3]; 2]> {
+		let shared: Vec<[f32; 3]> = a.vertices.iter()
+			.filter(|v| b.vertices.iter().any(|w| weld_key(v) == weld_key(w)))
+			.copied().collect();
+		if shared.len() == 2 { Some([shared[0], shared[1]]) } else { None }
+	}
+
+	// Finds the polygon under the point, preferring the surface closest below it.
+	pub fn find_polygon(&self, point: &[f32; 3]) -> Option<usize> {
+		let mut best: Option<(usize, f32)> = None;
+		for &i in self.grid.get(&grid_cell(point)).map(|cell| cell.as_slice()).unwrap_or(&[]) {
+			if let Some(height) = height_at(&self.polygons[i].vertices, point) {
+				let diff = point[1] - height;
+				if diff < -self.config.step_height || diff > self.config.agent_height * 2. {
+					continue;
+				}
+				if best.map(|b| diff.abs() < b.1).unwrap_or(true) {
+					best = Some((i, diff.abs()));
+				}
+			}
+		}
+		best.map(|b| b.0)
+	}
+
+	pub fn find_path(&self, start: &[f32; 3], end: &[f32; 3]) -> Option<Vec<[f32; 3]>> {
+		let start_polygon = self.find_polygon(start)?;
+		let end_polygon = self.find_polygon(end)?;
+		let corridor = self.find_corridor(start_polygon, end_polygon, end)?;
+		Some(self.string_pull(start, end, &corridor))
+	}
+
+	// A* over polygon centers, returning the polygons leading from start to end.
+	pub fn find_corridor(&self, start: usize, end: usize, end_point: &[f32; 3]) -> Option<Vec<usize>> {
+		let mut open = BinaryHeap::new();
+		let mut came_from: HashMap<usize, usize> = HashMap::new();
+		let mut costs: HashMap<usize, f32> = HashMap::new();
+
+		costs.insert(start, 0.);
+		open.push(OpenNode { cost: distance(&self.polygons[start].center, end_point), polygon: start });
+
+		while let Some(OpenNode { polygon, .. }) = open.pop() {
+			if polygon == end {
+				let mut result = vec![end];
+				let mut current = end;
+				while let Some(previous) = came_from.get(&current) {
+					result.push(*previous);
+					current = *previous;
+				}
+				result.reverse();
+				return Some(result);
+			}
+
+			let current_cost = costs[&polygon];
+			for (neighbour, _) in &self.polygons[polygon].neighbours {
+				let cost = current_cost + distance(&self.polygons[polygon].center, &self.polygons[*neighbour].center);
+				if costs.get(neighbour).map(|c| cost < *c).unwrap_or(true) {
+					costs.insert(*neighbour, cost);
+					came_from.insert(*neighbour, polygon);
+					open.push(OpenNode { cost: cost + distance(&self.polygons[*neighbour].center, end_point), polygon: *neighbour });
+				}
+			}
+		}
+		None
+	}
+
+	// Simple stupid funnel algorithm, straightening the path through the corridor portals.
+	fn string_pull(&self, start: &[f32; 3], end: &[f32; 3], corridor: &[usize]) -> Vec<[f32; 3]> {
+		let mut portals: Vec<([f32; 3], [f32; 3])> = vec![(*start, *start)];
+		for pair in corridor.windows(2) {
+			let from = &self.polygons[pair[0]];
+			let edge = from.neighbours.iter().find(|(n, _)| *n == pair[1]).unwrap().1;
+			// order the edge so that left and right are as seen when walking into the next polygon
+			if triarea2(&from.center, &edge[0], &edge[1]) > 0. {
+				portals.push((edge[0], edge[1]));
+			} else {
+				portals.push((edge[1], edge[0]));
+			}
+		}
+		portals.push((*end, *end));
+
+		let mut result = vec![*start];
+		let (mut apex, mut left, mut right) = (*start, *start, *start);
+		let (mut left_index, mut right_index) = (0, 0);
+		let mut i = 1;
+		while i < portals.len() {
+			let (portal_left, portal_right) = portals[i];
+
+			if triarea2(&apex, &right, &portal_right) <= 0. {
+				if same_xz(&apex, &right) || triarea2(&apex, &left, &portal_right) > 0. {
+					right = portal_right;
+					right_index = i;
+				} else {
+					// right crossed over left, so left becomes a corner of the path
+					let apex_index = left_index;
+					apex = left;
+					if result.last().map(|last| !same_xz(last, &apex)).unwrap_or(true) {
+						result.push(apex);
+					}
+					left = apex;
+					right = apex;
+					right_index = apex_index;
+					i = apex_index + 1;
+					continue;
+				}
+			}
+
+			if triarea2(&apex, &left, &portal_left) >= 0. {
+				if same_xz(&apex, &left) || triarea2(&apex, &right, &portal_left) < 0. {
+					left = portal_left;
+					left_index = i;
+				} else {
+					let apex_index = right_index;
+					apex = right;
+					if result.last().map(|last| !same_xz(last, &apex)).unwrap_or(true) {
+						result.push(apex);
+					}
+					left = apex;
+					right = apex;
+					left_index = apex_index;
+					i = apex_index + 1;
+					continue;
+				}
+			}
+			i += 1;
+		}
+
+		if result.last().map(|last| !same_xz(last, end) || last[1] != end[1]).unwrap_or(true) {
+			result.push(*end);
+		}
+		result
+	}
+}
@@ -0,0 +1,408 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+use glium::{Frame, Program};
+use serde::{Serialize, Deserialize};
+use crate::player::{Player, PlayerControlType, PlayerControlMessage};
+use crate::peer_player::PeerPlayer;
+use crate::animation::ObjAnimation;
+use crate::draw::{basic_render, ObjDef, EnvDrawInfo};
+use crate::collider::{CollisionFilter, LAYER_NPC, LAYER_ALL};
+use crate::spatial::SpatialIndex;
+use crate::collision::line_of_sight;
+use crate::navmesh::NavMesh;
+use crate::math::{add_vector, dot_product, normalize_vector};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NpcState {
+	Idle,
+	Patrol,
+	Chase,
+	Attack
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[serde(default)]
+pub struct NpcConfig {
+	pub sight_range: f32,
+	// full horizontal view angle in radians
+	pub field_of_view: f32,
+	pub attack_range: f32,
+	pub attack_interval: f32,
+	// time a target may be out of sight before the chase is given up
+	pub lose_target_time: f32,
+	pub patrol_wait_time: f32,
+	pub waypoint_radius: f32,
+	pub repath_interval: f32,
+	pub sprint_when_chasing: bool
+}
+
+impl Default for NpcConfig {
+	fn default() -> Self {
+		Self {
+			sight_range: 8.,
+			field_of_view: 2.1,
+			attack_range: 0.6,
+			attack_interval: 1.,
+			lose_target_time: 3.,
+			patrol_wait_time: 2.,
+			waypoint_radius: 0.15,
+			repath_interval: 0.5,
+			sprint_when_chasing: true
+		}
+	}
+}
+
+// Something the NPC may chase, usually a player.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NpcTarget {
+	// chosen by the caller, such as a player id, and reported back in NpcEvent::Attack
+	pub id: u32,
+	pub position: [f32; 3]
+}
+
+// What the NPC knows about its surroundings for the current update.
+#[derive(Debug, Clone, Copy)]
+pub struct NpcPerception {
+	// id of the closest visible target and its distance
+	pub visible_target: Option<(u32, f32)>,
+	pub time_since_seen: f32,
+	pub last_seen_position: Option<[f32; 3]>,
+	pub reached_destination: bool
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NpcEvent {
+	StateChanged { from: NpcState, to: NpcState },
+	Attack { target: u32 }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct NpcMessage {
+	pub position: [f32; 3],
+	pub yaw: f32,
+	pub state: NpcState,
+	pub is_colliding: bool,
+	pub is_moving: bool
+}
+
+pub type NpcTransitions = fn(&Npc, &NpcPerception) -> NpcState;
+
+pub fn default_transitions(npc: &Npc, perception: &NpcPerception) -> NpcState {
+	let config = &npc.config;
+	match (npc.state, perception.visible_target) {
+		(NpcState::Attack, Some((_, distance))) if distance <= config.attack_range * 1.2 => NpcState::Attack,
+		(_, Some((_, distance))) if distance <= config.attack_range => NpcState::Attack,
+		(_, Some(_)) => NpcState::Chase,
+		(NpcState::Attack, None) => NpcState::Chase,
+		(NpcState::Chase, None) => {
+			if perception.time_since_seen > config.lose_target_time || perception.reached_destination {
+				NpcState::Idle
+			} else {
+				NpcState::Chase
+			}
+		},
+		(NpcState::Patrol, None) => if perception.reached_destination { NpcState::Idle } else { NpcState::Patrol },
+		(NpcState::Idle, None) => {
+			if !npc.patrol_points.is_empty() && npc.state_time() >= config.patrol_wait_time {
+				NpcState::Patrol
+			} else {
+				NpcState::Idle
+			}
+		}
+	}
+}
+
+// Non-player character driven by a state machine. The body is a Player, so NPCs move
+// and collide exactly like players do. Nothing here requires a display, so NPCs can
+// be simulated on a headless server and replicated to clients with NpcMessage.
+pub struct Npc {
+	pub body: Player,
+	pub state: NpcState,
+	pub config: NpcConfig,
+	pub patrol_points: Vec<[f32; 3]>,
+	pub transitions: NpcTransitions,
+	pub update_interval: f32,
+
+	target: Option<u32>,
+	last_seen_position: Option<[f32; 3]>,
+	time_since_seen: f32,
+	state_time: f32,
+	patrol_index: usize,
+	attack_cooldown: f32,
+	path: Vec<[f32; 3]>,
+	path_goal: Option<[f32; 3]>,
+	repath_time: f32,
+	events: Vec<NpcEvent>,
+	net_update_time_count: f32
+}
+
+fn horizontal_distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+	((a[0] - b[0]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+impl Npc {
+	pub fn new(position: [f32; 3], player_cube_offset: [f32; 3], player_cube_size: [f32; 3]) -> Self {
+		let mut body = Player::new(position, PlayerControlType::Singleplayer, player_cube_offset, player_cube_size);
+		body.collision_filter = CollisionFilter::new(LAYER_NPC, LAYER_ALL);
+		body.camera.update_direction();
+		let update_interval = body.config.server_update_interval;
+		Self {
+			body: body,
+			state: NpcState::Idle,
+			config: Default::default(),
+			patrol_points: Vec::new(),
+			transitions: default_transitions,
+			update_interval: update_interval,
+			target: None,
+			last_seen_position: None,
+			time_since_seen: 0.,
+			state_time: 0.,
+			patrol_index: 0,
+			attack_cooldown: 0.,
+			path: Vec::new(),
+			path_goal: None,
+			repath_time: 0.,
+			events: Vec::new(),
+			net_update_time_count: 0.
+		}
+	}
+
+	pub fn position(&self) -> [f32; 3] {
+		self.body.camera.position
+	}
+
+	pub fn target(&self) -> Option<u32> {
+		self.target
+	}
+
+	pub fn state_time(&self) -> f32 {
+		self.state_time
+	}
+
+	pub fn set_state(&mut self, state: NpcState) {
+		if state == self.state {
+			return;
+		}
+		self.events.push(NpcEvent::StateChanged { from: self.state, to: state });
+		if state == NpcState::Idle {
+			self.target = None;
+			self.last_seen_position = None;
+		}
+		self.state = state;
+		self.state_time = 0.;
+		self.path.clear();
+		self.path_goal = None;
+	}
+
+	// Returns the events raised since the last call.
+	pub fn take_events(&mut self) -> Vec<NpcEvent> {
+		std::mem::take(&mut self.events)
+	}
+
+	fn eye_position(&self) -> [f32; 3] {
+		add_vector(&self.body.camera.position, &[0., self.body.camera.height, 0.], 1.)
+	}
+
+	// Checks range, view angle and line of sight to a target position.
+	pub fn can_see(&self, collision_index: Option<&dyn SpatialIndex>, target: &[f32; 3]) -> bool {
+		let eye = self.eye_position();
+		let target_eye = add_vector(target, &[0., self.body.camera.height, 0.], 1.);
+		let diff = add_vector(&target_eye, &eye, -1.);
+		let distance = horizontal_distance(&eye, &target_eye);
+		if distance > self.config.sight_range {
+			return false;
+		}
+		if distance > self.config.attack_range {
+			let facing = normalize_vector(&[self.body.camera.direction[0], 0., self.body.camera.direction[2]]);
+			let to_target = normalize_vector(&[diff[0], 0., diff[2]]);
+			if dot_product(&facing, &to_target) < (self.config.field_of_view / 2.).cos() {
+				return false;
+			}
+		}
+		collision_index.map(|index| line_of_sight(index, &eye, &target_eye, &self.body.collision_filter)).unwrap_or(true)
+	}
+
+	fn perceive(&mut self, collision_index: Option<&dyn SpatialIndex>, targets: &[NpcTarget], time_delta: f32) -> Option<(u32, f32)> {
+		let position = self.position();
+		// targets at non-finite positions, such as from a bad network message, are ignored
+		let visible = targets.iter()
+			.filter(|target| target.position.iter().all(|c| c.is_finite()))
+			.filter(|target| self.can_see(collision_index, &target.position))
+			.map(|target| (target, horizontal_distance(&position, &target.position)))
+			.min_by(|a, b| a.1.total_cmp(&b.1));
+
+		if let Some((target, _)) = visible {
+			self.target = Some(target.id);
+			self.last_seen_position = Some(target.position);
+			self.time_since_seen = 0.;
+		} else {
+			self.time_since_seen += time_delta;
+		}
+		visible.map(|(target, distance)| (target.id, distance))
+	}
+
+	fn face(&mut self, point: &[f32; 3]) {
+		let position = self.position();
+		let (dx, dz) = (point[0] - position[0], point[2] - position[2]);
+		if dx != 0. || dz != 0. {
+			self.body.camera.pitch_yaw = (0., dz.atan2(dx));
+			self.body.camera.update_direction();
+		}
+	}
+
+	fn stop(&mut self) {
+		self.body.input_state = Default::default();
+	}
+
+	// Steers towards the goal, following a navmesh path if one is available.
+	// Returns true once the goal has been reached.
+	fn move_towards(&mut self, goal: &[f32; 3], sprint: bool, navmesh: Option<&NavMesh>, time_delta: f32) -> bool {
+		let position = self.position();
+		if horizontal_distance(&position, goal) <= self.config.waypoint_radius {
+			self.stop();
+			return true;
+		}
+
+		self.repath_time += time_delta;
+		let goal_moved = self.path_goal.map(|g| horizontal_distance(&g, goal) > self.config.waypoint_radius).unwrap_or(true);
+		if goal_moved || self.repath_time >= self.config.repath_interval {
+			self.repath_time = 0.;
+			self.path_goal = Some(*goal);
+			self.path = navmesh.and_then(|navmesh| navmesh.find_path(&position, goal)).unwrap_or_else(|| vec![position, *goal]);
+			self.path.remove(0);
+		}
+
+		while self.path.len() > 1 && horizontal_distance(&position, &self.path[0]) <= self.config.waypoint_radius {
+			self.path.remove(0);
+		}
+
+		let waypoint = *self.path.first().unwrap_or(goal);
+		self.face(&waypoint);
+		self.body.input_state = Default::default();
+		self.body.input_state.move_forward = true;
+		self.body.input_state.sprint = sprint;
+		false
+	}
+
+	fn act(&mut self, targets: &[NpcTarget], navmesh: Option<&NavMesh>, time_delta: f32) -> bool {
+		self.attack_cooldown = (self.attack_cooldown - time_delta).max(0.);
+		match self.state {
+			NpcState::Idle => {
+				self.stop();
+				false
+			},
+			NpcState::Patrol => {
+				if self.patrol_points.is_empty() {
+					return true;
+				}
+				let point = self.patrol_points[self.patrol_index % self.patrol_points.len()];
+				let reached = self.move_towards(&point, false, navmesh, time_delta);
+				if reached {
+					self.patrol_index = (self.patrol_index + 1) % self.patrol_points.len();
+				}
+				reached
+			},
+			NpcState::Chase => match self.last_seen_position {
+				Some(point) => self.move_towards(&point, self.config.sprint_when_chasing, navmesh, time_delta),
+				None => true
+			},
+			NpcState::Attack => {
+				self.stop();
+				if let Some(target) = self.target.and_then(|id| targets.iter().find(|target| target.id == id)) {
+					self.face(&target.position);
+					if self.attack_cooldown <= 0. {
+						self.attack_cooldown = self.config.attack_interval;
+						self.events.push(NpcEvent::Attack { target: target.id });
+					}
+				}
+				false
+			}
+		}
+	}
+
+	// Targets are everything the NPC may chase, usually players.
+	// NpcEvent::Attack refers to targets by their id.
+	pub fn update(&mut self, time_delta: f32, collision_index: Option<&dyn SpatialIndex>, navmesh: Option<&NavMesh>,
+		targets: &[NpcTarget]) -> Option<NpcMessage> {
+		self.state_time += time_delta;
+		let visible_target = self.perceive(collision_index, targets, time_delta);
+		let reached_destination = self.act(targets, navmesh, time_delta);
+
+		let perception = NpcPerception {
+			visible_target: visible_target,
+			time_since_seen: self.time_since_seen,
+			last_seen_position: self.last_seen_position,
+			reached_destination: reached_destination
+		};
+		let next_state = (self.transitions)(self, &perception);
+		self.set_state(next_state);
+
+		self.body.update(time_delta, collision_index, None, None);
+
+		self.net_update_time_count += time_delta;
+		if self.net_update_time_count >= self.update_interval {
+			self.net_update_time_count = 0.;
+			Some(self.message())
+		} else {
+			None
+		}
+	}
+
+	pub fn message(&self) -> NpcMessage {
+		NpcMessage {
+			position: self.body.camera.position,
+			yaw: self.body.camera.pitch_yaw.1,
+			state: self.state,
+			is_colliding: self.body.is_colliding,
+			is_moving: self.body.is_moving
+		}
+	}
+}
+
+// Client side representation of a server controlled NPC.
+pub struct PeerNpc {
+	pub peer: PeerPlayer,
+	pub state: NpcState,
+
+	state_time: Instant
+}
+
+impl PeerNpc {
+	pub fn new() -> Self {
+		Self {
+			peer: PeerPlayer::new(),
+			state: NpcState::Idle,
+			state_time: Instant::now()
+		}
+	}
+
+	pub fn update(&mut self, incoming_msg: Option<NpcMessage>, time_delta: f32) {
+		if let Some(msg) = incoming_msg {
+			if msg.state != self.state {
+				self.state = msg.state;
+				self.state_time = Instant::now();
+			}
+			self.peer.update(Some(PlayerControlMessage::Server {
+				position: msg.position,
+				yaw: msg.yaw,
+				is_colliding: msg.is_colliding,
+				is_moving: msg.is_moving
+			}), time_delta);
+			return;
+		}
+		self.peer.update(None, time_delta);
+	}
+
+	// Draws like a PeerPlayer, using the attack animation while attacking if one is given.
+	pub fn draw(&mut self, target: &mut Frame, env_info: &EnvDrawInfo, program: &Program, moving_animation: &ObjAnimation,
+		stand_model: &BTreeMap<String, ObjDef>, jump_model: &BTreeMap<String, ObjDef>, attack_animation: Option<&ObjAnimation>) {
+		if let (NpcState::Attack, Some(attack_animation)) = (self.state, attack_animation) {
+			let model = attack_animation.get_keyframe(self.state_time.elapsed().as_secs_f32());
+			for def in model.values() {
+				basic_render(target, env_info, &self.peer.obj_draw_info, def, program, None);
+			}
+			return;
+		}
+		self.peer.draw(target, env_info, program, moving_animation, stand_model, jump_model);
+	}
+}
@@ -1,10 +1,11 @@
 use crate::cube::generate_cube_collideobj;
 use crate::quadoctree::CollisionObj;
-use crate::math::{normalize_vector, cross_product, add_vector};
+use crate::math::{normalize_vector, cross_product, add_vector, dot_product, vector_length, mult_vector};
 use crate::input::InputListener;
 use crate::camera::{Camera, UP};
-use crate::collision::check_player_collision;
-use crate::quadoctree::QuadOctreeNode;
+use crate::collision::{check_player_collision, sweep_player};
+use crate::collider::{CollisionFilter, LAYER_PLAYER, LAYER_ALL};
+use crate::spatial::SpatialIndex;
 use glium::glutin::event::{VirtualKeyCode, ElementState, MouseButton};
 use glium::Display;
 use glium::glutin::dpi::PhysicalPosition;
@@ -13,12 +14,13 @@ use crate::audio::{SoundData, SoundStream, create_sink, sound_decoder_from_data_
 use rodio::Sink;
 use crate::interpolation::InterpolationHelper;
 
-const DEFAULT_MOVE_RATE: f32 = 1.28;
-const MOUSE_SENSITIVITY: f32 = 1.8;
-const GRAVITY: f32 = 1.8;
-const JUMP_VELOCITY: f32 = 0.9;
-const CLIENT_UPDATE_INTERVAL: f32 = 0.017;
-const SERVER_UPDATE_INTERVAL: f32 = 0.15;
+const TERRAIN_CONTACT_DISTANCE: f32 = 0.08;
+const STEP_ITERATIONS: usize = 4;
+const SUBSTEP_FRACTION: f32 = 0.5;
+const SWEEP_SKIN: f32 = 0.001;
+const STOP_SPEED: f32 = 0.1;
+// just under 90 degrees, steeper slopes would be resolved by dividing by a near zero normal
+const MAX_SLOPE_ANGLE: f32 = 1.48;
 
 pub enum PlayerControlType {	
 	MultiplayerServer,
@@ -37,6 +39,9 @@ pub enum PlayerControlMessage {
 	Client {
 		pitch_yaw: (f32, f32),
 		input_state: PlayerInputState
+	},
+	Config {
+		config: PlayerConfig
 	}
 }
 
@@ -46,7 +51,64 @@ pub struct PlayerInputState {
 	pub move_left: bool,
 	pub move_right: bool,
 	pub move_back: bool,
-	pub jump: bool
+	pub jump: bool,
+	pub sprint: bool,
+	pub crouch: bool
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[serde(default)]
+pub struct PlayerConfig {
+	pub move_rate: f32,
+	pub sprint_rate: f32,
+	pub crouch_rate: f32,
+	pub crouch_height: f32,
+	pub air_control: f32,
+	pub acceleration: f32,
+	pub friction: f32,
+	pub gravity: f32,
+	pub jump_velocity: f32,
+	pub jump_count: u8,
+	// landing speed above which fall damage is taken
+	pub fall_damage_threshold: f32,
+	pub max_slope_angle: f32,
+	pub step_height: f32,
+	pub ground_snap_distance: f32,
+	pub mouse_sensitivity: f32,
+	pub client_update_interval: f32,
+	pub server_update_interval: f32
+}
+
+impl Default for PlayerConfig {
+	fn default() -> Self {
+		Self {
+			move_rate: 1.28,
+			sprint_rate: 2.2,
+			crouch_rate: 0.6,
+			crouch_height: 0.6,
+			air_control: 0.3,
+			acceleration: 10.,
+			friction: 8.,
+			gravity: 1.8,
+			jump_velocity: 0.9,
+			jump_count: 1,
+			fall_damage_threshold: 2.5,
+			max_slope_angle: 0.785,
+			step_height: 0.12,
+			ground_snap_distance: 0.15,
+			mouse_sensitivity: 1.8,
+			client_update_interval: 0.017,
+			server_update_interval: 0.15
+		}
+	}
+}
+
+impl PlayerConfig {
+	// Keeps the values within the range that movement can handle.
+	pub fn clamped(mut self) -> Self {
+		self.max_slope_angle = self.max_slope_angle.max(0.).min(MAX_SLOPE_ANGLE);
+		self
+	}
 }
 
 pub struct Player {
@@ -58,7 +120,9 @@ pub struct Player {
 	pub player_cube_size: [f32; 3],
 	pub velocity: [f32; 3],
 	pub noclip: bool,
-	pub move_rate: f32,
+	pub collision_filter: CollisionFilter,
+
+	pub config: PlayerConfig,
 
 	pub start_position: [f32; 3],
 
@@ -67,6 +131,10 @@ pub struct Player {
 
 	pub is_colliding: bool,
 	pub is_moving: bool,
+	pub is_grounded: bool,
+	pub is_crouching: bool,
+	jumps_remaining: u8,
+	last_jump_input: bool,
 
 	pub input_state: PlayerInputState,
 	net_update_time_count: f32,
@@ -87,10 +155,15 @@ impl Player {
 			player_cube: player_cube,
 			start_position: position,
 			velocity: [0., 0., 0.],
-			move_rate: DEFAULT_MOVE_RATE,
+			config: Default::default(),
 			noclip: false,
+			collision_filter: CollisionFilter::new(LAYER_PLAYER, LAYER_ALL),
 			is_colliding: false,
 			is_moving: false,
+			is_grounded: false,
+			is_crouching: false,
+			jumps_remaining: 0,
+			last_jump_input: false,
 			input_state: Default::default(),
 			net_update_time_count: 0.,
 			input_changed: false,
@@ -101,12 +174,56 @@ impl Player {
 	}
 
 	pub fn respawn(&mut self) {
-		self.camera.position = self.start_position;
+		self.respawn_at(self.start_position);
 	}
 
-	fn input_update(&mut self, time_delta: f32) {
-		let move_len = self.move_rate * time_delta;
+	pub fn respawn_at(&mut self, position: [f32; 3]) {
+		self.camera.position = position;
+		self.velocity = [0., 0., 0.];
+		self.is_grounded = false;
+		// clients would otherwise interpolate from the previous position
+		self.interpolation = InterpolationHelper::new();
+		let (offset, size) = self.cube_dimensions();
+		self.player_cube = generate_cube_collideobj(&offset, &self.camera.position,
+			&size, -self.camera.pitch_yaw.1);
+	}
+
+	pub fn config_message(&self) -> PlayerControlMessage {
+		PlayerControlMessage::Config { config: self.config }
+	}
+
+	fn cube_dimensions(&self) -> ([f32; 3], [f32; 3]) {
+		if !self.is_crouching {
+			return (self.player_cube_offset, self.player_cube_size);
+		}
+		// keep the bottom of the cube in place while shrinking its height
+		let bottom = self.player_cube_offset[1] - self.player_cube_size[1];
+		let half_height = self.player_cube_size[1] * self.config.crouch_height;
+		(
+			[self.player_cube_offset[0], bottom + half_height, self.player_cube_offset[2]],
+			[self.player_cube_size[0], half_height, self.player_cube_size[2]]
+		)
+	}
+
+	fn update_crouch(&mut self, collision_index: Option<&dyn SpatialIndex>) {
+		if self.input_state.crouch == self.is_crouching { return; }
+		if !self.input_state.crouch {
+			// only stand up if there is room above
+			if let Some(collision_index) = collision_index {
+				let standing_cube = generate_cube_collideobj(&self.player_cube_offset, &self.camera.position,
+					&self.player_cube_size, -self.camera.pitch_yaw.1);
+				let result = check_player_collision(collision_index, &self.camera.position, &standing_cube, &self.collision_filter);
+				if result.polygons.iter().any(|p| p[1] < 0.) {
+					return;
+				}
+			}
+		}
+		self.is_crouching = self.input_state.crouch;
+		let (offset, size) = self.cube_dimensions();
+		self.camera.height = offset[1] + (size[1] * 0.8);
+	}
 
+	fn input_update(&mut self, time_delta: f32) {
 		let move_dir = if !self.noclip {
 			[self.camera.direction[0], 0., self.camera.direction[2]]
 		} else {
@@ -122,12 +239,53 @@ impl Player {
 		if self.input_state.move_back { move_vec = add_vector(&move_vec, &move_dir, -1.0); }
 		if self.input_state.move_left { move_vec = add_vector(&move_vec, &direction_perp, 1.0); }
 		if self.input_state.move_right { move_vec = add_vector(&move_vec, &direction_perp, -1.0); }
-		self.camera.position = add_vector(&self.camera.position, &move_vec, move_len);
-
-		self.player_cube = generate_cube_collideobj(&self.player_cube_offset, &self.camera.position,
-			&self.player_cube_size, -self.camera.pitch_yaw.1);
 
 		self.is_moving = move_vec != [0., 0., 0.0f32];
+
+		let move_rate = if self.is_crouching {
+			self.config.crouch_rate
+		} else if self.input_state.sprint {
+			self.config.sprint_rate
+		} else {
+			self.config.move_rate
+		};
+
+		if self.noclip {
+			self.camera.position = add_vector(&self.camera.position, &move_vec, move_rate * time_delta);
+		} else {
+			let wish_dir = if self.is_moving { normalize_vector(&move_vec) } else { move_vec };
+			self.accelerate(&wish_dir, move_rate, time_delta);
+		}
+
+		let (offset, size) = self.cube_dimensions();
+		self.player_cube = generate_cube_collideobj(&offset, &self.camera.position,
+			&size, -self.camera.pitch_yaw.1);
+	}
+
+	fn accelerate(&mut self, wish_dir: &[f32; 3], wish_speed: f32, time_delta: f32) {
+		if self.is_grounded {
+			let speed = (self.velocity[0] * self.velocity[0] + self.velocity[2] * self.velocity[2]).sqrt();
+			if speed > 0. {
+				// slow speeds are braked at a fixed rate so that the player comes to a full stop
+				let new_speed = (speed - speed.max(STOP_SPEED) * self.config.friction * time_delta).max(0.);
+				self.velocity[0] *= new_speed / speed;
+				self.velocity[2] *= new_speed / speed;
+			}
+		}
+
+		let acceleration = if self.is_grounded {
+			self.config.acceleration
+		} else {
+			self.config.acceleration * self.config.air_control
+		};
+
+		let current_speed = self.velocity[0] * wish_dir[0] + self.velocity[2] * wish_dir[2];
+		let add_speed = wish_speed - current_speed;
+		if add_speed <= 0. { return; }
+
+		let accel_speed = (acceleration * wish_speed * time_delta).min(add_speed);
+		self.velocity[0] += wish_dir[0] * accel_speed;
+		self.velocity[2] += wish_dir[2] * accel_speed;
 	}
 
 	fn update_sound(&mut self, sound_stream: Option<&SoundStream>) {
@@ -149,52 +307,202 @@ impl Player {
 		}
 	}
 
-	fn fix_velocity(&mut self, correction_vec: &[f32; 3]) {
-		for i in 0..3 {
-			if (correction_vec[i] > 0.00001 && self.velocity[i] < 0.) || (correction_vec[i] < -0.00001 && self.velocity[i] > 0.) {
-				self.velocity[i] = 0.;
-			}
+	fn slide_velocity(&mut self, normal: &[f32; 3]) {
+		let into_surface = dot_product(&self.velocity, normal);
+		if into_surface < 0. {
+			self.velocity = add_vector(&self.velocity, normal, -into_surface);
 		}
 	}
 
 	fn maybe_jump(&mut self) {
-		if !self.input_state.jump { return; }
+		let jump_pressed = self.input_state.jump && !self.last_jump_input;
+		self.last_jump_input = self.input_state.jump;
+
+		if self.is_grounded {
+			// the jump off the ground does not count towards the remaining air jumps
+			self.jumps_remaining = self.config.jump_count.saturating_sub(1);
+			if !self.input_state.jump { return; }
+		} else if jump_pressed && self.jumps_remaining > 0 {
+			self.jumps_remaining -= 1;
+		} else {
+			return;
+		}
+
 		self.camera.position[1] += 0.08;
-		self.velocity[1] = JUMP_VELOCITY;
+		self.velocity[1] = self.config.jump_velocity;
+	}
+
+	fn player_cube_at(&self, position: &[f32; 3]) -> CollisionObj {
+		let (offset, size) = self.cube_dimensions();
+		generate_cube_collideobj(&offset, position, &size, -self.camera.pitch_yaw.1)
+	}
+
+	fn is_walkable(&self, normal: &[f32; 3]) -> bool {
+		normal[1] >= self.config.max_slope_angle.min(MAX_SLOPE_ANGLE).cos()
 	}
 
-	fn collision_gravity_update(&mut self, time_delta: f32, quadoctree: Option<&QuadOctreeNode>) {
+	fn try_step_up(&mut self, collision_index: &dyn SpatialIndex) -> bool {
+		for i in 1..=STEP_ITERATIONS {
+			let raised = [
+				self.camera.position[0],
+				self.camera.position[1] + self.config.step_height * (i as f32 / STEP_ITERATIONS as f32),
+				self.camera.position[2]
+			];
+			let result = check_player_collision(collision_index, &raised, &self.player_cube_at(&raised), &self.collision_filter);
+			if result.polygons.iter().all(|p| *p == [0., 0., 0.] || self.is_walkable(&normalize_vector(p))) {
+				self.camera.position = raised;
+				return true;
+			}
+		}
+		false
+	}
+
+	fn snap_to_ground(&mut self, collision_index: &dyn SpatialIndex) -> bool {
+		let lowered = [self.camera.position[0], self.camera.position[1] - self.config.ground_snap_distance, self.camera.position[2]];
+		let result = check_player_collision(collision_index, &lowered, &self.player_cube_at(&lowered), &self.collision_filter);
+		for poly_collide in result.polygons.iter().filter(|p| **p != [0., 0., 0.]) {
+			let normal = normalize_vector(poly_collide);
+			if self.is_walkable(&normal) {
+				self.camera.position[1] = lowered[1] + vector_length(poly_collide) / normal[1];
+				return true;
+			}
+		}
+		false
+	}
+
+	fn collision_gravity_update(&mut self, time_delta: f32, collision_index: Option<&dyn SpatialIndex>) {
+		let was_grounded = self.is_grounded;
 		self.is_colliding = false;
+		self.is_grounded = false;
+
+		if self.noclip { return; }
 
-		if let Some(quadoctree) = quadoctree {
-			if self.noclip { return; }
+		if collision_index.is_none() {
+			// there is nothing to fall onto, so movement stays on the plane with ground friction
+			self.is_grounded = true;
+		}
 
-			let collide_result = check_player_collision(&quadoctree, &self.camera.position, &self.player_cube);
+		if let Some(collision_index) = collision_index {
+			let collide_result = check_player_collision(collision_index, &self.camera.position, &self.player_cube, &self.collision_filter);
 
-			self.velocity[1] -= GRAVITY * time_delta;
+			self.velocity[1] -= self.config.gravity * time_delta;
 
 			for poly_collide in &collide_result.polygons {
-				self.camera.position = add_vector(&self.camera.position, &poly_collide, 1.);
-				self.fix_velocity(&poly_collide);
-				if normalize_vector(&poly_collide)[1] > 0.5 {
-					self.maybe_jump();
-				}
 				self.is_colliding = true;
+				if *poly_collide == [0., 0., 0.] {
+					// touching without penetration
+					self.is_grounded |= was_grounded;
+					continue;
+				}
+				let normal = normalize_vector(poly_collide);
+				if self.is_walkable(&normal) {
+					// resolve vertically so that standing on a slope does not push the player downhill
+					self.camera.position[1] += vector_length(poly_collide) / normal[1];
+					self.slide_velocity(&[0., 1., 0.]);
+					self.is_grounded = true;
+				} else if was_grounded && self.try_step_up(collision_index) {
+					// remaining results were computed before the step and no longer apply
+					self.is_grounded = true;
+					break;
+				} else {
+					self.camera.position = add_vector(&self.camera.position, poly_collide, 1.);
+					self.slide_velocity(&normal);
+				}
 			}
 			if let Some(tri_intersect) = collide_result.triangle {
-				if self.camera.position[1] < tri_intersect[1] + 0.08 {
+				let normal = collide_result.triangle_normal.unwrap_or([0., 1., 0.]);
+				let walkable = self.is_walkable(&normal);
+				let contact_distance = if was_grounded && walkable && self.velocity[1] <= 0. {
+					self.config.ground_snap_distance.max(TERRAIN_CONTACT_DISTANCE)
+				} else {
+					TERRAIN_CONTACT_DISTANCE
+				};
+				if self.camera.position[1] < tri_intersect[1] + contact_distance {
 					self.camera.position[1] = tri_intersect[1] + 0.02;
-					self.fix_velocity(&[0.0, 1.0, 0.0]);
-					self.maybe_jump();
+					if walkable {
+						self.slide_velocity(&[0., 1., 0.]);
+						self.is_grounded = true;
+					} else {
+						self.slide_velocity(&normal);
+					}
 					self.is_colliding = true;
 				}
 			}
 
-			self.camera.position = add_vector(&self.camera.position, &self.velocity, time_delta);
+			if !self.is_grounded && was_grounded && self.velocity[1] <= 0. && self.snap_to_ground(collision_index) {
+				self.slide_velocity(&[0., 1., 0.]);
+				self.is_grounded = true;
+				self.is_colliding = true;
+			}
+
+			self.maybe_jump();
 		}
+
+		self.apply_motion(time_delta, collision_index);
+	}
+
+	fn apply_motion(&mut self, time_delta: f32, collision_index: Option<&dyn SpatialIndex>) {
+		let motion = mult_vector(&self.velocity, time_delta);
+		let collision_index = match collision_index {
+			Some(collision_index) => collision_index,
+			None => {
+				self.camera.position = add_vector(&self.camera.position, &motion, 1.);
+				return;
+			}
+		};
+
+		let (_, size) = self.cube_dimensions();
+		let max_step = size[0].min(size[1]).min(size[2]) * 2. * SUBSTEP_FRACTION;
+		let length = vector_length(&motion);
+
+		if length <= max_step {
+			// only the vertical part is swept for regular movement, so that
+			// walking into steps still produces the overlap needed to climb them
+			self.camera.position = add_vector(&self.camera.position, &[motion[0], 0., motion[2]], 1.);
+			if motion[1] < 0. {
+				self.sweep_step(collision_index, &[0., motion[1], 0.]);
+			} else {
+				self.camera.position[1] += motion[1];
+			}
+		} else {
+			// fast movement is split into substeps, each swept against the tree so that
+			// thin colliders cannot be skipped over in a single update
+			let substeps = (length / max_step).ceil() as usize;
+			let mut step = mult_vector(&motion, 1. / substeps as f32);
+			for _ in 0..substeps {
+				step = self.sweep_step(collision_index, &step);
+			}
+		}
+		self.player_cube = self.player_cube_at(&self.camera.position);
+	}
+
+	// Moves up to the first contact along the step, returning the step clipped against the contact surface.
+	fn sweep_step(&mut self, collision_index: &dyn SpatialIndex, step: &[f32; 3]) -> [f32; 3] {
+		let cube = self.player_cube_at(&self.camera.position);
+		let (fraction, normal) = match sweep_player(collision_index, &cube, step, &self.collision_filter) {
+			Some(hit) => hit,
+			None => {
+				self.camera.position = add_vector(&self.camera.position, step, 1.);
+				return *step;
+			}
+		};
+
+		let travel = (fraction - SWEEP_SKIN / vector_length(step)).max(0.);
+		self.camera.position = add_vector(&self.camera.position, step, travel);
+
+		let fall_speed = -self.velocity[1];
+		self.slide_velocity(&normal);
+
+		self.is_colliding = true;
+		if self.is_walkable(&normal) && fall_speed > 0. {
+			self.is_grounded = true;
+		}
+
+		let into_surface = dot_product(step, &normal).min(0.);
+		add_vector(step, &normal, -into_surface)
 	}
 
-	pub fn update(&mut self, time_delta: f32, quadoctree: Option<&QuadOctreeNode>, sound_stream: Option<&SoundStream>, incoming_msg: Option<PlayerControlMessage>) -> Option<PlayerControlMessage> {
+	pub fn update(&mut self, time_delta: f32, collision_index: Option<&dyn SpatialIndex>, sound_stream: Option<&SoundStream>, incoming_msg: Option<PlayerControlMessage>) -> Option<PlayerControlMessage> {
 		match self.control_type {
 			PlayerControlType::MultiplayerServer => {
 				if let Some(incoming_msg) = incoming_msg {
@@ -205,10 +513,11 @@ impl Player {
 					}
 					return None;
 				}
+				self.update_crouch(collision_index);
 				self.input_update(time_delta);
-				self.collision_gravity_update(time_delta, quadoctree);
+				self.collision_gravity_update(time_delta, collision_index);
 				self.net_update_time_count += time_delta;
-				if self.net_update_time_count >= SERVER_UPDATE_INTERVAL {
+				if self.net_update_time_count >= self.config.server_update_interval {
 					self.net_update_time_count = 0.;
 					Some(PlayerControlMessage::Server {
 						position: self.camera.position,
@@ -222,10 +531,14 @@ impl Player {
 			},
 			PlayerControlType::MultiplayerClient => {
 				if let Some(incoming_msg) = incoming_msg {
-					if let PlayerControlMessage::Server { position, is_moving, is_colliding, .. } = incoming_msg {
-						self.is_moving = is_moving;
-						self.is_colliding = is_colliding;
-						self.interpolation.post_update(position);
+					match incoming_msg {
+						PlayerControlMessage::Server { position, is_moving, is_colliding, .. } => {
+							self.is_moving = is_moving;
+							self.is_colliding = is_colliding;
+							self.interpolation.post_update(position);
+						},
+						PlayerControlMessage::Config { config } => self.config = config.clamped(),
+						_ => ()
 					}
 					return None;
 				}
@@ -234,7 +547,7 @@ impl Player {
 					self.camera.position = value;
 				}
 				self.net_update_time_count += time_delta;
-				if self.input_changed && self.net_update_time_count >= CLIENT_UPDATE_INTERVAL {
+				if self.input_changed && self.net_update_time_count >= self.config.client_update_interval {
 					self.net_update_time_count = 0.;
 					self.input_changed = false;
 					Some(PlayerControlMessage::Client { input_state: self.input_state, pitch_yaw: self.camera.pitch_yaw })
@@ -243,8 +556,9 @@ impl Player {
 				}
 			},
 			PlayerControlType::Singleplayer => {
+				self.update_crouch(collision_index);
 				self.input_update(time_delta);
-				self.collision_gravity_update(time_delta, quadoctree);
+				self.collision_gravity_update(time_delta, collision_index);
 				self.update_sound(sound_stream);
 				None
 			}
@@ -261,6 +575,8 @@ impl InputListener for Player {
 				VirtualKeyCode::D => self.input_state.move_right = pressed,
 				VirtualKeyCode::S => self.input_state.move_back = pressed,
 				VirtualKeyCode::Space => self.input_state.jump = pressed,
+				VirtualKeyCode::LShift => self.input_state.sprint = pressed,
+				VirtualKeyCode::LControl => self.input_state.crouch = pressed,
 				VirtualKeyCode::N => {
 					if !pressed {
 						self.noclip = !self.noclip;
@@ -280,8 +596,8 @@ impl InputListener for Player {
 		let winsize = window.inner_size();
 		let middle = ((winsize.width / 2) as f32, (winsize.height / 2) as f32);
 
-		self.camera.pitch_yaw.1 -= new_pos.0 * MOUSE_SENSITIVITY;
-		self.camera.pitch_yaw.0 += (new_pos.1 * MOUSE_SENSITIVITY).min(1.57).max(-1.57);
+		self.camera.pitch_yaw.1 -= new_pos.0 * self.config.mouse_sensitivity;
+		self.camera.pitch_yaw.0 += (new_pos.1 * self.config.mouse_sensitivity).min(1.57).max(-1.57);
 		self.camera.pitch_yaw.0 = self.camera.pitch_yaw.0.min(1.57).max(-1.57);
 		
 		self.camera.update_direction();
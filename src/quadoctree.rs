@@ -1,25 +1,115 @@
+use std::borrow::Cow;
+use std::sync::Arc;
 use derive_more::{Display, Error};
-use crate::draw::Vertex;
+use serde::{Serialize, Deserialize};
+use crate::draw::{Vertex, ObjDrawInfo};
+use crate::collider::next_instance_id;
+// re-exported for code that imports them from here
+pub use crate::collider::{CollisionFilter, CollisionTransform, InstanceId, LAYER_WORLD, LAYER_PLAYER, LAYER_NPC, LAYER_PROJECTILE, LAYER_ALL};
 
 #[derive(Debug, Display, Error)]
 pub enum QuadOctreeError {
 	BucketFull { depth: usize }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct BoundingBox {
 	pub start_pos: [f32; 3],
 	pub end_pos: [f32; 3]
 }
 
-#[derive(Debug, Clone)]
+impl BoundingBox {
+	pub fn empty() -> Self {
+		Self { start_pos: [f32::MAX; 3], end_pos: [f32::MIN; 3] }
+	}
+
+	pub fn add_point(&mut self, point: &[f32; 3]) {
+		for i in 0..3 {
+			self.start_pos[i] = self.start_pos[i].min(point[i]);
+			self.end_pos[i] = self.end_pos[i].max(point[i]);
+		}
+	}
+
+	pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+		let mut result = *self;
+		result.add_point(&other.start_pos);
+		result.add_point(&other.end_pos);
+		result
+	}
+
+	pub fn intersects(&self, other: &BoundingBox) -> bool {
+		(0..3).all(|i| self.start_pos[i] <= other.end_pos[i] && self.end_pos[i] >= other.start_pos[i])
+	}
+
+	pub fn center(&self) -> [f32; 3] {
+		[
+			(self.start_pos[0] + self.end_pos[0]) / 2.,
+			(self.start_pos[1] + self.end_pos[1]) / 2.,
+			(self.start_pos[2] + self.end_pos[2]) / 2.
+		]
+	}
+
+	pub fn surface_area(&self) -> f32 {
+		let d = [
+			(self.end_pos[0] - self.start_pos[0]).max(0.),
+			(self.end_pos[1] - self.start_pos[1]).max(0.),
+			(self.end_pos[2] - self.start_pos[2]).max(0.)
+		];
+		2. * (d[0] * d[1] + d[1] * d[2] + d[2] * d[0])
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CollisionObj {
 	Triangle([[f32; 3]; 3]),
-	Polygon(Vec<Vertex>, [f32; 3])
+	Polygon(Vec<Vertex>, [f32; 3]),
+	// the id tells apart instances sharing a shape, so each can be moved on its own
+	Instance(Arc<CollisionObj>, CollisionTransform, InstanceId)
 }
 
+impl CollisionObj {
+	pub fn instance(shape: &Arc<CollisionObj>, transform: CollisionTransform, id: InstanceId) -> Self {
+		CollisionObj::Instance(shape.clone(), transform, id)
+	}
+
+	// Resolves instances into world space geometry for the narrow phase.
+	pub fn world(&self) -> Cow<'_, CollisionObj> {
+		match self {
+			CollisionObj::Instance(shape, transform, _) => Cow::Owned(match shape.world().as_ref() {
+				CollisionObj::Triangle(triangle) => CollisionObj::Triangle([
+					transform.transform_point(&triangle[0]),
+					transform.transform_point(&triangle[1]),
+					transform.transform_point(&triangle[2])
+				]),
+				CollisionObj::Polygon(vertices, center) => CollisionObj::Polygon(
+					vertices.iter().map(|v| Vertex {
+						position: transform.transform_point(&v.position),
+						normal: transform.transform_normal(&v.normal),
+						texcoords: v.texcoords
+					}).collect(),
+					transform.transform_point(center)
+				),
+				CollisionObj::Instance(..) => unreachable!()
+			}),
+			_ => Cow::Borrowed(self)
+		}
+	}
+
+	pub fn bounds(&self) -> BoundingBox {
+		let mut result = BoundingBox::empty();
+		match self.world().as_ref() {
+			CollisionObj::Triangle(triangle) => triangle.iter().for_each(|v| result.add_point(v)),
+			CollisionObj::Polygon(vertices, ..) => vertices.iter().for_each(|v| result.add_point(&v.position)),
+			CollisionObj::Instance(..) => unreachable!()
+		}
+		result
+	}
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct QuadOctreeNode {
 	child_nodes: Option<Vec<QuadOctreeNode>>,
-	items: Vec<CollisionObj>,
+	items: Vec<(CollisionObj, CollisionFilter)>,
 	bbox: BoundingBox,
 
 	is_octree: bool,
@@ -131,6 +221,10 @@ fn create_sub_nodes(node: &mut QuadOctreeNode) {
 
 fn obj_is_in_bbox(bbox: &BoundingBox, obj: &CollisionObj) -> bool {
 	match obj {
+		CollisionObj::Instance(..) => {
+			let bounds = obj.bounds();
+			return vert_is_in_bbox(bbox, &bounds.start_pos) && vert_is_in_bbox(bbox, &bounds.end_pos);
+		},
 		CollisionObj::Triangle(triangle) => {
 			for vert in triangle {
 				if !vert_is_in_bbox(bbox, &vert) {
@@ -155,81 +249,206 @@ fn vert_is_in_bbox(bbox: &BoundingBox, vert: &[f32; 3]) -> bool {
 		&& vert[2] >= bbox.start_pos[2] && vert[2] < bbox.end_pos[2]
 }
 
-fn insert_helper(node: &mut QuadOctreeNode, obj: CollisionObj) -> Result<(), QuadOctreeError> {
+fn insert_helper(node: &mut QuadOctreeNode, obj: CollisionObj, filter: CollisionFilter) -> Result<(), QuadOctreeError> {
 	let child_nodes = node.child_nodes.as_mut().unwrap();
 	for child_node in child_nodes {
 		if obj_is_in_bbox(&child_node.bbox, &obj) {
-			return insert_quadoctree_item(child_node, obj);
+			return insert_filtered_quadoctree_item(child_node, obj, filter);
 		}
 	}
 	if node.items.len() >= node.capacity {
 		return Err(QuadOctreeError::BucketFull { depth: node.depth });
 	}
-	node.items.push(obj);
+	node.items.push((obj, filter));
 	Ok(())
 }
 
-pub fn insert_quadoctree_item(node: &mut QuadOctreeNode, obj: CollisionObj) -> Result<(), QuadOctreeError> {
+pub(crate) fn insert_filtered_quadoctree_item(node: &mut QuadOctreeNode, obj: CollisionObj, filter: CollisionFilter) -> Result<(), QuadOctreeError> {
 	if node.child_nodes.is_none() {
 		if node.items.len() < node.capacity {
-			node.items.push(obj);
+			node.items.push((obj, filter));
 			return Ok(());
 		}
 
 		create_sub_nodes(node);
 		let items_clone = node.items.clone();
 		node.items.clear();
-		for item in items_clone {
-			insert_helper(node, item)?;
+		for (item, item_filter) in items_clone {
+			insert_helper(node, item, item_filter)?;
 		}
 	}
 
-	insert_helper(node, obj)
+	insert_helper(node, obj, filter)
 }
 
-pub fn traverse_quadoctree<T>(node: &QuadOctreeNode, vertex: &[f32; 3], check_func: &mut T) -> bool where T: FnMut(&CollisionObj) -> bool {
+pub fn traverse_quadoctree_bbox<T>(node: &QuadOctreeNode, bbox: &BoundingBox, filter: &CollisionFilter, check_func: &mut T) -> bool
+	where T: FnMut(&CollisionObj) -> bool {
 	if let Some(child_nodes) = node.child_nodes.as_ref() {
-		for child_node in (*child_nodes).iter() {
-			if !vert_is_in_bbox(&child_node.bbox, vertex) {
+		for child_node in child_nodes.iter() {
+			if !child_node.bbox.intersects(bbox) {
 				continue;
 			}
-			if traverse_quadoctree(child_node, vertex, check_func) {
+			if traverse_quadoctree_bbox(child_node, bbox, filter, check_func) {
 				return true;
 			}
 		}
 	}
-	for item in &node.items {
-		if check_func(&item) {
+	for (item, item_filter) in &node.items {
+		if filter.collides_with(item_filter) && check_func(item) {
 			return true;
 		}
 	}
-	return false;
+	false
 }
 
-pub fn add_obj_to_quadoctree(octree: &mut QuadOctreeNode, vertices: &[Vertex], indices: &[u32], is_collision_mesh: bool) -> Result<(), QuadOctreeError> {
+pub fn visit_quadoctree_items<T>(node: &QuadOctreeNode, visit_func: &mut T) where T: FnMut(&CollisionObj, &CollisionFilter) {
+	if let Some(child_nodes) = node.child_nodes.as_ref() {
+		for child_node in child_nodes.iter() {
+			visit_quadoctree_items(child_node, visit_func);
+		}
+	}
+	for (item, item_filter) in &node.items {
+		visit_func(item, item_filter);
+	}
+}
 
-	if is_collision_mesh {
-		let mut center = [0., 0., 0.0f32];
-		for vertex in vertices {
-			center[0] += vertex.position[0];
-			center[1] += vertex.position[1];
-			center[2] += vertex.position[2];
-		}
-		let vlen = vertices.len() as f32;
-		center[0] /= vlen;
-		center[1] /= vlen;
-		center[2] /= vlen;
-		insert_quadoctree_item(octree, CollisionObj::Polygon(
-			indices.iter().map(|i| vertices[*i as usize]).collect(),
-			center
-		))?;
-	} else {
-		for i in (0..indices.len()).step_by(3) {
-			insert_quadoctree_item(octree, CollisionObj::Triangle(
-				[vertices[indices[i] as usize].position, vertices[indices[i + 1] as usize].position,
-					vertices[indices[i + 2] as usize].position]))?;
+pub fn visit_quadoctree_nodes<T>(node: &QuadOctreeNode, visit_func: &mut T) where T: FnMut(&BoundingBox) {
+	visit_func(&node.bbox);
+	if let Some(child_nodes) = node.child_nodes.as_ref() {
+		for child_node in child_nodes.iter() {
+			visit_quadoctree_nodes(child_node, visit_func);
 		}
 	}
+}
 
+pub fn ray_intersects_bbox(bbox: &BoundingBox, origin: &[f32; 3], direction: &[f32; 3], max_distance: f32) -> bool {
+	let mut t_min = 0.0f32;
+	let mut t_max = max_distance;
+	for i in 0..3 {
+		if direction[i].abs() < f32::EPSILON {
+			if origin[i] < bbox.start_pos[i] || origin[i] > bbox.end_pos[i] {
+				return false;
+			}
+			continue;
+		}
+		let inv_dir = 1. / direction[i];
+		let mut t0 = (bbox.start_pos[i] - origin[i]) * inv_dir;
+		let mut t1 = (bbox.end_pos[i] - origin[i]) * inv_dir;
+		if t0 > t1 {
+			std::mem::swap(&mut t0, &mut t1);
+		}
+		t_min = t_min.max(t0);
+		t_max = t_max.min(t1);
+		if t_min > t_max {
+			return false;
+		}
+	}
+	true
+}
+
+pub fn traverse_quadoctree_ray<T>(node: &QuadOctreeNode, origin: &[f32; 3], direction: &[f32; 3], max_distance: f32,
+	filter: &CollisionFilter, check_func: &mut T) where T: FnMut(&CollisionObj) {
+	if !ray_intersects_bbox(&node.bbox, origin, direction, max_distance) {
+		return;
+	}
+	if let Some(child_nodes) = node.child_nodes.as_ref() {
+		for child_node in child_nodes.iter() {
+			traverse_quadoctree_ray(child_node, origin, direction, max_distance, filter, check_func);
+		}
+	}
+	for (item, item_filter) in &node.items {
+		if filter.collides_with(item_filter) {
+			check_func(item);
+		}
+	}
+}
+
+pub fn remove_quadoctree_items<T>(node: &mut QuadOctreeNode, predicate: &T) -> Vec<(CollisionObj, CollisionFilter)>
+	where T: Fn(&CollisionObj) -> bool {
+	let mut result = Vec::new();
+	let mut i = 0;
+	while i < node.items.len() {
+		if predicate(&node.items[i].0) {
+			result.push(node.items.remove(i));
+		} else {
+			i += 1;
+		}
+	}
+	if let Some(child_nodes) = node.child_nodes.as_mut() {
+		for child_node in child_nodes {
+			result.append(&mut remove_quadoctree_items(child_node, predicate));
+		}
+	}
+	result
+}
+
+pub fn collision_obj_from_mesh(vertices: &[Vertex], indices: &[u32]) -> CollisionObj {
+	let mut center = [0., 0., 0.0f32];
+	for vertex in vertices {
+		center[0] += vertex.position[0];
+		center[1] += vertex.position[1];
+		center[2] += vertex.position[2];
+	}
+	let vlen = vertices.len() as f32;
+	center[0] /= vlen;
+	center[1] /= vlen;
+	center[2] /= vlen;
+	CollisionObj::Polygon(
+		indices.iter().map(|i| vertices[*i as usize]).collect(),
+		center
+	)
+}
+
+// Collision meshes become a single polygon, anything else is split into triangles.
+pub fn collision_objs_from_mesh(vertices: &[Vertex], indices: &[u32], is_collision_mesh: bool) -> Vec<CollisionObj> {
+	if is_collision_mesh {
+		return vec![collision_obj_from_mesh(vertices, indices)];
+	}
+	indices.chunks(3).filter(|tri| tri.len() == 3).map(|tri| CollisionObj::Triangle([
+		vertices[tri[0] as usize].position,
+		vertices[tri[1] as usize].position,
+		vertices[tri[2] as usize].position
+	])).collect()
+}
+
+#[deprecated(note = "use SpatialIndex::traverse_bbox")]
+pub fn traverse_quadoctree<T>(node: &QuadOctreeNode, vertex: &[f32; 3], check_func: &mut T) -> bool where T: FnMut(&CollisionObj) -> bool {
+	let point = BoundingBox { start_pos: *vertex, end_pos: *vertex };
+	traverse_quadoctree_bbox(node, &point, &CollisionFilter::new(LAYER_ALL, LAYER_ALL), check_func)
+}
+
+#[deprecated(note = "use SpatialIndex::insert")]
+pub fn insert_quadoctree_item(node: &mut QuadOctreeNode, obj: CollisionObj) -> Result<(), QuadOctreeError> {
+	insert_filtered_quadoctree_item(node, obj, CollisionFilter::default())
+}
+
+#[deprecated(note = "use spatial::add_obj_to_index")]
+pub fn add_obj_to_quadoctree(octree: &mut QuadOctreeNode, vertices: &[Vertex], indices: &[u32], is_collision_mesh: bool) -> Result<(), QuadOctreeError> {
+	for obj in collision_objs_from_mesh(vertices, indices, is_collision_mesh) {
+		insert_filtered_quadoctree_item(octree, obj, CollisionFilter::default())?;
+	}
 	Ok(())
 }
+
+#[deprecated(note = "use spatial::add_instance_to_index, which returns the id needed to move the instance")]
+pub fn add_instance_to_quadoctree(octree: &mut QuadOctreeNode, shape: &Arc<CollisionObj>, draw_info: &ObjDrawInfo,
+	filter: CollisionFilter) -> Result<(), QuadOctreeError> {
+	insert_filtered_quadoctree_item(octree, CollisionObj::instance(shape, draw_info.into(), next_instance_id()), filter)
+}
+
+// Moves every instance of the shape to the new transform, returning false if none were found.
+#[deprecated(note = "use spatial::move_instance_in_index, which moves a single instance")]
+pub fn move_quadoctree_instance(octree: &mut QuadOctreeNode, shape: &Arc<CollisionObj>,
+	transform: CollisionTransform) -> Result<bool, QuadOctreeError> {
+	let removed = remove_quadoctree_items(octree, &|obj| match obj {
+		CollisionObj::Instance(item_shape, ..) => Arc::ptr_eq(item_shape, shape),
+		_ => false
+	});
+	let found = !removed.is_empty();
+	for (obj, filter) in removed {
+		if let CollisionObj::Instance(_, _, id) = obj {
+			insert_filtered_quadoctree_item(octree, CollisionObj::instance(shape, transform, id), filter)?;
+		}
+	}
+	Ok(found)
+}
@@ -0,0 +1,334 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use rhai::{Engine, AST, Scope, Dynamic, Array, Map, EvalAltResult, ParseError, Position, FuncArgs};
+use crate::map::GameMap;
+use crate::assets::read_asset_to_string;
+use crate::trigger::{TriggerEvent, TriggerEventKind, TriggerOccupant};
+use crate::ecs::{World, Entity, Transform};
+
+// keeps runaway scripts from stalling the server
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_CALL_LEVELS: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct ScriptError {
+	pub file: String,
+	pub line: Option<usize>,
+	pub column: Option<usize>,
+	pub message: String
+}
+
+impl fmt::Display for ScriptError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match (self.line, self.column) {
+			(Some(line), Some(column)) => write!(f, "{}:{}:{}: {}", self.file, line, column, self.message),
+			(Some(line), None) => write!(f, "{}:{}: {}", self.file, line, self.message),
+			_ => write!(f, "{}: {}", self.file, self.message)
+		}
+	}
+}
+
+impl std::error::Error for ScriptError {}
+
+impl ScriptError {
+	fn new(file: &str, position: Position, message: String) -> Self {
+		Self {
+			file: file.to_string(),
+			line: position.line(),
+			column: position.position(),
+			message: message
+		}
+	}
+
+	fn from_parse(file: &str, err: ParseError) -> Self {
+		Self::new(file, err.1, err.0.to_string())
+	}
+
+	fn from_eval(file: &str, mut err: Box<EvalAltResult>) -> Self {
+		let position = err.take_position();
+		Self::new(file, position, err.to_string())
+	}
+}
+
+// Requests made by scripts, carried out by the game after each script call.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptCommand {
+	Spawn { id: i64, model: String, position: [f32; 3] },
+	Move { id: i64, position: [f32; 3] },
+	Despawn { id: i64 },
+	PlaySound { name: String, position: Option<[f32; 3]> },
+	Chat { text: String }
+}
+
+// Component for entities spawned by scripts.
+#[derive(Debug, Clone)]
+pub struct ScriptEntity {
+	pub id: i64,
+	pub model: String
+}
+
+#[derive(Default)]
+struct ScriptState {
+	commands: Vec<ScriptCommand>,
+	misc_objs: HashMap<String, [f32; 3]>,
+	lights: HashMap<String, [f32; 3]>,
+	entities: HashMap<i64, [f32; 3]>,
+	values: HashMap<String, Dynamic>,
+	next_id: i64
+}
+
+struct Script {
+	file: String,
+	ast: AST,
+	scope: Scope<'static>
+}
+
+fn to_f32(value: &Dynamic) -> Result<f32, Box<EvalAltResult>> {
+	if let Ok(float) = value.as_float() {
+		return Ok(float as f32);
+	}
+	value.as_int().map(|int| int as f32)
+		.map_err(|type_name| format!("expected a number, found {}", type_name).into())
+}
+
+fn to_position(x: &Dynamic, y: &Dynamic, z: &Dynamic) -> Result<[f32; 3], Box<EvalAltResult>> {
+	Ok([to_f32(x)?, to_f32(y)?, to_f32(z)?])
+}
+
+fn position_array(position: &[f32; 3]) -> Array {
+	position.iter().map(|v| Dynamic::from_float(*v as rhai::FLOAT)).collect()
+}
+
+// Runs Rhai gameplay scripts. Scripts may define on_load(), on_update(time_delta),
+// on_trigger_enter(trigger, occupant_kind, occupant_id) and on_trigger_exit(...).
+// Script errors are returned rather than raised, so a broken script never takes
+// the server down.
+pub struct ScriptHost {
+	engine: Engine,
+	scripts: Vec<Script>,
+	state: Rc<RefCell<ScriptState>>,
+	entities: HashMap<i64, Entity>
+}
+
+impl ScriptHost {
+	pub fn new(map: Option<&GameMap>) -> Self {
+		let state = Rc::new(RefCell::new(ScriptState::default()));
+		if let Some(map) = map {
+			let mut state = state.borrow_mut();
+			state.misc_objs = map.misc_objs.clone();
+			state.lights = map.lights.iter().map(|(name, light)| (name.clone(), light.position)).collect();
+		}
+
+		let mut engine = Engine::new();
+		engine.set_max_operations(MAX_OPERATIONS);
+		engine.set_max_call_levels(MAX_CALL_LEVELS);
+		Self::register_bindings(&mut engine, &state);
+
+		Self {
+			engine: engine,
+			scripts: Vec::new(),
+			state: state,
+			entities: HashMap::new()
+		}
+	}
+
+	fn register_bindings(engine: &mut Engine, state: &Rc<RefCell<ScriptState>>) {
+		let s = state.clone();
+		engine.register_fn("spawn_entity", move |model: &str, x: Dynamic, y: Dynamic, z: Dynamic| -> Result<i64, Box<EvalAltResult>> {
+			let position = to_position(&x, &y, &z)?;
+			let mut state = s.borrow_mut();
+			state.next_id += 1;
+			let id = state.next_id;
+			state.entities.insert(id, position);
+			state.commands.push(ScriptCommand::Spawn { id: id, model: model.to_string(), position: position });
+			Ok(id)
+		});
+
+		let s = state.clone();
+		engine.register_fn("move_entity", move |id: i64, x: Dynamic, y: Dynamic, z: Dynamic| -> Result<(), Box<EvalAltResult>> {
+			let position = to_position(&x, &y, &z)?;
+			let mut state = s.borrow_mut();
+			match state.entities.get_mut(&id) {
+				Some(entity_position) => *entity_position = position,
+				None => return Err(format!("unknown entity {}", id).into())
+			}
+			state.commands.push(ScriptCommand::Move { id: id, position: position });
+			Ok(())
+		});
+
+		let s = state.clone();
+		engine.register_fn("despawn_entity", move |id: i64| -> bool {
+			let mut state = s.borrow_mut();
+			let found = state.entities.remove(&id).is_some();
+			if found {
+				state.commands.push(ScriptCommand::Despawn { id: id });
+			}
+			found
+		});
+
+		let s = state.clone();
+		engine.register_fn("entity_position", move |id: i64| -> Dynamic {
+			s.borrow().entities.get(&id).map(|p| position_array(p).into()).unwrap_or(Dynamic::UNIT)
+		});
+
+		let s = state.clone();
+		engine.register_fn("misc_obj", move |name: &str| -> Dynamic {
+			s.borrow().misc_objs.get(name).map(|p| position_array(p).into()).unwrap_or(Dynamic::UNIT)
+		});
+
+		let s = state.clone();
+		engine.register_fn("misc_objs", move || -> Map {
+			s.borrow().misc_objs.iter().map(|(name, p)| (name.as_str().into(), position_array(p).into())).collect()
+		});
+
+		let s = state.clone();
+		engine.register_fn("light_position", move |name: &str| -> Dynamic {
+			s.borrow().lights.get(name).map(|p| position_array(p).into()).unwrap_or(Dynamic::UNIT)
+		});
+
+		let s = state.clone();
+		engine.register_fn("light_names", move || -> Array {
+			s.borrow().lights.keys().map(|name| name.clone().into()).collect()
+		});
+
+		let s = state.clone();
+		engine.register_fn("play_sound", move |name: &str| {
+			s.borrow_mut().commands.push(ScriptCommand::PlaySound { name: name.to_string(), position: None });
+		});
+
+		let s = state.clone();
+		engine.register_fn("play_sound_at", move |name: &str, x: Dynamic, y: Dynamic, z: Dynamic| -> Result<(), Box<EvalAltResult>> {
+			let position = to_position(&x, &y, &z)?;
+			s.borrow_mut().commands.push(ScriptCommand::PlaySound { name: name.to_string(), position: Some(position) });
+			Ok(())
+		});
+
+		let s = state.clone();
+		engine.register_fn("send_chat", move |text: &str| {
+			s.borrow_mut().commands.push(ScriptCommand::Chat { text: text.to_string() });
+		});
+
+		// script functions cannot see global variables, so state shared between callbacks lives here
+		let s = state.clone();
+		engine.register_fn("set_value", move |key: &str, value: Dynamic| {
+			s.borrow_mut().values.insert(key.to_string(), value);
+		});
+
+		let s = state.clone();
+		engine.register_fn("get_value", move |key: &str| -> Dynamic {
+			s.borrow().values.get(key).cloned().unwrap_or(Dynamic::UNIT)
+		});
+	}
+
+	// Loads every script listed in the map meta. A failing script does not stop the others from loading.
+	pub fn load_map_scripts(&mut self, map: &GameMap, app_id: &str) -> Vec<ScriptError> {
+		map.scripts.iter().filter_map(|path| self.load_file(path, app_id).err()).collect()
+	}
+
+	pub fn load_file(&mut self, path: &str, app_id: &str) -> Result<(), ScriptError> {
+		let source = read_asset_to_string(path, app_id).map_err(|e| ScriptError {
+			file: path.to_string(),
+			line: None,
+			column: None,
+			message: e.to_string()
+		})?;
+		self.load_source(path, &source)
+	}
+
+	// Compiles the script, runs its top level statements and calls on_load if defined.
+	pub fn load_source(&mut self, file: &str, source: &str) -> Result<(), ScriptError> {
+		let ast = self.engine.compile(source).map_err(|e| ScriptError::from_parse(file, e))?;
+		let mut scope = Scope::new();
+		self.engine.run_ast_with_scope(&mut scope, &ast).map_err(|e| ScriptError::from_eval(file, e))?;
+		self.scripts.push(Script {
+			file: file.to_string(),
+			ast: ast,
+			scope: scope
+		});
+		let index = self.scripts.len() - 1;
+		self.call_script(index, "on_load", ())
+	}
+
+	pub fn script_count(&self) -> usize {
+		self.scripts.len()
+	}
+
+	fn call_script(&mut self, index: usize, name: &str, args: impl FuncArgs + Clone) -> Result<(), ScriptError> {
+		let script = &mut self.scripts[index];
+		if !script.ast.iter_functions().any(|f| f.name == name) {
+			return Ok(());
+		}
+		self.engine.call_fn::<Dynamic>(&mut script.scope, &script.ast, name, args)
+			.map(|_| ())
+			.map_err(|e| ScriptError::from_eval(&script.file, e))
+	}
+
+	// Calls the function in every script that defines it, collecting the errors.
+	pub fn call(&mut self, name: &str, args: impl FuncArgs + Clone) -> Vec<ScriptError> {
+		(0..self.scripts.len()).filter_map(|i| self.call_script(i, name, args.clone()).err()).collect()
+	}
+
+	pub fn update(&mut self, time_delta: f32) -> Vec<ScriptError> {
+		self.call("on_update", (time_delta as rhai::FLOAT,))
+	}
+
+	pub fn handle_trigger_events(&mut self, events: &[TriggerEvent]) -> Vec<ScriptError> {
+		let mut errors = Vec::new();
+		for event in events {
+			let name = match event.kind {
+				TriggerEventKind::Enter => "on_trigger_enter",
+				TriggerEventKind::Exit => "on_trigger_exit",
+				TriggerEventKind::Stay => continue
+			};
+			let (kind, id) = match event.occupant {
+				TriggerOccupant::Player(id) => ("player", id as i64),
+				TriggerOccupant::Entity(id) => ("entity", id as i64)
+			};
+			errors.extend(self.call(name, (event.trigger.clone(), kind.to_string(), id)));
+		}
+		errors
+	}
+
+	pub fn take_commands(&mut self) -> Vec<ScriptCommand> {
+		std::mem::take(&mut self.state.borrow_mut().commands)
+	}
+
+	// Carries out the entity commands on the world, returning the remaining commands
+	// such as sounds and chat for the game to handle.
+	pub fn apply_to_world(&mut self, world: &mut World) -> Vec<ScriptCommand> {
+		let mut result = Vec::new();
+		for command in self.take_commands() {
+			match command {
+				ScriptCommand::Spawn { id, model, position } => {
+					let entity = world.spawn();
+					let mut transform = Transform { position: position, ..Default::default() };
+					transform.generate_matrix();
+					world.insert(entity, transform);
+					world.insert(entity, ScriptEntity { id: id, model: model });
+					self.entities.insert(id, entity);
+				},
+				ScriptCommand::Move { id, position } => {
+					if let Some(entity) = self.entities.get(&id) {
+						if let Some(transform) = world.storage_mut::<Transform>().get_mut(*entity) {
+							transform.position = position;
+							transform.generate_matrix();
+						}
+					}
+				},
+				ScriptCommand::Despawn { id } => {
+					if let Some(entity) = self.entities.remove(&id) {
+						world.despawn(entity);
+					}
+				},
+				other => result.push(other)
+			}
+		}
+		result
+	}
+
+	pub fn entity(&self, id: i64) -> Option<Entity> {
+		self.entities.get(&id).copied()
+	}
+}
@@ -59,9 +59,17 @@ pub fn main_program(display: &Display) -> glium::Program {
 
 	out vec4 color;
 	uniform vec3 mtl_color;
+	uniform vec3 ambient_color;
+	uniform vec3 specular_color;
+	uniform vec3 emissive_color;
+	uniform float shininess;
+	uniform float opacity;
 	uniform vec3 obj_color;
 
 	uniform sampler2D tex;
+	uniform sampler2D specular_tex;
+	uniform sampler2D alpha_tex;
+	uniform sampler2D emissive_tex;
 	uniform vec2 texcoord_displacement;
 	uniform int light_count;
 	uniform Light lights[MAX_LIGHTS];
@@ -69,12 +77,15 @@ pub fn main_program(display: &Display) -> glium::Program {
 
 	const float ambient_val = 0.005;
 	const float diffuse_val = 0.7;
-	const float specular_val = 0.0;
 
 	void main() {
-		vec4 text_val = max(min_text_val, texture(tex, v_texcoords + texcoord_displacement));
+		vec2 texcoords = v_texcoords + texcoord_displacement;
+		vec4 text_val = max(min_text_val, texture(tex, texcoords));
+		vec3 specular_val = specular_color * texture(specular_tex, texcoords).rgb;
+		vec3 emissive_val = emissive_color * texture(emissive_tex, texcoords).rgb;
+		float alpha = text_val.a * opacity * texture(alpha_tex, texcoords).r;
 
-		color = vec4(text_val.rgb * ambient_val, text_val.a);
+		color = vec4(text_val.rgb * ambient_color * ambient_val + emissive_val, alpha);
 		for (int i = 0; i < light_count; i++) {
 			vec3 norm = normalize(v_normal);
 			vec3 light_dir = normalize(v_lights_positions[i] - v_position);
@@ -82,7 +93,7 @@ pub fn main_program(display: &Display) -> glium::Program {
 
 			vec3 view_dir = normalize(-v_position);
 			vec3 reflect_dir = reflect(-light_dir, norm);
-			float specular = pow(max(dot(view_dir, reflect_dir), 0.0), 256.0);
+			float specular = pow(max(dot(view_dir, reflect_dir), 0.0), max(shininess, 1.0));
 
 			float distance = length(v_lights_positions[i] - v_position);
 			float attenuation = 1.0 / (lights[i].att_constant + (lights[i].att_linear * distance) +
@@ -164,3 +175,35 @@ pub fn ui_program(display: &Display) -> glium::Program {
 	"#;
 	glium::Program::from_source(display, vertex_shader_src, fragment_shader_src, None).unwrap()
 }
+
+pub fn debug_program(display: &Display) -> glium::Program {
+	let vertex_shader_src = r#"
+	#version 330 core
+
+	in vec3 position;
+	in vec3 color;
+
+	out vec3 v_color;
+
+	uniform mat4 view;
+	uniform mat4 perspective;
+
+	void main() {
+		v_color = color;
+		gl_Position = perspective * view * vec4(position, 1.0);
+	}
+	"#;
+
+	let fragment_shader_src = r#"
+	#version 330 core
+
+	in vec3 v_color;
+
+	out vec4 color;
+
+	void main() {
+		color = vec4(v_color, 1.0);
+	}
+	"#;
+	glium::Program::from_source(display, vertex_shader_src, fragment_shader_src, None).unwrap()
+}
@@ -1,11 +1,10 @@
 use glium::texture::{cubemap::Cubemap, CubeLayer, TextureCreationError};
 use glium::{Frame, Surface, Display, BlitTarget, framebuffer::{SimpleFrameBuffer, ValidationError}, uniforms::MagnifySamplerFilter};
 use derive_more::{Error, From};
-use crate::textures::{load_texture, TextureLoadError};
+use crate::textures::{load_asset_texture, TextureLoadError};
 use crate::draw::{EnvDrawInfo, ObjDef};
 use crate::cube::load_cube;
 use std::path::Path;
-use crate::assets::find_asset;
 
 #[derive(Debug, derive_more::Display, Error, From)]
 pub enum SkyboxError {
@@ -23,8 +22,7 @@ pub struct Skybox {
 impl Skybox {
 	fn load_side(&self, display: &Display, blit_target: &BlitTarget, layer: CubeLayer, skybox_name: &str, img_filename: &str, app_id: &str) -> Result<(), SkyboxError> {
 		let path = Path::new("./textures").join(skybox_name).join(img_filename);
-		let path = find_asset(path.to_str().unwrap(), app_id);
-		let texture = load_texture(display, path.as_path(), false)?;
+		let texture = load_asset_texture(display, path.as_path(), app_id, false)?;
 
 		let fb = SimpleFrameBuffer::new(display, self.cubemap.main_level().image(layer))?;
 
@@ -75,6 +73,6 @@ impl Skybox {
 			perspective: env_info.perspective_mat,
 			cubemap: self.cubemap.sampled().magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear)
 		};
-		target.draw(&self.obj_def.vertices, &self.obj_def.indices, program, &uniforms, env_info.params).unwrap();
+		target.draw(&*self.obj_def.vertices, self.obj_def.index_slice(), program, &uniforms, env_info.params).unwrap();
 	}
 }
@@ -0,0 +1,255 @@
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use crate::draw::{ObjDrawInfo, Vertex};
+use crate::bvh::{Bvh, DEFAULT_LEAF_SIZE};
+use crate::collider::{CollisionFilter, CollisionTransform, InstanceId, next_instance_id};
+use crate::quadoctree::{QuadOctreeNode, QuadOctreeError, BoundingBox, CollisionObj,
+	collision_objs_from_mesh, insert_filtered_quadoctree_item, traverse_quadoctree_bbox, traverse_quadoctree_ray, remove_quadoctree_items, visit_quadoctree_items, visit_quadoctree_nodes};
+
+pub trait SpatialIndex {
+	fn insert(&mut self, obj: CollisionObj, filter: CollisionFilter) -> Result<(), QuadOctreeError>;
+
+	// Calls check_func for every item that may overlap the box, stopping early once it returns true.
+	fn traverse_bbox(&self, bbox: &BoundingBox, filter: &CollisionFilter, check_func: &mut dyn FnMut(&CollisionObj) -> bool) -> bool;
+
+	fn traverse_ray(&self, origin: &[f32; 3], direction: &[f32; 3], max_distance: f32, filter: &CollisionFilter,
+		check_func: &mut dyn FnMut(&CollisionObj));
+
+	fn remove_items(&mut self, predicate: &dyn Fn(&CollisionObj) -> bool) -> Vec<(CollisionObj, CollisionFilter)>;
+
+	// Moves an instance to the new transform, returning false if it is not in the index.
+	fn move_instance(&mut self, id: InstanceId, transform: CollisionTransform) -> Result<bool, QuadOctreeError> {
+		let removed = self.remove_items(&|obj| is_instance(obj, id));
+		let found = !removed.is_empty();
+		for (obj, filter) in removed {
+			if let CollisionObj::Instance(shape, _, _) = obj {
+				self.insert(CollisionObj::Instance(shape, transform, id), filter)?;
+			}
+		}
+		Ok(found)
+	}
+
+	fn visit_items(&self, visit_func: &mut dyn FnMut(&CollisionObj, &CollisionFilter));
+
+	// Visits the bounds of the internal nodes, mainly for debug drawing.
+	fn visit_bounds(&self, _visit_func: &mut dyn FnMut(&BoundingBox)) {}
+
+	// Rebuilds internal structures after a batch of inserts or removals.
+	fn optimize(&mut self) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpatialIndexType {
+	QuadOctree,
+	Bvh
+}
+
+impl Default for SpatialIndexType {
+	fn default() -> Self {
+		SpatialIndexType::QuadOctree
+	}
+}
+
+impl SpatialIndex for QuadOctreeNode {
+	fn insert(&mut self, obj: CollisionObj, filter: CollisionFilter) -> Result<(), QuadOctreeError> {
+		insert_filtered_quadoctree_item(self, obj, filter)
+	}
+
+	fn traverse_bbox(&self, bbox: &BoundingBox, filter: &CollisionFilter, check_func: &mut dyn FnMut(&CollisionObj) -> bool) -> bool {
+		traverse_quadoctree_bbox(self, bbox, filter, &mut |obj: &CollisionObj| check_func(obj))
+	}
+
+	fn traverse_ray(&self, origin: &[f32; 3], direction: &[f32; 3], max_distance: f32, filter: &CollisionFilter,
+		check_func: &mut dyn FnMut(&CollisionObj)) {
+		traverse_quadoctree_ray(self, origin, direction, max_distance, filter, &mut |obj: &CollisionObj| check_func(obj))
+	}
+
+	fn remove_items(&mut self, predicate: &dyn Fn(&CollisionObj) -> bool) -> Vec<(CollisionObj, CollisionFilter)> {
+		remove_quadoctree_items(self, &|obj: &CollisionObj| predicate(obj))
+	}
+
+	fn visit_items(&self, visit_func: &mut dyn FnMut(&CollisionObj, &CollisionFilter)) {
+		visit_quadoctree_items(self, &mut |obj: &CollisionObj, filter: &CollisionFilter| visit_func(obj, filter))
+	}
+
+	fn visit_bounds(&self, visit_func: &mut dyn FnMut(&BoundingBox)) {
+		visit_quadoctree_nodes(self, &mut |bbox: &BoundingBox| visit_func(bbox))
+	}
+}
+
+// Static map geometry index; an enum rather than a trait object so that built
+// indices can be serialized and cached.
+#[derive(Serialize, Deserialize)]
+pub enum StaticIndex {
+	QuadOctree(QuadOctreeNode),
+	Bvh(Bvh)
+}
+
+impl StaticIndex {
+	fn as_index(&self) -> &dyn SpatialIndex {
+		match self {
+			StaticIndex::QuadOctree(node) => node,
+			StaticIndex::Bvh(bvh) => bvh
+		}
+	}
+
+	fn as_index_mut(&mut self) -> &mut dyn SpatialIndex {
+		match self {
+			StaticIndex::QuadOctree(node) => node,
+			StaticIndex::Bvh(bvh) => bvh
+		}
+	}
+}
+
+impl SpatialIndex for StaticIndex {
+	fn insert(&mut self, obj: CollisionObj, filter: CollisionFilter) -> Result<(), QuadOctreeError> {
+		self.as_index_mut().insert(obj, filter)
+	}
+
+	fn traverse_bbox(&self, bbox: &BoundingBox, filter: &CollisionFilter, check_func: &mut dyn FnMut(&CollisionObj) -> bool) -> bool {
+		self.as_index().traverse_bbox(bbox, filter, check_func)
+	}
+
+	fn traverse_ray(&self, origin: &[f32; 3], direction: &[f32; 3], max_distance: f32, filter: &CollisionFilter,
+		check_func: &mut dyn FnMut(&CollisionObj)) {
+		self.as_index().traverse_ray(origin, direction, max_distance, filter, check_func)
+	}
+
+	fn remove_items(&mut self, predicate: &dyn Fn(&CollisionObj) -> bool) -> Vec<(CollisionObj, CollisionFilter)> {
+		self.as_index_mut().remove_items(predicate)
+	}
+
+	fn move_instance(&mut self, id: InstanceId, transform: CollisionTransform) -> Result<bool, QuadOctreeError> {
+		self.as_index_mut().move_instance(id, transform)
+	}
+
+	fn visit_items(&self, visit_func: &mut dyn FnMut(&CollisionObj, &CollisionFilter)) {
+		self.as_index().visit_items(visit_func)
+	}
+
+	fn visit_bounds(&self, visit_func: &mut dyn FnMut(&BoundingBox)) {
+		self.as_index().visit_bounds(visit_func)
+	}
+
+	fn optimize(&mut self) {
+		self.as_index_mut().optimize()
+	}
+}
+
+// Keeps terrain triangles and collision polygons in separate indices, so that
+// the many small terrain triangles do not crowd out the polygons and vice versa.
+// Instances go into their own small BVH, so that moving them each frame stays cheap.
+#[derive(Serialize, Deserialize)]
+pub struct CollisionIndices {
+	pub polygons: StaticIndex,
+	pub triangles: StaticIndex,
+	pub instances: Bvh
+}
+
+impl CollisionIndices {
+	pub fn new(polygons: StaticIndex, triangles: StaticIndex) -> Self {
+		Self {
+			polygons: polygons,
+			triangles: triangles,
+			instances: Bvh::new(DEFAULT_LEAF_SIZE)
+		}
+	}
+
+	pub fn new_quadoctree(bbox: BoundingBox, is_octree: bool, bucket_capacity: usize) -> Self {
+		Self::new(
+			StaticIndex::QuadOctree(QuadOctreeNode::new_tree(bbox, is_octree, bucket_capacity)),
+			StaticIndex::QuadOctree(QuadOctreeNode::new_tree(bbox, is_octree, bucket_capacity))
+		)
+	}
+
+	pub fn new_bvh(leaf_size: usize) -> Self {
+		Self::new(StaticIndex::Bvh(Bvh::new(leaf_size)), StaticIndex::Bvh(Bvh::new(leaf_size)))
+	}
+}
+
+impl SpatialIndex for CollisionIndices {
+	fn insert(&mut self, obj: CollisionObj, filter: CollisionFilter) -> Result<(), QuadOctreeError> {
+		match obj {
+			CollisionObj::Triangle(..) => self.triangles.insert(obj, filter),
+			CollisionObj::Instance(..) => self.instances.insert(obj, filter),
+			_ => self.polygons.insert(obj, filter)
+		}
+	}
+
+	fn traverse_bbox(&self, bbox: &BoundingBox, filter: &CollisionFilter, check_func: &mut dyn FnMut(&CollisionObj) -> bool) -> bool {
+		self.polygons.traverse_bbox(bbox, filter, check_func) || self.triangles.traverse_bbox(bbox, filter, check_func)
+			|| self.instances.traverse_bbox(bbox, filter, check_func)
+	}
+
+	fn traverse_ray(&self, origin: &[f32; 3], direction: &[f32; 3], max_distance: f32, filter: &CollisionFilter,
+		check_func: &mut dyn FnMut(&CollisionObj)) {
+		self.polygons.traverse_ray(origin, direction, max_distance, filter, check_func);
+		self.triangles.traverse_ray(origin, direction, max_distance, filter, check_func);
+		self.instances.traverse_ray(origin, direction, max_distance, filter, check_func);
+	}
+
+	fn remove_items(&mut self, predicate: &dyn Fn(&CollisionObj) -> bool) -> Vec<(CollisionObj, CollisionFilter)> {
+		let mut result = self.polygons.remove_items(predicate);
+		result.append(&mut self.triangles.remove_items(predicate));
+		result.append(&mut self.instances.remove_items(predicate));
+		result
+	}
+
+	fn move_instance(&mut self, id: InstanceId, transform: CollisionTransform) -> Result<bool, QuadOctreeError> {
+		self.instances.move_instance(id, transform)
+	}
+
+	fn visit_items(&self, visit_func: &mut dyn FnMut(&CollisionObj, &CollisionFilter)) {
+		self.polygons.visit_items(visit_func);
+		self.triangles.visit_items(visit_func);
+		self.instances.visit_items(visit_func);
+	}
+
+	fn visit_bounds(&self, visit_func: &mut dyn FnMut(&BoundingBox)) {
+		self.polygons.visit_bounds(visit_func);
+		self.triangles.visit_bounds(visit_func);
+		self.instances.visit_bounds(visit_func);
+	}
+
+	fn optimize(&mut self) {
+		self.polygons.optimize();
+		self.triangles.optimize();
+		self.instances.optimize();
+	}
+}
+
+pub(crate) fn is_instance(obj: &CollisionObj, id: InstanceId) -> bool {
+	match obj {
+		CollisionObj::Instance(_, _, item_id) => *item_id == id,
+		_ => false
+	}
+}
+
+// Returns the id used to move or remove this instance later.
+pub fn add_instance_to_index(index: &mut dyn SpatialIndex, shape: &Arc<CollisionObj>, draw_info: &ObjDrawInfo,
+	filter: CollisionFilter) -> Result<InstanceId, QuadOctreeError> {
+	let id = next_instance_id();
+	index.insert(CollisionObj::instance(shape, draw_info.into(), id), filter)?;
+	Ok(id)
+}
+
+// Moves the instance to the new transform, returning false if it was not found.
+// A BVH refits the boxes above the instance in place; call optimize() now and then
+// to rebuild a tree whose instances have moved far.
+pub fn move_instance_in_index(index: &mut dyn SpatialIndex, id: InstanceId,
+	transform: CollisionTransform) -> Result<bool, QuadOctreeError> {
+	index.move_instance(id, transform)
+}
+
+pub fn remove_instance_from_index(index: &mut dyn SpatialIndex, id: InstanceId) -> bool {
+	!index.remove_items(&|obj| is_instance(obj, id)).is_empty()
+}
+
+pub fn add_obj_to_index(index: &mut dyn SpatialIndex, vertices: &[Vertex], indices: &[u32], is_collision_mesh: bool,
+	filter: CollisionFilter) -> Result<(), QuadOctreeError> {
+	for obj in collision_objs_from_mesh(vertices, indices, is_collision_mesh) {
+		index.insert(obj, filter)?;
+	}
+	Ok(())
+}
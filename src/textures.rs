@@ -1,12 +1,17 @@
 use std::io;
-use std::fs::File;
+use std::fs;
 use std::path::Path;
 use derive_more::{Error, From};
-use std::io::BufReader;
 use std::collections::HashMap;
 use glium::{Display, texture::{Texture2d, SrgbTexture2d, RawImage2d, TextureCreationError}};
+use crate::assets::read_asset;
 
 pub const WHITE: &str = "cubik_white";
+// placeholder for textures that could not be loaded
+pub const CHECKERBOARD: &str = "cubik_checkerboard";
+
+const CHECKERBOARD_SIZE: u32 = 64;
+const CHECKERBOARD_CELL_SIZE: u32 = 8;
 
 #[derive(Debug, derive_more::Display, Error, From)]
 pub enum TextureLoadError {
@@ -15,31 +20,98 @@ pub enum TextureLoadError {
 	TextureUploadError(TextureCreationError)
 }
 
-fn load_raw_image(path: &Path, reversed: bool) -> Result<RawImage2d<u8>, TextureLoadError> {
-	let f = File::open(path.clone())?;
-	let f = BufReader::new(f);
-
-	let image = image::load(f, image::ImageFormat::from_path(path.clone())?)?.to_rgba8();
+fn raw_image_from(image: image::RgbaImage, reversed: bool) -> RawImage2d<'static, u8> {
 	let image_dim = image.dimensions();
-	Ok(if reversed {
+	if reversed {
 		RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dim)
 	} else {
 		RawImage2d::from_raw_rgba(image.into_vec(), image_dim)
-	})
+	}
+}
+
+// The image format is picked from the extension of the path the data was read from.
+fn decode_raw_image(data: &[u8], path: &Path, reversed: bool) -> Result<RawImage2d<'static, u8>, TextureLoadError> {
+	let image = image::load_from_memory_with_format(data, image::ImageFormat::from_path(path)?)?.to_rgba8();
+	Ok(raw_image_from(image, reversed))
+}
+
+fn load_raw_image(path: &Path, app_id: &str, reversed: bool) -> Result<RawImage2d<'static, u8>, TextureLoadError> {
+	decode_raw_image(&read_asset(path, app_id)?, path, reversed)
 }
 
+pub fn load_asset_texture(display: &Display, path: &Path, app_id: &str, reversed: bool) -> Result<Texture2d, TextureLoadError> {
+	let raw_image = load_raw_image(path, app_id, reversed)?;
+	Ok(Texture2d::new(display, raw_image)?)
+}
+
+// Reads the file at the path itself, without going through the asset mounts.
+#[deprecated(note = "use load_asset_texture, which reads through the asset mounts")]
 pub fn load_texture(display: &Display, path: &Path, reversed: bool) -> Result<Texture2d, TextureLoadError> {
-	let raw_image = load_raw_image(path, reversed)?;
+	let raw_image = decode_raw_image(&fs::read(path)?, path, reversed)?;
 	Ok(Texture2d::new(display, raw_image)?)
 }
 
+// Loads a texture from encoded image data, such as images embedded in model files.
+pub fn load_texture_from_memory(display: &Display, data: &[u8], reversed: bool) -> Result<Texture2d, TextureLoadError> {
+	let image = image::load_from_memory(data)?.to_rgba8();
+	Ok(Texture2d::new(display, raw_image_from(image, reversed))?)
+}
+
+pub fn load_asset_srgb_texture(display: &Display, path: &Path, app_id: &str, reversed: bool) -> Result<SrgbTexture2d, TextureLoadError> {
+	let raw_image = load_raw_image(path, app_id, reversed)?;
+	Ok(SrgbTexture2d::new(display, raw_image)?)
+}
+
+// Reads the file at the path itself, without going through the asset mounts.
+#[deprecated(note = "use load_asset_srgb_texture, which reads through the asset mounts")]
 pub fn load_srgb_texture(display: &Display, path: &Path, reversed: bool) -> Result<SrgbTexture2d, TextureLoadError> {
-	let raw_image = load_raw_image(path, reversed)?;
+	let raw_image = decode_raw_image(&fs::read(path)?, path, reversed)?;
 	Ok(SrgbTexture2d::new(display, raw_image)?)
 }
 
+pub fn create_checkerboard_texture(display: &Display) -> Result<Texture2d, TextureLoadError> {
+	let mut data: Vec<u8> = Vec::with_capacity((CHECKERBOARD_SIZE * CHECKERBOARD_SIZE * 4) as usize);
+	for y in 0..CHECKERBOARD_SIZE {
+		for x in 0..CHECKERBOARD_SIZE {
+			let is_odd = (x / CHECKERBOARD_CELL_SIZE + y / CHECKERBOARD_CELL_SIZE) % 2 == 1;
+			data.extend_from_slice(if is_odd { &[255, 0, 255, 255] } else { &[0, 0, 0, 255] });
+		}
+	}
+	Ok(Texture2d::new(display, RawImage2d::from_raw_rgba(data, (CHECKERBOARD_SIZE, CHECKERBOARD_SIZE)))?)
+}
+
+// Adds the texture to the map under the name unless it is already there. If loading fails,
+// a warning is logged and the name of the checkerboard placeholder is returned instead.
+pub fn load_named_texture<F>(display: &Display, textures: &mut HashMap<String, Texture2d>, name: &str, load: F) -> Result<String, TextureLoadError>
+	where F: FnOnce() -> Result<Texture2d, TextureLoadError> {
+	insert_named_texture(textures, name, load, || create_checkerboard_texture(display))
+}
+
+// Does the work of load_named_texture for any kind of texture, creating the placeholder
+// with the given function when it is first needed.
+pub fn insert_named_texture<T, F, P>(textures: &mut HashMap<String, T>, name: &str, load: F, placeholder: P) -> Result<String, TextureLoadError>
+	where F: FnOnce() -> Result<T, TextureLoadError>, P: FnOnce() -> Result<T, TextureLoadError> {
+	if textures.contains_key(name) {
+		return Ok(name.to_string());
+	}
+	match load() {
+		Ok(texture) => {
+			textures.insert(name.to_string(), texture);
+			Ok(name.to_string())
+		},
+		Err(e) => {
+			eprintln!("warning: could not load texture {}, using placeholder: {}", name, e);
+			if !textures.contains_key(CHECKERBOARD) {
+				textures.insert(CHECKERBOARD.to_string(), placeholder()?);
+			}
+			Ok(CHECKERBOARD.to_string())
+		}
+	}
+}
+
 pub fn create_texture_map(display: &Display) -> Result<HashMap<String, Texture2d>, TextureLoadError> {
 	let mut result = HashMap::new();
-	result.insert(WHITE.to_string(), Texture2d::new(display, RawImage2d::from_raw_rgba(vec![0, 0, 0, 0], (1, 1)))?);
+	result.insert(WHITE.to_string(), Texture2d::new(display, RawImage2d::from_raw_rgba(vec![255, 255, 255, 255], (1, 1)))?);
+	result.insert(CHECKERBOARD.to_string(), create_checkerboard_texture(display)?);
 	Ok(result)
 }
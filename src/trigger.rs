@@ -0,0 +1,129 @@
+use std::collections::{HashMap, BTreeSet};
+use serde::{Serialize, Deserialize};
+use crate::draw::Vertex;
+use crate::math::{add_vector, cross_product, dot_product};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TriggerOccupant {
+	Player(u8),
+	Entity(u32)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerEventKind {
+	Enter,
+	Stay,
+	Exit
+}
+
+#[derive(Debug, Clone)]
+pub struct TriggerEvent {
+	pub trigger: String,
+	pub occupant: TriggerOccupant,
+	pub kind: TriggerEventKind
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TriggerVolume {
+	pub triangles: Vec<[[f32; 3]; 3]>,
+	pub center: [f32; 3],
+	pub start_pos: [f32; 3],
+	pub end_pos: [f32; 3]
+}
+
+impl TriggerVolume {
+	pub fn new(vertices: &[Vertex], indices: &[u32]) -> Self {
+		let mut start_pos = [f32::MAX; 3];
+		let mut end_pos = [f32::MIN; 3];
+		let mut center = [0., 0., 0.0f32];
+		for vertex in vertices {
+			for i in 0..3 {
+				start_pos[i] = start_pos[i].min(vertex.position[i]);
+				end_pos[i] = end_pos[i].max(vertex.position[i]);
+			}
+			center = add_vector(&center, &vertex.position, 1.);
+		}
+		let vlen = vertices.len().max(1) as f32;
+		center = [center[0] / vlen, center[1] / vlen, center[2] / vlen];
+
+		Self {
+			triangles: indices.chunks(3).filter(|c| c.len() == 3).map(|c| [
+				vertices[c[0] as usize].position,
+				vertices[c[1] as usize].position,
+				vertices[c[2] as usize].position
+			]).collect(),
+			center: center,
+			start_pos: start_pos,
+			end_pos: end_pos
+		}
+	}
+
+	// Volumes are expected to be convex; a point is inside if it lies on
+	// the same side of every face plane as the volume center.
+	pub fn contains_point(&self, point: &[f32; 3]) -> bool {
+		for i in 0..3 {
+			if point[i] < self.start_pos[i] || point[i] > self.end_pos[i] {
+				return false;
+			}
+		}
+		for triangle in &self.triangles {
+			let normal = cross_product(&add_vector(&triangle[1], &triangle[0], -1.), &add_vector(&triangle[2], &triangle[0], -1.));
+			let center_side = dot_product(&normal, &add_vector(&self.center, &triangle[0], -1.));
+			let point_side = dot_product(&normal, &add_vector(point, &triangle[0], -1.));
+			if center_side * point_side < 0. {
+				return false;
+			}
+		}
+		true
+	}
+}
+
+#[derive(Default)]
+pub struct TriggerTracker {
+	// ordered so that events come out in the same order on every run
+	occupants: BTreeSet<(String, TriggerOccupant)>
+}
+
+impl TriggerTracker {
+	pub fn new() -> Self {
+		Default::default()
+	}
+
+	pub fn update<I>(&mut self, triggers: &HashMap<String, TriggerVolume>, positions: I) -> Vec<TriggerEvent>
+		where I: IntoIterator<Item = (TriggerOccupant, [f32; 3])> {
+		let mut result = Vec::new();
+		let mut new_occupants = BTreeSet::new();
+		let mut triggers: Vec<(&String, &TriggerVolume)> = triggers.iter().collect();
+		triggers.sort_by(|a, b| a.0.cmp(b.0));
+
+		for (occupant, position) in positions {
+			for &(name, volume) in triggers.iter() {
+				if !volume.contains_point(&position) {
+					continue;
+				}
+				let key = (name.clone(), occupant);
+				result.push(TriggerEvent {
+					trigger: name.clone(),
+					occupant: occupant,
+					kind: if self.occupants.contains(&key) { TriggerEventKind::Stay } else { TriggerEventKind::Enter }
+				});
+				new_occupants.insert(key);
+			}
+		}
+
+		for (name, occupant) in self.occupants.difference(&new_occupants) {
+			result.push(TriggerEvent {
+				trigger: name.clone(),
+				occupant: *occupant,
+				kind: TriggerEventKind::Exit
+			});
+		}
+
+		self.occupants = new_occupants;
+		result
+	}
+
+	pub fn occupants(&self, trigger: &str) -> Vec<TriggerOccupant> {
+		self.occupants.iter().filter(|(name, _)| name == trigger).map(|(_, o)| *o).collect()
+	}
+}
@@ -1,11 +1,11 @@
+use std::path::Path;
 use crate::fonts::{LoadedFont, FontText, TextAlign, FontError};
 use crate::input::InputListener;
 use glium::glutin::event::{VirtualKeyCode, ElementState, MouseButton};
 use glium::{Display, Frame, texture::SrgbTexture2d};
 use crate::draw::{Vertex, ObjDef, load_data_to_gpu, UIDrawInfo, ui_draw};
-use crate::textures::{load_srgb_texture, TextureLoadError};
+use crate::textures::{load_asset_srgb_texture, TextureLoadError};
 use derive_more::{From, Error};
-use crate::assets::find_asset;
 
 #[derive(Debug, derive_more::Display, From, Error)]
 pub enum UIError {
@@ -171,7 +171,7 @@ impl ImageBackground {
 		];
 		let indices = [0, 1, 2, 0, 2, 3];
 		Ok(Self {
-			texture: load_srgb_texture(display, find_asset(image_filename, app_id).as_path(), true)?,
+			texture: load_asset_srgb_texture(display, Path::new(image_filename), app_id, true)?,
 			obj_def: load_data_to_gpu(display, &vertices, &indices),
 			ui_draw_info: UIDrawInfo::new(pos, size)
 		})
@@ -1,20 +1,30 @@
 use std::io;
 use std::str::Split;
 use std::collections::{HashMap, BTreeMap};
-use std::io::{BufReader, BufRead};
-use std::path::Path;
-use std::fs::File;
-use crate::draw::{ObjDef, Vertex, load_data_to_gpu, MtlInfo, Light};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::ops::Range;
+use crate::draw::{SubMesh, Vertex, MtlInfo, Light};
+use crate::mesh::Mesh;
+use crate::baked::{BakedModel, BakedObject, BakedSubMesh, BakedModelError, BAKED_EXTENSION};
 use glium::{Display, texture::Texture2d};
 use derive_more::{Error, From};
-use crate::quadoctree::{QuadOctreeNode, QuadOctreeError, add_obj_to_quadoctree};
-use crate::textures::{load_texture, TextureLoadError};
-use crate::assets::find_asset;
-
-const COLLISION_PREFIX: &str = "collision_";
-const LIGHT_PREFIX: &str = "light_";
-const TERRAIN_PREFIX: &str = "terrain_";
-const MISC_PREFIX: &str = "misc_";
+use crate::quadoctree::QuadOctreeError;
+use crate::collider::CollisionFilter;
+use crate::spatial::{SpatialIndex, add_obj_to_index};
+use crate::textures::{load_asset_texture, load_named_texture, TextureLoadError};
+use crate::assets::open_asset;
+use crate::trigger::TriggerVolume;
+use crate::math::{add_vector, normalize_vector, vector_length};
+
+pub(crate) const COLLISION_PREFIX: &str = "collision_";
+pub(crate) const LIGHT_PREFIX: &str = "light_";
+pub(crate) const TERRAIN_PREFIX: &str = "terrain_";
+pub(crate) const MISC_PREFIX: &str = "misc_";
+pub(crate) const TRIGGER_PREFIX: &str = "trigger_";
+pub(crate) const NAV_PREFIX: &str = "nav_";
+// name given to faces that come before the first o line
+pub const DEFAULT_OBJECT_NAME: &str = "default";
 
 #[derive(Debug, derive_more::Display, Error, From)]
 pub enum WavefrontLoadError {
@@ -26,7 +36,27 @@ pub enum WavefrontLoadError {
 	FloatParseError(std::num::ParseFloatError),
 	IntParseError(std::num::ParseIntError),
 	TextureLoadError(TextureLoadError),
-	QuadOctreeCreateError(QuadOctreeError)
+	QuadOctreeCreateError(QuadOctreeError),
+	BakedModelError(BakedModelError),
+	// wraps any of the above with the location in the OBJ or MTL file
+	#[from(ignore)]
+	#[display(fmt = "{}:{}: {} (\"{}\")", "path.display()", line, source, text)]
+	ParseError { path: PathBuf, line: usize, text: String, source: Box<WavefrontLoadError> }
+}
+
+impl WavefrontLoadError {
+	// Errors from a nested file, such as an MTL file, keep their own location.
+	fn at(self, path: &Path, line: usize, text: &str) -> Self {
+		match self {
+			WavefrontLoadError::ParseError { .. } => self,
+			_ => WavefrontLoadError::ParseError {
+				path: path.to_path_buf(),
+				line: line,
+				text: text.trim().to_string(),
+				source: Box::new(self)
+			}
+		}
+	}
 }
 
 #[derive(PartialEq)]
@@ -35,7 +65,9 @@ enum MeshType {
 	Terrain,
 	Collision,
 	Light,
-	Misc
+	Misc,
+	Trigger,
+	Nav
 }
 
 fn parse_vertex_or_normal(split: &mut Split<char>, scale: &[f32; 3]) -> Result<[f32; 3], WavefrontLoadError> {
@@ -60,109 +92,332 @@ fn parse_texcoords(split: &mut Split<char>) -> Result<[f32; 2], WavefrontLoadErr
 	Ok(components)
 }
 
-fn parse_face(split: &mut Split<char>, vertex_info: &Vec<[f32; 3]>, normal_info: &Vec<[f32; 3]>,
-	texcoord_info: &Vec<[f32; 2]>, vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) -> Result<(), WavefrontLoadError> {
+// A vertex that had no normal in the file and belongs to a smoothing group.
+// Its normal is averaged over all faces sharing the position within the group.
+struct SmoothVertex {
+	vertex: u32,
+	group: u32,
+	position_index: usize,
+	face_normal: [f32; 3]
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum CornerNormal {
+	Index(usize),
+	Smooth(u32),
+	// bits of a generated flat normal
+	Flat([u32; 3])
+}
 
-	let mut face_indices: Vec<u32> = Vec::new();
+// position index, texcoord index, normal
+type CornerKey = (usize, Option<usize>, CornerNormal);
+
+// Vertex and index data of the object currently being parsed.
+#[derive(Default)]
+pub(crate) struct MeshBuilder {
+	pub(crate) vertices: Vec<Vertex>,
+	pub(crate) indices: Vec<u32>,
+	// face corners with the same indices share a vertex
+	corners: HashMap<CornerKey, u32>,
+	smooth_vertices: Vec<SmoothVertex>,
+	// first index and material of each usemtl group
+	materials: Vec<(usize, Option<MtlInfo>)>
+}
 
-	for i in 0..4 {
-		let face_index_txt = split.next();
-		let face_index_txt = match face_index_txt {
+impl MeshBuilder {
+	fn smooth_normals(&mut self) {
+		let mut sums: HashMap<(u32, usize), [f32; 3]> = HashMap::new();
+		for smooth_vertex in self.smooth_vertices.iter() {
+			let sum = sums.entry((smooth_vertex.group, smooth_vertex.position_index)).or_insert([0., 0., 0.]);
+			*sum = add_vector(sum, &smooth_vertex.face_normal, 1.);
+		}
+		for smooth_vertex in self.smooth_vertices.iter() {
+			let sum = sums[&(smooth_vertex.group, smooth_vertex.position_index)];
+			if vector_length(&sum) > 0. {
+				self.vertices[smooth_vertex.vertex as usize].normal = normalize_vector(&sum);
+			}
+		}
+		self.smooth_vertices.clear();
+	}
+
+	pub(crate) fn use_material(&mut self, material: Option<MtlInfo>) {
+		if self.materials.last().map(|(start, _)| *start == self.indices.len()).unwrap_or(false) {
+			self.materials.pop();
+		}
+		self.materials.push((self.indices.len(), material));
+	}
+
+	// Index ranges of the non-empty usemtl groups.
+	fn submeshes(&self) -> Vec<(Range<usize>, Option<MtlInfo>)> {
+		let mut result = Vec::new();
+		if self.materials.first().map(|(start, _)| *start > 0).unwrap_or(true) {
+			let end = self.materials.first().map(|(start, _)| *start).unwrap_or(self.indices.len());
+			result.push((0..end, None));
+		}
+		for (i, (start, material)) in self.materials.iter().enumerate() {
+			let end = self.materials.get(i + 1).map(|(start, _)| *start).unwrap_or(self.indices.len());
+			result.push((*start..end, material.clone()));
+		}
+		result.retain(|(range, _)| !range.is_empty());
+		result
+	}
+
+	pub(crate) fn to_mesh(&self) -> Mesh {
+		let submeshes = self.submeshes().into_iter().map(|(range, material)| SubMesh {
+			index_range: range,
+			material: material.unwrap_or_default()
+		}).collect();
+		Mesh::new(self.vertices.clone(), self.indices.clone(), submeshes)
+	}
+
+	// The material in use carries over to the next object.
+	pub(crate) fn clear(&mut self) {
+		let material = self.materials.pop().map(|(_, material)| material);
+		self.vertices.clear();
+		self.indices.clear();
+		self.corners.clear();
+		self.smooth_vertices.clear();
+		self.materials.clear();
+		if let Some(material) = material {
+			self.materials.push((0, material));
+		}
+	}
+}
+
+// OBJ indices start at 1, and negative indices count backwards from the last element read.
+fn resolve_index(index_txt: &str, len: usize, msg: &'static str) -> Result<usize, WavefrontLoadError> {
+	let index: i64 = index_txt.trim().parse()?;
+	let resolved = if index < 0 { len as i64 + index } else { index - 1 };
+	if resolved < 0 || resolved >= len as i64 {
+		return Err(WavefrontLoadError::BadIndexError { msg: msg });
+	}
+	Ok(resolved as usize)
+}
+
+// Newell's method, which also works for non-planar and concave polygons.
+// The length of the result is twice the polygon area.
+fn polygon_normal(positions: &[[f32; 3]]) -> [f32; 3] {
+	let mut normal = [0., 0., 0.0f32];
+	for (i, a) in positions.iter().enumerate() {
+		let b = &positions[(i + 1) % positions.len()];
+		normal[0] += (a[1] - b[1]) * (a[2] + b[2]);
+		normal[1] += (a[2] - b[2]) * (a[0] + b[0]);
+		normal[2] += (a[0] - b[0]) * (a[1] + b[1]);
+	}
+	normal
+}
+
+fn cross_2d(o: &[f32; 2], a: &[f32; 2], b: &[f32; 2]) -> f32 {
+	(a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+// Ear clipping triangulation of a polygon, returning triangles of polygon corner indices
+// in the winding of the polygon. Convex polygons end up as a fan around the first corner.
+fn triangulate_polygon(positions: &[[f32; 3]], normal: &[f32; 3]) -> Vec<[usize; 3]> {
+	// project onto the plane most aligned with the polygon
+	let drop_axis = (0..3).max_by(|a, b| normal[*a].abs().total_cmp(&normal[*b].abs())).unwrap();
+	let (u, v) = match drop_axis { 0 => (1, 2), 1 => (2, 0), _ => (0, 1) };
+	let points: Vec<[f32; 2]> = positions.iter().map(|p| [p[u], p[v]]).collect();
+	let winding = normal[drop_axis].signum();
+
+	let mut remaining: Vec<usize> = (0..positions.len()).collect();
+	let mut result = Vec::new();
+	while remaining.len() > 3 {
+		let len = remaining.len();
+		let ear = (1..=len).map(|k| k % len).find(|&k| {
+			let (prev, cur, next) = (remaining[(k + len - 1) % len], remaining[k], remaining[(k + 1) % len]);
+			if cross_2d(&points[prev], &points[cur], &points[next]) * winding <= 0. {
+				return false;
+			}
+			!remaining.iter().any(|&other| {
+				other != prev && other != cur && other != next
+					&& cross_2d(&points[prev], &points[cur], &points[other]) * winding >= 0.
+					&& cross_2d(&points[cur], &points[next], &points[other]) * winding >= 0.
+					&& cross_2d(&points[next], &points[prev], &points[other]) * winding >= 0.
+			})
+		});
+		match ear {
+			Some(k) => {
+				result.push([remaining[(k + len - 1) % len], remaining[k], remaining[(k + 1) % len]]);
+				remaining.remove(k);
+			},
 			None => {
-				if i < 3 {
-					return Err(WavefrontLoadError::FormatError { msg: "Face has less than 3 vertices" });
+				// degenerate polygon, fall back to a fan
+				for i in 1..(len - 1) {
+					result.push([remaining[0], remaining[i], remaining[i + 1]]);
 				}
-				break;
-			},
-			Some(val) => val
-		};
-		let mut face_index_split: Split<&str> = face_index_txt.split("/");
-		let mut face_index_ref = [0, 0, 0u32];
-		for j in 0..3 {
-			let face_index_comp = face_index_split.next()
-				.ok_or(WavefrontLoadError::FormatError { msg: "Face index ref must contain 3 indices" })?;
-			if j == 1 && face_index_comp.len() == 0 {
-				continue;
+				return result;
 			}
-			face_index_ref[j] = face_index_comp.trim().parse()?;
 		}
+	}
+	result.push([remaining[0], remaining[1], remaining[2]]);
+	result
+}
 
-		let mut new_vert = Vertex {
-			position: vertex_info.get((face_index_ref[0] - 1) as usize)
-				.ok_or(WavefrontLoadError::BadIndexError { msg: "Vertex index does not exist" })?.clone(),
-			normal: normal_info.get((face_index_ref[2] - 1) as usize)
-				.ok_or(WavefrontLoadError::BadIndexError { msg: "Normal index does not exist" })?.clone(),
-			texcoords: [0., 0.]
-		};
-		if face_index_ref[1] > 0 {
-			new_vert.texcoords = texcoord_info.get((face_index_ref[1] - 1) as usize)
-				.ok_or(WavefrontLoadError::BadIndexError { msg: "Texcoord index does not exist" })?.clone();
+// Supports the v, v/vt, v//vn and v/vt/vn forms, negative indices and any number of vertices.
+// Vertices without a normal get the face normal, or are queued for smoothing if a smoothing group is active.
+fn parse_face(split: &mut Split<char>, vertex_info: &Vec<[f32; 3]>, normal_info: &Vec<[f32; 3]>,
+	texcoord_info: &Vec<[f32; 2]>, smoothing_group: u32, mesh: &mut MeshBuilder) -> Result<(), WavefrontLoadError> {
+
+	// position index, texcoord index, normal index
+	let mut face_refs: Vec<(usize, Option<usize>, Option<usize>)> = Vec::new();
+
+	for face_index_txt in split.map(|s| s.trim()).filter(|s| !s.is_empty()) {
+		let face_index_comps: Vec<&str> = face_index_txt.split('/').collect();
+		if face_index_comps.len() > 3 {
+			return Err(WavefrontLoadError::FormatError { msg: "Face index ref has more than 3 indices" });
 		}
-		vertices.push(new_vert);
-		
-		face_indices.push((vertices.len() - 1) as u32);
+		let position = resolve_index(face_index_comps[0], vertex_info.len(), "Vertex index does not exist")?;
+		let texcoord = match face_index_comps.get(1) {
+			Some(comp) if !comp.is_empty() => Some(resolve_index(comp, texcoord_info.len(), "Texcoord index does not exist")?),
+			_ => None
+		};
+		let normal = match face_index_comps.get(2) {
+			Some(comp) if !comp.is_empty() => Some(resolve_index(comp, normal_info.len(), "Normal index does not exist")?),
+			_ => None
+		};
+		face_refs.push((position, texcoord, normal));
 	}
 
-	if split.next().is_some() {
-		return Err(WavefrontLoadError::FormatError { msg: "Face has more than 4 vertices" });
+	if face_refs.len() < 3 {
+		return Err(WavefrontLoadError::FormatError { msg: "Face has less than 3 vertices" });
 	}
 
-	indices.extend_from_slice(&[face_indices[2], face_indices[1], face_indices[0]]);
-	if face_indices.len() == 4 {
-		indices.extend_from_slice(&[face_indices[3], face_indices[2], face_indices[0]]);
+	let positions: Vec<[f32; 3]> = face_refs.iter().map(|r| vertex_info[r.0]).collect();
+	let face_normal = polygon_normal(&positions);
+	let flat_normal = if vector_length(&face_normal) > 0. { normalize_vector(&face_normal) } else { [0., 1., 0.] };
+
+	let mut corner_vertices: Vec<u32> = Vec::with_capacity(face_refs.len());
+	for (position, texcoord, normal) in face_refs.iter() {
+		let corner_normal = match normal {
+			Some(normal) => CornerNormal::Index(*normal),
+			None if smoothing_group != 0 => CornerNormal::Smooth(smoothing_group),
+			None => CornerNormal::Flat([flat_normal[0].to_bits(), flat_normal[1].to_bits(), flat_normal[2].to_bits()])
+		};
+		let is_smooth = matches!(corner_normal, CornerNormal::Smooth(_));
+		let next_vertex = mesh.vertices.len() as u32;
+		let vertex = *mesh.corners.entry((*position, *texcoord, corner_normal)).or_insert(next_vertex);
+		if vertex == next_vertex {
+			mesh.vertices.push(Vertex {
+				position: vertex_info[*position],
+				normal: normal.map(|n| normal_info[n]).unwrap_or(flat_normal),
+				texcoords: texcoord.map(|t| texcoord_info[t]).unwrap_or([0., 0.])
+			});
+		}
+		if is_smooth {
+			mesh.smooth_vertices.push(SmoothVertex {
+				vertex: vertex,
+				group: smoothing_group,
+				position_index: *position,
+				face_normal: face_normal
+			});
+		}
+		corner_vertices.push(vertex);
+	}
+
+	// the winding is reversed for rendering
+	for tri in triangulate_polygon(&positions, &face_normal) {
+		mesh.indices.extend_from_slice(&[corner_vertices[tri[2]], corner_vertices[tri[1]], corner_vertices[tri[0]]]);
 	}
 	Ok(())
 }
 
 
 
-fn load_mtl(display: &Display, obj_split: &mut Split<char>, obj_parent_dir: &Path,
-	textures: &mut HashMap<String, Texture2d>, mtl_map: &mut HashMap<String, MtlInfo>) -> Result<(), WavefrontLoadError> {
+fn parse_mtl_color(split: &mut Split<char>, msg: &'static str) -> Result<[f32; 3], WavefrontLoadError> {
+	let mut color = [0.0, 0.0, 0.0f32];
+	for component in color.iter_mut() {
+		*component = split.next()
+			.ok_or(WavefrontLoadError::FormatError { msg: msg })?
+			.trim().parse()?;
+	}
+	Ok(color)
+}
+
+fn parse_mtl_float(split: &mut Split<char>, msg: &'static str) -> Result<f32, WavefrontLoadError> {
+	Ok(split.next()
+		.ok_or(WavefrontLoadError::FormatError { msg: msg })?
+		.trim().parse()?)
+}
+
+// Loads the texture of a map statement, returning its name in the texture map.
+// Options such as -bm come before the filename, so the last argument is used.
+fn load_mtl_texture(display: Option<&Display>, split: &mut Split<char>, obj_parent_dir: &Path, app_id: &str,
+	textures: Option<&mut HashMap<String, Texture2d>>, msg: &'static str) -> Result<String, WavefrontLoadError> {
+	let img_filename = split.map(|s| s.trim()).filter(|s| !s.is_empty()).last()
+		.ok_or(WavefrontLoadError::FormatError { msg: msg })?;
+	// without a display, only the name is kept
+	match (display, textures) {
+		(Some(display), Some(textures)) => {
+			let img_path = obj_parent_dir.join(img_filename);
+			Ok(load_named_texture(display, textures, img_filename, || load_asset_texture(display, img_path.as_path(), app_id, true))?)
+		},
+		_ => Ok(img_filename.to_string())
+	}
+}
+
+fn load_mtl(display: Option<&Display>, obj_split: &mut Split<char>, obj_parent_dir: &Path, app_id: &str,
+	mut textures: Option<&mut HashMap<String, Texture2d>>, mtl_map: &mut HashMap<String, MtlInfo>) -> Result<(), WavefrontLoadError> {
 	let filename = obj_split.next()
 		.ok_or(WavefrontLoadError::FormatError { msg: "mtllib does not have filename" })?;
 	
-	let f = File::open(obj_parent_dir.join(filename.trim()).as_path())?;
-	let mut f = BufReader::new(f);
-
+	// objects using the missing materials fall back to the default material
+	let mtl_path = obj_parent_dir.join(filename.trim());
+	let mut f = match open_asset(&mtl_path, app_id) {
+		Ok(f) => f,
+		Err(e) => {
+			eprintln!("warning: could not open material library {}: {}", mtl_path.display(), e);
+			return Ok(());
+		}
+	};
 	let mut line = String::new();
 
 	let mut current_name: Option<String> = None;
 	
+	let mut line_number = 0;
 	while f.read_line(&mut line)? != 0 {
-		let mut split = line.split(' ');
+		line_number += 1;
+		let mut split = line.trim_start().split(' ');
 
-		let key = split.next().unwrap().trim();
+		let result: Result<(), WavefrontLoadError> = (|| {
+			let key = split.next().unwrap().trim();
 
-		if key == "newmtl" {
-			let name = split.next()
-					.ok_or(WavefrontLoadError::FormatError { msg: "newmtl does not have a name" })?.to_string();
-			mtl_map.insert(name.clone(), Default::default());
-			current_name = Some(name);
-		}
+			if key == "newmtl" {
+				let name = split.next()
+						.ok_or(WavefrontLoadError::FormatError { msg: "newmtl does not have a name" })?.trim().to_string();
+				mtl_map.insert(name.clone(), Default::default());
+				current_name = Some(name);
+			}
 
-		if let Some(name) = current_name.as_ref() {
-			let mtl = mtl_map.get_mut(name).unwrap();
-			match key {
-				"map_Kd" => {
-					let img_filename = split.next()
-						.ok_or(WavefrontLoadError::FormatError { msg: "map_Kd does not have a filename" })?.to_string();
-					if !textures.contains_key(&img_filename) {
-						let img_path = obj_parent_dir.join(img_filename.trim());
-						let txt = load_texture(display, img_path.as_path(), true)?;
-						textures.insert(img_filename.clone(), txt);
-					}
-					mtl.diffuse_texture = Some(img_filename.clone());
-				},
-				"Kd" => {
-					for i in 0..3 {
-						mtl.color[i] = split.next()
-							.ok_or(WavefrontLoadError::FormatError { msg: "Kd is missing a component" })?
-							.trim().parse()?;
-					}
-				},
-				&_ => ()
+			if let Some(name) = current_name.as_ref() {
+				let mtl = mtl_map.get_mut(name).unwrap();
+				match key {
+					"map_Kd" => mtl.diffuse_texture = Some(load_mtl_texture(display, &mut split, obj_parent_dir, app_id, textures.as_deref_mut(),
+						"map_Kd does not have a filename")?),
+					"map_Ks" => mtl.specular_texture = Some(load_mtl_texture(display, &mut split, obj_parent_dir, app_id, textures.as_deref_mut(),
+						"map_Ks does not have a filename")?),
+					"map_Ke" => mtl.emissive_texture = Some(load_mtl_texture(display, &mut split, obj_parent_dir, app_id, textures.as_deref_mut(),
+						"map_Ke does not have a filename")?),
+					"map_d" => mtl.alpha_texture = Some(load_mtl_texture(display, &mut split, obj_parent_dir, app_id, textures.as_deref_mut(),
+						"map_d does not have a filename")?),
+					"map_Bump" | "map_bump" | "bump" | "norm" => mtl.normal_texture = Some(load_mtl_texture(display, &mut split,
+						obj_parent_dir, app_id, textures.as_deref_mut(), "Normal map does not have a filename")?),
+					"Kd" => mtl.color = parse_mtl_color(&mut split, "Kd is missing a component")?,
+					"Ka" => mtl.ambient_color = parse_mtl_color(&mut split, "Ka is missing a component")?,
+					"Ks" => mtl.specular_color = parse_mtl_color(&mut split, "Ks is missing a component")?,
+					"Ke" => mtl.emissive_color = parse_mtl_color(&mut split, "Ke is missing a component")?,
+					"Ns" => mtl.shininess = parse_mtl_float(&mut split, "Ns does not have a value")?,
+					"d" => mtl.opacity = parse_mtl_float(&mut split, "d does not have a value")?,
+					"Tr" => mtl.opacity = 1. - parse_mtl_float(&mut split, "Tr does not have a value")?,
+					"illum" => mtl.illumination_model = split.next()
+						.ok_or(WavefrontLoadError::FormatError { msg: "illum does not have a value" })?
+						.trim().parse()?,
+					&_ => ()
+				}
 			}
-		}
+			Ok(())
+		})();
+		result.map_err(|e| e.at(&mtl_path, line_number, &line))?;
 		
 		line.clear();
 	}
@@ -170,10 +425,31 @@ fn load_mtl(display: &Display, obj_split: &mut Split<char>, obj_parent_dir: &Pat
 	Ok(())
 }
 
-fn process_obj(display: Option<&&Display>, vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>,
-	current_mtl: &Option<MtlInfo>, quadoctree: Option<&mut &mut QuadOctreeNode>, lights: Option<&mut &mut HashMap<String, Light>>,
-	misc_objs: Option<&mut &mut HashMap<String, [f32; 3]>>,
-	o_name: &mut Option<String>, result: &mut BTreeMap<String, ObjDef>) -> Result<(), WavefrontLoadError> {
+// Where the prefixed objects of a model end up. Targets left as None are skipped.
+#[derive(Default)]
+pub struct LoadTargets<'a> {
+	pub collision_index: Option<&'a mut dyn SpatialIndex>,
+	pub collision_filters: Option<&'a HashMap<String, CollisionFilter>>,
+	pub lights: Option<&'a mut HashMap<String, Light>>,
+	pub misc_objs: Option<&'a mut HashMap<String, [f32; 3]>>,
+	pub triggers: Option<&'a mut HashMap<String, TriggerVolume>>,
+	pub nav_triangles: Option<&'a mut Vec<[[f32; 3]; 3]>>
+}
+
+fn collision_filter_for(o_name: &str, collision_filters: Option<&HashMap<String, CollisionFilter>>) -> CollisionFilter {
+	// the longest matching name prefix wins
+	collision_filters.and_then(|filters| {
+		filters.iter()
+			.filter(|(prefix, _)| o_name.starts_with(prefix.as_str()))
+			.max_by_key(|(prefix, _)| prefix.len())
+			.map(|(_, filter)| *filter)
+	}).unwrap_or_default()
+}
+
+pub(crate) fn process_obj(mesh: &mut MeshBuilder, targets: &mut LoadTargets,
+	o_name: &mut Option<String>, meshes: &mut BTreeMap<String, Mesh>) -> Result<(), WavefrontLoadError> {
+	mesh.smooth_normals();
+	let (vertices, indices) = (&mesh.vertices, &mesh.indices);
 	let mesh_type = if o_name.as_ref().unwrap().starts_with(COLLISION_PREFIX) {
 		MeshType::Collision
 	} else if o_name.as_ref().unwrap().starts_with(LIGHT_PREFIX) {
@@ -182,47 +458,75 @@ fn process_obj(display: Option<&&Display>, vertices: &mut Vec<Vertex>, indices:
 		MeshType::Terrain
 	} else if o_name.as_ref().unwrap().starts_with(MISC_PREFIX) {
 		MeshType::Misc
+	} else if o_name.as_ref().unwrap().starts_with(TRIGGER_PREFIX) {
+		MeshType::Trigger
+	} else if o_name.as_ref().unwrap().starts_with(NAV_PREFIX) {
+		MeshType::Nav
 	} else { MeshType::Normal };
 
-	if let Some(display) = display {
-		if MeshType::Normal == mesh_type || MeshType::Terrain == mesh_type {
-			let mut def = load_data_to_gpu(*display, &vertices, &indices);
-			def.material = Some(current_mtl.as_ref().unwrap().clone());
-			result.insert(o_name.as_ref().unwrap().clone(), def);
-		}
+	if (MeshType::Normal == mesh_type || MeshType::Terrain == mesh_type) && !indices.is_empty() {
+		meshes.insert(o_name.as_ref().unwrap().clone(), mesh.to_mesh());
 	}
 
 	if MeshType::Misc == mesh_type {
-		if let Some(misc_objs) = misc_objs {
-			misc_objs.insert(o_name.as_ref().unwrap().to_string(), vertices[0].position);
+		if let Some(misc_objs) = targets.misc_objs.as_deref_mut() {
+			misc_objs.insert(o_name.as_ref().unwrap().trim().to_string(), vertices[0].position);
 		}
 	}
 
 	if MeshType::Terrain == mesh_type || MeshType::Collision == mesh_type {
-		if let Some(quadoctree) = quadoctree {
-			add_obj_to_quadoctree(&mut (**quadoctree), &vertices, &indices, MeshType::Collision == mesh_type)?;
+		if let Some(collision_index) = targets.collision_index.as_deref_mut() {
+			let filter = collision_filter_for(o_name.as_ref().unwrap().trim(), targets.collision_filters);
+			add_obj_to_index(collision_index, &vertices, &indices, MeshType::Collision == mesh_type, filter)?;
+		}
+	}
+
+	if MeshType::Trigger == mesh_type {
+		if let Some(triggers) = targets.triggers.as_deref_mut() {
+			triggers.insert(o_name.as_ref().unwrap().trim().to_string(), TriggerVolume::new(&vertices, &indices));
+		}
+	}
+
+	if MeshType::Nav == mesh_type {
+		if let Some(nav_triangles) = targets.nav_triangles.as_deref_mut() {
+			for tri in indices.chunks(3).filter(|c| c.len() == 3) {
+				nav_triangles.push([vertices[tri[0] as usize].position, vertices[tri[1] as usize].position,
+					vertices[tri[2] as usize].position]);
+			}
 		}
 	}
 
 	if MeshType::Light == mesh_type {
-		if let Some(lights) = lights {
+		if let Some(lights) = targets.lights.as_deref_mut() {
 			lights.insert(o_name.as_ref().unwrap().to_string().trim().to_string(),
 				Light { position: vertices[0].position, ..Default::default() });
 		}
 	}
 
-	vertices.clear();
-	indices.clear();
+	mesh.clear();
 	*o_name = None;
 	Ok(())
 }
 
-pub fn load_obj(filename: &str, app_id: &str, display: Option<&Display>, mut textures: Option<&mut HashMap<String, Texture2d>>,
-	scale: &[f32; 3], mut quadoctree: Option<&mut QuadOctreeNode>, mut lights: Option<&mut HashMap<String, Light>>,
-	mut misc_objs: Option<&mut HashMap<String, [f32; 3]>>) -> Result<BTreeMap<String, ObjDef>, WavefrontLoadError> {
-	let path = find_asset(filename, app_id);
-	let f = File::open(path.as_path())?;
-	let mut f = BufReader::new(f);
+// Hands the faces read so far to on_object, returning the number of objects finished.
+// Faces before the first o line become an object of their own instead of joining the next one.
+fn finish_object<F>(mesh: &mut MeshBuilder, o_name: &mut Option<String>, on_object: &mut F) -> Result<usize, WavefrontLoadError>
+	where F: FnMut(&mut MeshBuilder, &mut Option<String>) -> Result<(), WavefrontLoadError> {
+	if o_name.is_none() {
+		if mesh.indices.is_empty() {
+			return Ok(0);
+		}
+		*o_name = Some(DEFAULT_OBJECT_NAME.to_string());
+	}
+	on_object(mesh, o_name)?;
+	Ok(1)
+}
+
+// Parses the OBJ, calling on_object with each object once all of its faces have been read.
+fn parse_obj<F>(path: &Path, filename: &str, app_id: &str, display: Option<&Display>, mut textures: Option<&mut HashMap<String, Texture2d>>,
+	scale: &[f32; 3], mut on_object: F) -> Result<(), WavefrontLoadError>
+	where F: FnMut(&mut MeshBuilder, &mut Option<String>) -> Result<(), WavefrontLoadError> {
+	let mut f = open_asset(path, app_id)?;
 
 	let mut line = String::new();
 
@@ -230,57 +534,133 @@ pub fn load_obj(filename: &str, app_id: &str, display: Option<&Display>, mut tex
 	let mut normal_info: Vec<[f32; 3]> = Vec::new();
 	let mut texcoord_info: Vec<[f32; 2]> = Vec::new();
 
-	let mut vertices: Vec<Vertex> = Vec::new();
-	let mut indices: Vec<u32> = Vec::new();
+	let mut mesh: MeshBuilder = Default::default();
+	let mut smoothing_group = 0u32;
 
 	let mut mtl_map: HashMap<String, MtlInfo> = HashMap::new();
-	let mut current_mtl: Option<MtlInfo> = None;
 	let mut current_o_name: Option<String> = None;
+	let mut object_count = 0;
 
-	let mut result: BTreeMap<String, ObjDef> = BTreeMap::new();
-
+	let mut line_number = 0;
 	while f.read_line(&mut line)? != 0 {
+		line_number += 1;
 		let mut split = line.split(' ');
 
-		match split.next().unwrap() {
-			"mtllib" => {
-				if let Some(display) = display.as_ref() {
+		let result: Result<(), WavefrontLoadError> = (|| {
+			match split.next().unwrap() {
+				"mtllib" => {
 					let parent_dir = path.parent().unwrap();
-					load_mtl(*display, &mut split, &parent_dir, *textures.as_mut().unwrap(), &mut mtl_map)?;
-				}
-			},
-			"usemtl" => {
-				if display.is_some() {
+					load_mtl(display, &mut split, parent_dir, app_id, textures.as_deref_mut(), &mut mtl_map)?;
+				},
+				"usemtl" => {
 					let mtl_name = split.next()
-						.ok_or(WavefrontLoadError::FormatError { msg: "usemtl does not have a name" })?;
-					current_mtl = Some(mtl_map.get(mtl_name)
-						.ok_or(WavefrontLoadError::FormatError { msg: "Material does not exist" })?.clone());
-				}
-			},
-			"v" => vertex_info.push(parse_vertex_or_normal(&mut split, scale)?),
-			"vn" => normal_info.push(parse_vertex_or_normal(&mut split, &[1., 1., 1.])?),
-			"vt" => texcoord_info.push(parse_texcoords(&mut split)?),
-			"f" => parse_face(&mut split, &vertex_info, &normal_info, &texcoord_info, &mut vertices,
-				&mut indices)?,
-			"o" => {
-				if current_o_name.is_some() {
-					process_obj(display.as_ref(), &mut vertices, &mut indices, &current_mtl,
-						quadoctree.as_mut(), lights.as_mut(), misc_objs.as_mut(), &mut current_o_name, &mut result)?;
+						.ok_or(WavefrontLoadError::FormatError { msg: "usemtl does not have a name" })?.trim();
+					let material = mtl_map.get(mtl_name).cloned();
+					if material.is_none() {
+						eprintln!("warning: material {} does not exist in {}, using default material", mtl_name, filename);
+					}
+					mesh.use_material(material);
+				},
+				"v" => vertex_info.push(parse_vertex_or_normal(&mut split, scale)?),
+				"vn" => normal_info.push(parse_vertex_or_normal(&mut split, &[1., 1., 1.])?),
+				"vt" => texcoord_info.push(parse_texcoords(&mut split)?),
+				"f" => parse_face(&mut split, &vertex_info, &normal_info, &texcoord_info, smoothing_group, &mut mesh)?,
+				"s" => {
+					let group = split.next()
+						.ok_or(WavefrontLoadError::FormatError { msg: "s does not have a group" })?.trim();
+					smoothing_group = if group == "off" { 0 } else { group.parse()? };
+				},
+				"o" => {
+					object_count += finish_object(&mut mesh, &mut current_o_name, &mut on_object)?;
+					current_o_name = Some(split.next()
+						.ok_or(WavefrontLoadError::FormatError { msg: "o does not have a name" })?.to_string());
 				}
-				current_o_name = Some(split.next()
-					.ok_or(WavefrontLoadError::FormatError { msg: "o does not have a name" })?.to_string());
+				&_ => ()
 			}
-			&_ => ()
-		}
+			Ok(())
+		})();
+		result.map_err(|e| e.at(path, line_number, &line))?;
 		line.clear();
 	}
 
-	if current_o_name.is_some() {
-		process_obj(display.as_ref(), &mut vertices, &mut indices, &current_mtl,
-			quadoctree.as_mut(), lights.as_mut(), misc_objs.as_mut(), &mut current_o_name, &mut result)?;
+	object_count += finish_object(&mut mesh, &mut current_o_name, &mut on_object)?;
+	if object_count == 0 {
+		return Err(WavefrontLoadError::FormatError { msg: "OBJ file has no objects or faces" });
+	}
+	Ok(())
+}
+
+// Textures of baked materials are loaded from the directory of the baked file.
+fn load_baked_textures(display: &Display, textures: &mut HashMap<String, Texture2d>, material: &mut MtlInfo,
+	parent_dir: &Path, app_id: &str) -> Result<(), WavefrontLoadError> {
+	let mut names = [&mut material.diffuse_texture, &mut material.specular_texture, &mut material.normal_texture,
+		&mut material.alpha_texture, &mut material.emissive_texture];
+	for name in names.iter_mut().filter_map(|name| name.as_mut()) {
+		let img_path = parent_dir.join(name.as_str());
+		*name = load_named_texture(display, textures, name, || load_asset_texture(display, img_path.as_path(), app_id, true))?;
 	}
+	Ok(())
+}
 
+// Parses the OBJ and its materials without sorting the objects by name prefix, for writing
+// a baked model. Texture names are kept, but the textures are not loaded.
+pub fn bake_obj(filename: &str, app_id: &str) -> Result<BakedModel, WavefrontLoadError> {
+	let path = PathBuf::from(filename);
+	let mut result: BakedModel = Default::default();
+	parse_obj(&path, filename, app_id, None, None, &[1., 1., 1.], |mesh, o_name| {
+		mesh.smooth_normals();
+		result.objects.push(BakedObject {
+			name: o_name.take().unwrap(),
+			vertices: mesh.vertices.clone(),
+			indices: mesh.indices.clone(),
+			submeshes: mesh.submeshes().into_iter().map(|(range, material)| BakedSubMesh {
+				index_range: range,
+				material: material
+			}).collect()
+		});
+		mesh.clear();
+		Ok(())
+	})?;
 	Ok(result)
 }
 
+// Reads an OBJ, or a baked model if the file has the .cbm extension. Textures are only loaded
+// if a display is given, the meshes are returned either way. upload_meshes prepares them for drawing.
+pub fn load_obj(filename: &str, app_id: &str, display: Option<&Display>, mut textures: Option<&mut HashMap<String, Texture2d>>,
+	scale: &[f32; 3], mut targets: LoadTargets) -> Result<BTreeMap<String, Mesh>, WavefrontLoadError> {
+	let path = PathBuf::from(filename);
+	let mut meshes: BTreeMap<String, Mesh> = BTreeMap::new();
+
+	if path.extension().map(|extension| extension == BAKED_EXTENSION).unwrap_or(false) {
+		let parent_dir = path.parent().unwrap();
+		let baked = BakedModel::read(&mut open_asset(&path, app_id)?)?;
+		let mut mesh: MeshBuilder = Default::default();
+		for object in baked.objects {
+			mesh.vertices = object.vertices;
+			mesh.indices = object.indices;
+			for submesh in object.submeshes {
+				let mut material = submesh.material;
+				if let (Some(display), Some(textures), Some(material)) = (display, textures.as_deref_mut(), material.as_mut()) {
+					load_baked_textures(display, textures, material, parent_dir, app_id)?;
+				}
+				mesh.materials.push((submesh.index_range.start, material));
+			}
+			// models are baked unscaled
+			for vertex in mesh.vertices.iter_mut() {
+				for (component, scale) in vertex.position.iter_mut().zip(scale.iter()) {
+					*component *= scale;
+				}
+			}
+			process_obj(&mut mesh, &mut targets, &mut Some(object.name), &mut meshes)?;
+			mesh.materials.clear();
+		}
+		return Ok(meshes);
+	}
+
+	parse_obj(&path, filename, app_id, display, textures, scale, |mesh, o_name| {
+		process_obj(mesh, &mut targets, o_name, &mut meshes)
+	})?;
+	Ok(meshes)
+}
+
 
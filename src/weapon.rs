@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+use glium::{Frame, Program, Surface};
+use serde::{Serialize, Deserialize};
+use crate::quadoctree::CollisionObj;
+use crate::collider::{CollisionFilter, LAYER_PROJECTILE, LAYER_ALL};
+use crate::spatial::SpatialIndex;
+use crate::collision::{raycast, ray_cube, RayHit};
+use crate::camera::Camera;
+use crate::draw::{basic_render, ObjDef, ObjDrawInfo, EnvDrawInfo};
+use crate::audio::{SoundData, SoundStream, play_sound_from_data};
+use crate::math::{add_vector, mult_vector, normalize_vector, vector_length};
+
+// how long impact effects are kept around on clients
+const IMPACT_LIFETIME: f32 = 0.5;
+const RECOIL_RECOVERY_RATE: f32 = 8.;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum WeaponKind {
+	Hitscan,
+	Projectile { speed: f32, gravity: f32 }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[serde(default)]
+pub struct WeaponConfig {
+	pub kind: WeaponKind,
+	pub damage: f32,
+	// minimum time between shots
+	pub fire_interval: f32,
+	pub magazine_size: u32,
+	pub reload_time: f32,
+	pub range: f32,
+	// damage is scaled linearly from full to min_damage_factor between these distances
+	pub falloff_start: f32,
+	pub falloff_end: f32,
+	pub min_damage_factor: f32,
+	// what hitscan shots and projectiles from this weapon can hit
+	pub filter: CollisionFilter
+}
+
+impl Default for WeaponConfig {
+	fn default() -> Self {
+		Self {
+			kind: WeaponKind::Hitscan,
+			damage: 25.,
+			fire_interval: 0.2,
+			magazine_size: 12,
+			reload_time: 1.5,
+			range: 50.,
+			falloff_start: 10.,
+			falloff_end: 30.,
+			min_damage_factor: 0.4,
+			filter: CollisionFilter::new(LAYER_PROJECTILE, LAYER_ALL)
+		}
+	}
+}
+
+impl WeaponConfig {
+	pub fn damage_at(&self, distance: f32) -> f32 {
+		if distance <= self.falloff_start {
+			return self.damage;
+		}
+		let range = (self.falloff_end - self.falloff_start).max(f32::EPSILON);
+		let progress = ((distance - self.falloff_start) / range).min(1.);
+		self.damage * (1. - progress * (1. - self.min_damage_factor))
+	}
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct WeaponHit<T: Copy> {
+	// the target that was hit, or None if the shot hit the world
+	pub target: Option<T>,
+	pub point: [f32; 3],
+	pub normal: [f32; 3],
+	pub distance: f32,
+	pub damage: f32
+}
+
+pub enum FireResult<T: Copy> {
+	Hitscan(Option<WeaponHit<T>>),
+	Projectile(Projectile<T>)
+}
+
+// Replicated weapon events. The shooter is identified by the message wrapping these.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub enum WeaponMessage {
+	// client requests, resolved by the server
+	FireRequest { direction: [f32; 3] },
+	ReloadRequest,
+	// server events
+	Fired { origin: [f32; 3], direction: [f32; 3] },
+	Impact { point: [f32; 3], normal: [f32; 3], hit_target: bool },
+	Reloading,
+	Ammo { ammo: u32, reserve_ammo: u32 }
+}
+
+// Finds the closest thing hit by a ray: either world geometry in the collision index,
+// or one of the target boxes, which are generated by generate_cube_collideobj.
+pub fn trace_shot<T: Copy>(collision_index: Option<&dyn SpatialIndex>, origin: &[f32; 3], direction: &[f32; 3], max_distance: f32,
+	filter: &CollisionFilter, targets: &[(T, &CollisionObj)]) -> Option<(Option<T>, RayHit)> {
+	let mut closest: Option<(Option<T>, RayHit)> = collision_index
+		.and_then(|index| raycast(index, origin, direction, max_distance, filter))
+		.map(|hit| (None, hit));
+
+	for (id, cube) in targets {
+		if let Some(hit) = ray_cube(cube, origin, direction) {
+			if hit.distance <= max_distance && closest.map(|c| hit.distance < c.1.distance).unwrap_or(true) {
+				closest = Some((Some(*id), hit));
+			}
+		}
+	}
+	closest
+}
+
+pub struct Projectile<T: Copy> {
+	pub owner: T,
+	pub position: [f32; 3],
+	pub velocity: [f32; 3],
+	pub gravity: f32,
+	pub distance_travelled: f32,
+	pub config: WeaponConfig
+}
+
+impl<T: Copy + PartialEq> Projectile<T> {
+	pub fn is_expired(&self) -> bool {
+		self.distance_travelled >= self.config.range
+	}
+
+	// Moves the projectile and checks the travelled segment for hits. The owner is never hit.
+	pub fn update(&mut self, time_delta: f32, collision_index: Option<&dyn SpatialIndex>, targets: &[(T, &CollisionObj)]) -> Option<WeaponHit<T>> {
+		let motion = mult_vector(&self.velocity, time_delta);
+		let distance = vector_length(&motion);
+		self.velocity[1] -= self.gravity * time_delta;
+		if distance <= f32::EPSILON {
+			return None;
+		}
+
+		let other_targets: Vec<(T, &CollisionObj)> = targets.iter().filter(|(id, _)| *id != self.owner).copied().collect();
+		let hit = trace_shot(collision_index, &self.position, &motion, distance, &self.config.filter, &other_targets);
+
+		if let Some((target, hit)) = hit {
+			self.position = hit.point;
+			self.distance_travelled += hit.distance;
+			return Some(WeaponHit {
+				target: target,
+				point: hit.point,
+				normal: hit.normal,
+				distance: self.distance_travelled,
+				damage: self.config.damage_at(self.distance_travelled)
+			});
+		}
+		self.position = add_vector(&self.position, &motion, 1.);
+		self.distance_travelled += distance;
+		None
+	}
+}
+
+pub struct Weapon {
+	pub config: WeaponConfig,
+	pub ammo: u32,
+	pub reserve_ammo: u32,
+
+	cooldown: f32,
+	reload_time_left: Option<f32>
+}
+
+impl Weapon {
+	pub fn new(config: WeaponConfig, reserve_ammo: u32) -> Self {
+		Self {
+			config: config,
+			ammo: config.magazine_size,
+			reserve_ammo: reserve_ammo,
+			cooldown: 0.,
+			reload_time_left: None
+		}
+	}
+
+	pub fn is_reloading(&self) -> bool {
+		self.reload_time_left.is_some()
+	}
+
+	pub fn can_fire(&self) -> bool {
+		self.cooldown <= 0. && self.ammo > 0 && !self.is_reloading()
+	}
+
+	// Starts a reload, returning false if the magazine is full or there is no ammo left.
+	pub fn reload(&mut self) -> bool {
+		if self.is_reloading() || self.ammo >= self.config.magazine_size || self.reserve_ammo == 0 {
+			return false;
+		}
+		self.reload_time_left = Some(self.config.reload_time);
+		true
+	}
+
+	// Advances the fire and reload timers, returning true when a reload has finished.
+	pub fn update(&mut self, time_delta: f32) -> bool {
+		self.cooldown = (self.cooldown - time_delta).max(0.);
+		if let Some(time_left) = self.reload_time_left {
+			let time_left = time_left - time_delta;
+			if time_left > 0. {
+				self.reload_time_left = Some(time_left);
+				return false;
+			}
+			self.reload_time_left = None;
+			let loaded = (self.config.magazine_size - self.ammo).min(self.reserve_ammo);
+			self.ammo += loaded;
+			self.reserve_ammo -= loaded;
+			return true;
+		}
+		false
+	}
+
+	// Consumes a round and resolves the shot if the weapon is ready. Hitscan shots are resolved
+	// immediately; projectiles are returned so the caller can update them until they hit.
+	pub fn fire<T: Copy + PartialEq>(&mut self, owner: T, origin: &[f32; 3], direction: &[f32; 3],
+		collision_index: Option<&dyn SpatialIndex>, targets: &[(T, &CollisionObj)]) -> Option<FireResult<T>> {
+		if !self.can_fire() {
+			return None;
+		}
+		self.ammo -= 1;
+		self.cooldown = self.config.fire_interval;
+
+		let direction = normalize_vector(direction);
+		Some(match self.config.kind {
+			WeaponKind::Hitscan => {
+				let other_targets: Vec<(T, &CollisionObj)> = targets.iter().filter(|(id, _)| *id != owner).copied().collect();
+				let hit = trace_shot(collision_index, origin, &direction, self.config.range, &self.config.filter, &other_targets);
+				FireResult::Hitscan(hit.map(|(target, hit)| WeaponHit {
+					target: target,
+					point: hit.point,
+					normal: hit.normal,
+					distance: hit.distance,
+					damage: self.config.damage_at(hit.distance)
+				}))
+			},
+			WeaponKind::Projectile { speed, gravity } => FireResult::Projectile(Projectile {
+				owner: owner,
+				position: *origin,
+				velocity: mult_vector(&direction, speed),
+				gravity: gravity,
+				distance_travelled: 0.,
+				config: self.config
+			})
+		})
+	}
+
+	pub fn ammo_message(&self) -> WeaponMessage {
+		WeaponMessage::Ammo { ammo: self.ammo, reserve_ammo: self.reserve_ammo }
+	}
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Impact {
+	pub point: [f32; 3],
+	pub normal: [f32; 3],
+	pub hit_target: bool,
+	pub age: f32
+}
+
+// Client side sounds and effects for replicated weapon events.
+#[derive(Default)]
+pub struct WeaponEffects {
+	pub fire_sound: Option<SoundData>,
+	pub reload_sound: Option<SoundData>,
+	pub impact_sound: Option<SoundData>,
+	pub impacts: Vec<Impact>
+}
+
+impl WeaponEffects {
+	pub fn new() -> Self {
+		Default::default()
+	}
+
+	pub fn handle_message(&mut self, msg: &WeaponMessage, sound_stream: Option<&SoundStream>) {
+		let sound = match msg {
+			WeaponMessage::Fired { .. } => self.fire_sound.as_ref(),
+			WeaponMessage::Reloading => self.reload_sound.as_ref(),
+			WeaponMessage::Impact { point, normal, hit_target } => {
+				self.impacts.push(Impact { point: *point, normal: *normal, hit_target: *hit_target, age: 0. });
+				self.impact_sound.as_ref()
+			},
+			_ => None
+		};
+		if let (Some(sound), Some(sound_stream)) = (sound, sound_stream) {
+			let _ = play_sound_from_data(sound_stream, sound);
+		}
+	}
+
+	pub fn update(&mut self, time_delta: f32) {
+		for impact in self.impacts.iter_mut() {
+			impact.age += time_delta;
+		}
+		self.impacts.retain(|impact| impact.age < IMPACT_LIFETIME);
+	}
+}
+
+// First person weapon model, drawn on top of the scene at a fixed offset from the camera.
+pub struct ViewModel {
+	pub objects: BTreeMap<String, ObjDef>,
+	pub offset: [f32; 3],
+	pub scale: f32,
+	pub recoil_distance: f32,
+
+	recoil: f32
+}
+
+impl ViewModel {
+	pub fn new(objects: BTreeMap<String, ObjDef>, offset: [f32; 3], scale: f32) -> Self {
+		Self {
+			objects: objects,
+			offset: offset,
+			scale: scale,
+			recoil_distance: 0.05,
+			recoil: 0.
+		}
+	}
+
+	pub fn kick(&mut self) {
+		self.recoil = 1.;
+	}
+
+	pub fn update(&mut self, time_delta: f32) {
+		self.recoil = (self.recoil - time_delta * RECOIL_RECOVERY_RATE).max(0.);
+	}
+
+	pub fn draw(&self, target: &mut Frame, env_info: &EnvDrawInfo, program: &Program, camera: &Camera) {
+		// clearing the depth buffer keeps the model from clipping into walls
+		target.clear_depth(1.0);
+		let offset = [self.offset[0], self.offset[1], self.offset[2] - self.recoil * self.recoil_distance];
+		let draw_info = ObjDrawInfo {
+			model_mat: Some(camera.view_model_matrix(&offset, self.scale)),
+			..Default::default()
+		};
+		for def in self.objects.values() {
+			basic_render(target, env_info, &draw_info, def, program, None);
+		}
+	}
+}
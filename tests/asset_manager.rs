@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+use cubik::assets::{Mount, vfs_mut};
+use cubik::asset_manager::{AssetManager, Handle};
+
+// Mounts the file in memory, ahead of the mounts added before it.
+fn mount_file(path: &str, data: &[u8]) {
+	let files: HashMap<String, Vec<u8>> = vec![(path.to_string(), data.to_vec())].into_iter().collect();
+	vfs_mut(|vfs| vfs.mount_first(Mount::memory(files)));
+}
+
+#[test]
+fn second_load_returns_cached_asset() {
+	mount_file("asset_manager_cached/beep.ogg", b"first");
+	let mut manager = AssetManager::headless();
+	let first = manager.load_sound("asset_manager_cached/beep.ogg", "cubik").unwrap();
+	// the cached copy is returned without reading the file again
+	mount_file("asset_manager_cached/beep.ogg", b"second");
+	let second = manager.load_sound("asset_manager_cached/beep.ogg", "cubik").unwrap();
+	assert!(Handle::ptr_eq(&first, &second));
+	assert_eq!(*second, b"first".to_vec());
+}
+
+#[test]
+fn unused_assets_are_unloaded() {
+	mount_file("asset_manager_unload/beep.ogg", b"first");
+	mount_file("asset_manager_unload/boop.ogg", b"kept");
+	let mut manager = AssetManager::headless();
+	let first = manager.load_sound("asset_manager_unload/beep.ogg", "cubik").unwrap();
+	let second = manager.load_sound("asset_manager_unload/beep.ogg", "cubik").unwrap();
+	let kept = manager.load_sound("asset_manager_unload/boop.ogg", "cubik").unwrap();
+
+	drop(first);
+	assert_eq!(manager.unload_unused(), 0);
+	drop(second);
+	assert_eq!(manager.unload_unused(), 1);
+	assert_eq!(manager.unload_unused(), 0);
+
+	// the next load reads the file again
+	mount_file("asset_manager_unload/beep.ogg", b"second");
+	assert_eq!(*manager.load_sound("asset_manager_unload/beep.ogg", "cubik").unwrap(), b"second".to_vec());
+	let kept_again = manager.load_sound("asset_manager_unload/boop.ogg", "cubik").unwrap();
+	assert!(Handle::ptr_eq(&kept, &kept_again));
+}
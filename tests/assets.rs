@@ -0,0 +1,91 @@
+use std::io::Write;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use zip::ZipWriter;
+use zip::write::FileOptions;
+use cubik::assets::{Vfs, Mount};
+
+fn memory_mount(files: &[(&str, &str)]) -> Mount {
+	Mount::memory(files.iter().map(|(path, data)| (path.to_string(), data.as_bytes().to_vec())).collect::<HashMap<_, _>>())
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("cubik_assets_{}_{}", name, std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+fn read_string(vfs: &Vfs, path: &str) -> String {
+	String::from_utf8(vfs.read(Path::new(path), "cubik").unwrap()).unwrap()
+}
+
+#[test]
+fn memory_mount_reads_and_lists_files() {
+	let mut vfs = Vfs::new();
+	vfs.mount(memory_mount(&[("models/a.obj", "a"), ("models/textures/b.png", "b"), ("./c.toml", "c")]));
+
+	assert_eq!(read_string(&vfs, "models/a.obj"), "a");
+	assert_eq!(read_string(&vfs, "models/../models/textures/b.png"), "b");
+	assert_eq!(read_string(&vfs, "c.toml"), "c");
+	assert!(vfs.exists(Path::new("models/textures"), "cubik"));
+	assert!(!vfs.exists(Path::new("models/missing.obj"), "cubik"));
+	assert!(vfs.read(Path::new("models/missing.obj"), "cubik").is_err());
+	assert!(vfs.read(Path::new("../models/a.obj"), "cubik").is_err());
+
+	let listed: Vec<String> = vfs.list(Path::new("models"), "cubik").into_iter().collect();
+	assert_eq!(listed, vec!["a.obj", "textures"]);
+	// memory mounts have no path on disk
+	assert_eq!(vfs.real_path(Path::new("models/a.obj"), "cubik"), None);
+}
+
+#[test]
+fn first_mount_containing_path_wins() {
+	let dir = temp_dir("order");
+	fs::write(dir.join("shared.txt"), "directory").unwrap();
+	fs::write(dir.join("directory_only.txt"), "directory").unwrap();
+
+	let mut vfs = Vfs::new();
+	vfs.mount(Mount::directory(&dir));
+	vfs.mount(memory_mount(&[("shared.txt", "memory"), ("memory_only.txt", "memory")]));
+	assert_eq!(read_string(&vfs, "shared.txt"), "directory");
+	assert_eq!(read_string(&vfs, "memory_only.txt"), "memory");
+	assert_eq!(vfs.real_path(Path::new("shared.txt"), "cubik"), Some(dir.join("shared.txt")));
+
+	vfs.mount_first(memory_mount(&[("shared.txt", "first")]));
+	assert_eq!(read_string(&vfs, "shared.txt"), "first");
+	assert_eq!(read_string(&vfs, "directory_only.txt"), "directory");
+	assert_eq!(vfs.real_path(Path::new("shared.txt"), "cubik"), None);
+
+	let listed: Vec<String> = vfs.list(Path::new(""), "cubik").into_iter().collect();
+	assert_eq!(listed, vec!["directory_only.txt", "memory_only.txt", "shared.txt"]);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn archive_mount_reads_zip_entries() {
+	let dir = temp_dir("archive");
+	let archive_path = dir.join("assets.zip");
+	{
+		let mut zip = ZipWriter::new(File::create(&archive_path).unwrap());
+		zip.start_file("models/a.obj", FileOptions::default()).unwrap();
+		zip.write_all(b"zipped obj").unwrap();
+		zip.start_file("sounds/b.ogg", FileOptions::default()).unwrap();
+		zip.write_all(b"zipped ogg").unwrap();
+		zip.finish().unwrap();
+	}
+
+	let mut vfs = Vfs::new();
+	vfs.mount(Mount::archive(&archive_path).unwrap());
+	assert_eq!(read_string(&vfs, "models/a.obj"), "zipped obj");
+	assert_eq!(read_string(&vfs, "./sounds/b.ogg"), "zipped ogg");
+	assert!(vfs.exists(Path::new("models"), "cubik"));
+	assert!(vfs.read(Path::new("models/missing.obj"), "cubik").is_err());
+
+	let listed: Vec<String> = vfs.list(Path::new(""), "cubik").into_iter().collect();
+	assert_eq!(listed, vec!["models", "sounds"]);
+	assert_eq!(vfs.real_path(Path::new("models/a.obj"), "cubik"), None);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+use cubik::bvh::Bvh;
+use cubik::draw::ObjDrawInfo;
+use cubik::collider::{LAYER_WORLD, LAYER_PLAYER, LAYER_NPC, LAYER_PROJECTILE, LAYER_ALL};
+use cubik::collision::{check_player_collision, raycast, line_of_sight};
+use cubik::cube::generate_cube_collideobj;
+use cubik::quadoctree::{BoundingBox, CollisionObj, CollisionFilter, CollisionTransform};
+use cubik::spatial::{CollisionIndices, SpatialIndex, add_instance_to_index, move_instance_in_index, remove_instance_from_index};
+
+fn draw_info_at(position: [f32; 3]) -> ObjDrawInfo {
+	let mut result = ObjDrawInfo { position: position, ..Default::default() };
+	result.generate_matrix();
+	result
+}
+
+fn item_min_x(index: &dyn SpatialIndex) -> Vec<f32> {
+	let mut result = Vec::new();
+	index.visit_items(&mut |obj, _| result.push(obj.bounds().start_pos[0]));
+	result.sort_by(|a, b| a.total_cmp(b));
+	result
+}
+
+#[test]
+fn moving_instance_leaves_other_instances_of_shape() {
+	let shape = Arc::new(CollisionObj::Triangle([[0., 0., 0.], [1., 0., 0.], [0., 0., 1.]]));
+	let mut index = Bvh::new(4);
+	let first = add_instance_to_index(&mut index, &shape, &draw_info_at([0., 0., 0.]), CollisionFilter::default()).unwrap();
+	let second = add_instance_to_index(&mut index, &shape, &draw_info_at([10., 0., 0.]), CollisionFilter::default()).unwrap();
+	assert_ne!(first, second);
+
+	let moved = CollisionTransform::from(&draw_info_at([5., 0., 0.]));
+	assert!(move_instance_in_index(&mut index, first, moved).unwrap());
+	assert_eq!(item_min_x(&index), vec![5., 10.]);
+
+	assert!(remove_instance_from_index(&mut index, second));
+	assert!(!remove_instance_from_index(&mut index, second));
+	assert_eq!(item_min_x(&index), vec![5.]);
+}
+
+fn count_in_bbox(index: &dyn SpatialIndex, start_pos: [f32; 3], end_pos: [f32; 3]) -> usize {
+	let mut result = 0;
+	index.traverse_bbox(&BoundingBox { start_pos: start_pos, end_pos: end_pos }, &CollisionFilter::default(), &mut |_| {
+		result += 1;
+		false
+	});
+	result
+}
+
+#[test]
+fn moved_instance_is_found_in_optimized_bvh() {
+	let shape = Arc::new(CollisionObj::Triangle([[0., 0., 0.], [1., 0., 0.], [0., 0., 1.]]));
+	let mut index = Bvh::new(1);
+	let mut ids = Vec::new();
+	for i in 0..8 {
+		ids.push(add_instance_to_index(&mut index, &shape, &draw_info_at([i as f32 * 10., 0., 0.]), CollisionFilter::default()).unwrap());
+	}
+	index.optimize();
+
+	// moved outside the bounds of the whole tree, so the boxes up to the root must grow
+	assert!(move_instance_in_index(&mut index, ids[3], CollisionTransform::from(&draw_info_at([0., 0., 200.]))).unwrap());
+	assert_eq!(count_in_bbox(&index, [-1., -1., 199.], [2., 1., 202.]), 1);
+	assert_eq!(count_in_bbox(&index, [29., -1., -1.], [32., 1., 2.]), 0);
+	assert_eq!(count_in_bbox(&index, [-1., -1., -1.], [80., 1., 2.]), 7);
+}
+
+#[test]
+fn collision_indices_move_instances() {
+	let shape = Arc::new(CollisionObj::Triangle([[0., 0., 0.], [1., 0., 0.], [0., 0., 1.]]));
+	let mut index = CollisionIndices::new_quadoctree(BoundingBox { start_pos: [-50.; 3], end_pos: [50.; 3] }, false, 4);
+	let id = add_instance_to_index(&mut index, &shape, &draw_info_at([0., 0., 0.]), CollisionFilter::default()).unwrap();
+	index.optimize();
+
+	assert!(move_instance_in_index(&mut index, id, CollisionTransform::from(&draw_info_at([10., 0., 10.]))).unwrap());
+	assert_eq!(count_in_bbox(&index, [9., -1., 9.], [12., 1., 12.]), 1);
+	assert_eq!(count_in_bbox(&index, [-1., -1., -1.], [2., 1., 2.]), 0);
+	assert!(!move_instance_in_index(&mut index, id + 1, CollisionTransform::from(&draw_info_at([0., 0., 0.]))).unwrap());
+}
+
+const LAYER_GLASS: u32 = 1 << 4;
+
+fn both_index_types() -> Vec<CollisionIndices> {
+	vec![
+		CollisionIndices::new_bvh(4),
+		CollisionIndices::new_quadoctree(BoundingBox { start_pos: [-50.; 3], end_pos: [50.; 3] }, false, 4)
+	]
+}
+
+#[test]
+fn player_collision_ignores_layers_outside_mask() {
+	let player_filter = CollisionFilter::new(LAYER_PLAYER, LAYER_WORLD);
+	let player_box = generate_cube_collideobj(&[0., 0.8, 0.], &[0., 0., 0.], &[0.2, 0.8, 0.2], 0.);
+	let wall = || generate_cube_collideobj(&[0., 0., 0.], &[0.25, 1., 0.], &[0.1, 1., 2.], 0.);
+
+	for mut index in both_index_types() {
+		index.insert(wall(), CollisionFilter::new(LAYER_GLASS, LAYER_ALL)).unwrap();
+		index.optimize();
+		assert!(check_player_collision(&index, &[0., 0., 0.], &player_box, &player_filter).polygons.is_empty());
+		// the wall collides with everything else
+		assert_eq!(check_player_collision(&index, &[0., 0., 0.], &player_box, &CollisionFilter::default()).polygons.len(), 1);
+
+		index.insert(wall(), CollisionFilter::new(LAYER_WORLD, LAYER_ALL)).unwrap();
+		index.optimize();
+		assert_eq!(check_player_collision(&index, &[0., 0., 0.], &player_box, &player_filter).polygons.len(), 1);
+	}
+}
+
+#[test]
+fn player_collision_ignores_objects_whose_mask_excludes_player() {
+	let player_filter = CollisionFilter::new(LAYER_PLAYER, LAYER_ALL);
+	let player_box = generate_cube_collideobj(&[0., 0.8, 0.], &[0., 0., 0.], &[0.2, 0.8, 0.2], 0.);
+	let mut index = CollisionIndices::new_bvh(4);
+	let barrier = generate_cube_collideobj(&[0., 0., 0.], &[0.25, 1., 0.], &[0.1, 1., 2.], 0.);
+	index.insert(barrier, CollisionFilter::new(LAYER_WORLD, LAYER_ALL & !LAYER_PLAYER)).unwrap();
+	index.optimize();
+	assert!(check_player_collision(&index, &[0., 0., 0.], &player_box, &player_filter).polygons.is_empty());
+	assert_eq!(check_player_collision(&index, &[0., 0., 0.], &player_box, &CollisionFilter::new(LAYER_NPC, LAYER_ALL)).polygons.len(), 1);
+}
+
+// Two triangles in the plane x = 2, facing the origin.
+fn wall_triangles() -> Vec<CollisionObj> {
+	vec![
+		CollisionObj::Triangle([[2., -1., -1.], [2., 1., -1.], [2., 1., 1.]]),
+		CollisionObj::Triangle([[2., -1., -1.], [2., 1., 1.], [2., -1., 1.]])
+	]
+}
+
+#[test]
+fn rays_pass_through_layers_outside_mask() {
+	// glass that stops players but lets projectiles through
+	let glass_filter = CollisionFilter::new(LAYER_GLASS, LAYER_ALL & !LAYER_PROJECTILE);
+	let projectile_filter = CollisionFilter::new(LAYER_PROJECTILE, LAYER_ALL);
+	let player_filter = CollisionFilter::new(LAYER_PLAYER, LAYER_ALL);
+
+	for mut index in both_index_types() {
+		for triangle in wall_triangles() {
+			index.insert(triangle, glass_filter).unwrap();
+		}
+		index.optimize();
+		assert!(raycast(&index, &[0., 0., 0.], &[1., 0., 0.], 10., &projectile_filter).is_none());
+		assert!(line_of_sight(&index, &[0., 0., 0.], &[4., 0., 0.], &projectile_filter));
+		let hit = raycast(&index, &[0., 0., 0.], &[1., 0., 0.], 10., &player_filter).unwrap();
+		assert!((hit.distance - 2.).abs() < 1e-4);
+		assert!(!line_of_sight(&index, &[0., 0., 0.], &[4., 0., 0.], &player_filter));
+
+		for triangle in wall_triangles() {
+			index.insert(triangle, CollisionFilter::new(LAYER_WORLD, LAYER_ALL)).unwrap();
+		}
+		index.optimize();
+		let hit = raycast(&index, &[0., 0., 0.], &[1., 0., 0.], 10., &projectile_filter).unwrap();
+		assert!((hit.distance - 2.).abs() < 1e-4);
+	}
+}
+
+#[test]
+#[allow(deprecated)]
+fn deprecated_quadoctree_helpers_use_default_filter() {
+	use cubik::draw::Vertex;
+	use cubik::quadoctree::{QuadOctreeNode, add_obj_to_quadoctree, insert_quadoctree_item};
+
+	let vertex = |position: [f32; 3]| Vertex { position: position, normal: [-1., 0., 0.], texcoords: [0., 0.] };
+	let vertices = [vertex([2., -1., -1.]), vertex([2., 1., -1.]), vertex([2., 1., 1.]), vertex([2., -1., 1.])];
+	let mut tree = QuadOctreeNode::new_tree(BoundingBox { start_pos: [-50.; 3], end_pos: [50.; 3] }, false, 4);
+	add_obj_to_quadoctree(&mut tree, &vertices, &[0, 1, 2, 0, 2, 3], false).unwrap();
+	insert_quadoctree_item(&mut tree, CollisionObj::Triangle([[5., -1., -1.], [5., 1., -1.], [5., 1., 1.]])).unwrap();
+
+	let mut filters = Vec::new();
+	tree.visit_items(&mut |_, filter| filters.push(*filter));
+	assert_eq!(filters.len(), 3);
+	assert!(filters.iter().all(|filter| *filter == CollisionFilter::default()));
+}
@@ -0,0 +1,36 @@
+use cubik::debug_draw::{DebugLines, CollisionDebugOptions, collision_debug_lines};
+use cubik::quadoctree::{BoundingBox, CollisionObj, CollisionFilter, LAYER_NPC};
+use cubik::player::{Player, PlayerControlType};
+use cubik::spatial::{CollisionIndices, SpatialIndex};
+
+#[test]
+fn bbox_has_twelve_edges() {
+	let mut lines = DebugLines::new();
+	lines.add_bbox(&BoundingBox { start_pos: [0., 0., 0.], end_pos: [1., 2., 3.] }, &[1., 1., 1.]);
+	assert_eq!(lines.line_count(), 12);
+	assert_eq!(lines.vertices.len(), 24);
+}
+
+#[test]
+fn triangle_collider_has_three_edges() {
+	let mut index = CollisionIndices::new_bvh(4);
+	index.insert(CollisionObj::Triangle([[0., 0., 0.], [1., 0., 0.], [0., 0., 1.]]), CollisionFilter::default()).unwrap();
+	index.optimize();
+
+	let lines = collision_debug_lines(Some(&index), None, &CollisionDebugOptions::default());
+	assert_eq!(lines.line_count(), 3);
+
+	let options = CollisionDebugOptions { colliders: false, ..Default::default() };
+	assert_eq!(collision_debug_lines(Some(&index), None, &options).line_count(), 0);
+}
+
+#[test]
+fn colliders_outside_world_layer_are_drawn_near_player() {
+	let mut index = CollisionIndices::new_bvh(4);
+	index.insert(CollisionObj::Triangle([[0., 0., 0.], [1., 0., 0.], [0., 0., 1.]]), CollisionFilter::new(LAYER_NPC, LAYER_NPC)).unwrap();
+	index.optimize();
+
+	let player = Player::new([0., 0., 0.], PlayerControlType::Singleplayer, [0., 0.8, 0.], [0.2, 0.8, 0.2]);
+	let options = CollisionDebugOptions { player_cube: false, contacts: false, radius: Some(5.), ..Default::default() };
+	assert_eq!(collision_debug_lines(Some(&index), Some(&player), &options).line_count(), 3);
+}
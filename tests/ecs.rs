@@ -0,0 +1,63 @@
+use std::sync::Arc;
+use cubik::bvh::Bvh;
+use cubik::ecs::{World, Transform, Collider, Replicated, EntityMessage, sync_colliders, replication_messages};
+use cubik::quadoctree::{CollisionObj, CollisionFilter};
+use cubik::spatial::SpatialIndex;
+
+fn transform_at(position: [f32; 3]) -> Transform {
+	let mut result = Transform { position: position, ..Default::default() };
+	result.generate_matrix();
+	result
+}
+
+fn item_min_x(index: &dyn SpatialIndex) -> Vec<f32> {
+	let mut result = Vec::new();
+	index.visit_items(&mut |obj, _| result.push(obj.bounds().start_pos[0]));
+	result.sort_by(|a, b| a.total_cmp(b));
+	result
+}
+
+#[test]
+fn despawned_collider_is_removed_from_index() {
+	let shape = Arc::new(CollisionObj::Triangle([[0., 0., 0.], [1., 0., 0.], [0., 0., 1.]]));
+	let mut world = World::new();
+	let mut index = Bvh::new(4);
+	let first = world.spawn();
+	world.insert(first, Collider::new(shape.clone(), CollisionFilter::default()));
+	world.insert(first, transform_at([0., 0., 0.]));
+	let second = world.spawn();
+	world.insert(second, Collider::new(shape, CollisionFilter::default()));
+	world.insert(second, transform_at([10., 0., 0.]));
+
+	sync_colliders(&mut world, &mut index).unwrap();
+	assert_eq!(item_min_x(&index), vec![0., 10.]);
+
+	world.insert(first, transform_at([5., 0., 0.]));
+	sync_colliders(&mut world, &mut index).unwrap();
+	assert_eq!(item_min_x(&index), vec![5., 10.]);
+
+	world.despawn(second);
+	sync_colliders(&mut world, &mut index).unwrap();
+	assert_eq!(item_min_x(&index), vec![5.]);
+
+	world.remove::<Collider>(first);
+	sync_colliders(&mut world, &mut index).unwrap();
+	assert!(item_min_x(&index).is_empty());
+}
+
+#[test]
+fn despawned_replicated_entity_sends_despawn() {
+	let mut world = World::new();
+	let entity = world.spawn();
+	world.insert(entity, Replicated { net_id: 7 });
+	world.insert(entity, transform_at([1., 2., 3.]));
+
+	let messages = replication_messages(&mut world);
+	assert!(matches!(messages[..], [EntityMessage::Transform { net_id: 7, position: [1., 2., 3.], .. }]));
+	assert!(replication_messages(&mut world).is_empty());
+
+	world.despawn(entity);
+	let messages = replication_messages(&mut world);
+	assert!(matches!(messages[..], [EntityMessage::Despawn { net_id: 7 }]));
+	assert!(replication_messages(&mut world).is_empty());
+}
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use cubik::draw::Light;
+use cubik::gltf::load_gltf;
+use cubik::wavefront::LoadTargets;
+use cubik::assets::{Mount, vfs_mut};
+
+// Three positions of a triangle followed by its u16 indices.
+const BUFFER: &str = "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAAAAAAIA/AAABAAIAAAA=";
+
+// A node whose only primitive is made of points, a red triangle and a light without a mesh.
+fn minimal_gltf() -> String {
+	format!(r#"{{
+	"asset": {{ "version": "2.0" }},
+	"scene": 0,
+	"scenes": [{{ "nodes": [0, 1, 2] }}],
+	"nodes": [
+		{{ "name": "points", "mesh": 0 }},
+		{{ "name": "triangle", "mesh": 1 }},
+		{{ "name": "light_lamp", "translation": [1, 2, 3] }}
+	],
+	"meshes": [
+		{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "mode": 0, "material": 1 }}] }},
+		{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "material": 0 }}] }}
+	],
+	"materials": [
+		{{ "pbrMetallicRoughness": {{ "baseColorFactor": [1, 0, 0, 1] }} }},
+		{{ "pbrMetallicRoughness": {{ "baseColorFactor": [0, 0, 1, 1] }} }}
+	],
+	"buffers": [{{ "byteLength": 44, "uri": "{}" }}],
+	"bufferViews": [
+		{{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+		{{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+	],
+	"accessors": [
+		{{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 0, 1] }},
+		{{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+	]
+}}"#, BUFFER)
+}
+
+#[test]
+fn minimal_embedded_gltf_loads_without_display() {
+	let mut files = HashMap::new();
+	files.insert("gltf_minimal/model.gltf".to_string(), minimal_gltf().into_bytes());
+	vfs_mut(|vfs| vfs.mount_first(Mount::memory(files)));
+
+	let mut lights: HashMap<String, Light> = HashMap::new();
+	let targets = LoadTargets { lights: Some(&mut lights), ..Default::default() };
+	let model = load_gltf("gltf_minimal/model.gltf", "cubik", None, None, &[1., 1., 1.], targets).unwrap();
+
+	assert_eq!(model.meshes.keys().collect::<Vec<_>>(), vec!["triangle"]);
+	let triangle = &model.meshes["triangle"];
+	assert_eq!(triangle.vertices.len(), 3);
+	assert_eq!(triangle.indices.len(), 3);
+	assert_eq!(triangle.submeshes.len(), 1);
+	assert_eq!(triangle.submeshes[0].material.color, [1., 0., 0.]);
+
+	assert_eq!(model.nodes[0].object, None);
+	assert_eq!(model.nodes[1].object.as_deref(), Some("triangle"));
+	assert_eq!(lights["light_lamp"].position, [1., 2., 3.]);
+}
@@ -0,0 +1,110 @@
+use cubik::health::{Health, HealthConfig, DamageEvent, DamageSource, DamageType, choose_spawn_point};
+use cubik::player::{Player, PlayerControlType};
+
+fn damage(amount: f32) -> DamageEvent {
+	DamageEvent {
+		source: DamageSource::World,
+		damage_type: DamageType::Generic,
+		amount: amount
+	}
+}
+
+#[test]
+fn armor_absorbs_its_share_of_damage() {
+	let mut health = Health::new(HealthConfig::default());
+	health.add_armor(50.);
+
+	let result = health.apply_damage(damage(30.)).unwrap();
+	assert!((result.armor_taken - 19.8).abs() < 1e-4);
+	assert!((result.health_taken - 10.2).abs() < 1e-4);
+	assert!((health.armor - 30.2).abs() < 1e-4);
+	assert!((health.health - 89.8).abs() < 1e-4);
+	assert!(!result.killed);
+}
+
+#[test]
+fn damage_beyond_armor_goes_to_health() {
+	let mut health = Health::new(HealthConfig::default());
+	health.add_armor(10.);
+
+	let result = health.apply_damage(damage(50.)).unwrap();
+	assert_eq!(result.armor_taken, 10.);
+	assert_eq!(result.health_taken, 40.);
+	assert_eq!(health.armor, 0.);
+	assert_eq!(health.health, 60.);
+}
+
+#[test]
+fn lethal_damage_kills_and_starts_respawn_timer() {
+	let mut health = Health::new(HealthConfig::default());
+
+	let result = health.apply_damage(damage(150.)).unwrap();
+	assert!(result.killed);
+	assert_eq!(result.health_taken, 100.);
+	assert!(health.is_dead());
+	assert_eq!(health.respawn_time_left(), Some(3.));
+
+	assert!(health.apply_damage(damage(10.)).is_none());
+}
+
+#[test]
+fn respawn_timer_fires_once_after_respawn_time() {
+	let mut health = Health::new(HealthConfig::default());
+	assert!(!health.update(10.));
+
+	health.apply_damage(damage(100.));
+	assert!(!health.update(1.));
+	assert!(!health.update(1.));
+	assert!(health.update(1.5));
+	assert_eq!(health.respawn_time_left(), None);
+	assert!(!health.update(1.));
+
+	health.reset();
+	assert!(!health.is_dead());
+	assert_eq!(health.health, 100.);
+}
+
+#[test]
+fn hard_landing_causes_fall_damage() {
+	let mut health = Health::new(HealthConfig::default());
+	let mut player = Player::new([0., 10., 0.], PlayerControlType::Singleplayer, [0., 0.8, 0.], [0.2, 0.8, 0.2]);
+
+	player.velocity = [0., -1., 0.];
+	assert!(health.fall_damage(&player).is_none());
+	player.velocity = [0., 0., 0.];
+	player.is_grounded = true;
+	assert!(health.fall_damage(&player).is_none());
+
+	player.is_grounded = false;
+	player.velocity = [0., -4.5, 0.];
+	assert!(health.fall_damage(&player).is_none());
+	player.velocity = [0., 0., 0.];
+	player.is_grounded = true;
+	let event = health.fall_damage(&player).unwrap();
+	assert_eq!(event.damage_type, DamageType::Fall);
+	assert!((event.amount - 80.).abs() < 1e-4);
+	assert!(health.fall_damage(&player).is_none());
+}
+
+#[test]
+fn fall_damage_threshold_comes_from_player_config() {
+	let mut health = Health::new(HealthConfig::default());
+	let mut player = Player::new([0., 10., 0.], PlayerControlType::Singleplayer, [0., 0.8, 0.], [0.2, 0.8, 0.2]);
+	player.config.fall_damage_threshold = 5.;
+
+	player.velocity = [0., -4.5, 0.];
+	assert!(health.fall_damage(&player).is_none());
+	player.velocity = [0., 0., 0.];
+	player.is_grounded = true;
+	assert!(health.fall_damage(&player).is_none());
+}
+
+#[test]
+fn spawn_point_furthest_from_occupied_positions_is_chosen() {
+	let spawn_points = [[0., 0., 0.], [10., 0., 0.], [-3., 0., 0.]];
+	assert_eq!(choose_spawn_point(&spawn_points, &[[1., 0., 0.]]), Some([10., 0., 0.]));
+	assert_eq!(choose_spawn_point(&[], &[[1., 0., 0.]]), None);
+
+	let with_nan = [[f32::NAN, 0., 0.], [5., 0., 0.]];
+	assert!(choose_spawn_point(&with_nan, &[[0., 0., 0.]]).is_some());
+}
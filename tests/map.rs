@@ -0,0 +1,60 @@
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use cubik::baked::BAKED_EXTENSION;
+use cubik::map::GameMap;
+use cubik::wavefront::bake_obj;
+
+fn triangle_obj(name: &str) -> String {
+	format!("v 0 0 0\nv 1 0 0\nv 0 0 1\no {}\nf 1 2 3\n", name)
+}
+
+fn set_modified(path: &Path, time: SystemTime) {
+	File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+}
+
+fn object_names(map: &GameMap) -> Vec<String> {
+	map.meshes.keys().map(|name| name.trim().to_string()).collect()
+}
+
+#[test]
+fn baked_model_older_than_obj_is_skipped() {
+	let dir = std::env::temp_dir().join(format!("cubik_map_test_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let base = dir.join("map");
+	let obj_path = base.with_extension("obj");
+	let baked_path = base.with_extension(BAKED_EXTENSION);
+	fs::write(base.with_extension("toml"), "").unwrap();
+
+	fs::write(&obj_path, triangle_obj("baked")).unwrap();
+	bake_obj(obj_path.to_str().unwrap(), "cubik").unwrap().save(&baked_path).unwrap();
+	let now = SystemTime::now();
+	set_modified(&obj_path, now - Duration::from_secs(60));
+	set_modified(&baked_path, now - Duration::from_secs(30));
+
+	let map = GameMap::load_map(base.to_str().unwrap(), "cubik", None, None, false).unwrap();
+	assert_eq!(object_names(&map), vec!["baked"]);
+
+	// the OBJ changed after it was baked
+	fs::write(&obj_path, triangle_obj("edited")).unwrap();
+	set_modified(&obj_path, now);
+	let map = GameMap::load_map(base.to_str().unwrap(), "cubik", None, None, false).unwrap();
+	assert_eq!(object_names(&map), vec!["edited"]);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn player_config_is_read_from_map_meta() {
+	let dir = std::env::temp_dir().join(format!("cubik_map_player_test_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let base = dir.join("map");
+	fs::write(base.with_extension("obj"), triangle_obj("floor")).unwrap();
+	fs::write(base.with_extension("toml"), "[player]\nfall_damage_threshold = 4.0\njump_count = 2\n").unwrap();
+
+	let map = GameMap::load_map(base.to_str().unwrap(), "cubik", None, None, false).unwrap();
+	assert_eq!(map.player_config.fall_damage_threshold, 4.);
+	assert_eq!(map.player_config.jump_count, 2);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
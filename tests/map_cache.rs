@@ -0,0 +1,112 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, BTreeMap};
+use std::time::{Duration, SystemTime};
+use cubik::assets::{Mount, vfs_mut};
+use cubik::map::GameMap;
+use cubik::map_cache::MapCache;
+use cubik::spatial::CollisionIndices;
+
+fn temp_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("cubik_{}_{}", name, std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+fn empty_cache() -> MapCache {
+	MapCache {
+		collision: CollisionIndices::new_bvh(4),
+		lights: HashMap::new(),
+		misc_objs: HashMap::new(),
+		triggers: HashMap::new(),
+		nav_triangles: Vec::new(),
+		meshes: BTreeMap::new()
+	}
+}
+
+fn modified(path: &Path) -> SystemTime {
+	fs::metadata(path).unwrap().modified().unwrap()
+}
+
+fn set_modified(path: &Path, time: SystemTime) {
+	File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+}
+
+fn leftover_temp_files(dir: &Path) -> usize {
+	fs::read_dir(dir).unwrap().filter(|entry| entry.as_ref().unwrap().path().extension().unwrap() == "tmp").count()
+}
+
+#[test]
+fn cache_is_only_loaded_with_matching_key() {
+	let dir = temp_dir("map_cache_key_test");
+	let path = MapCache::path_for(&dir.join("map.obj"));
+	empty_cache().save(&path, 1).unwrap();
+
+	assert!(MapCache::load(&path, 1).unwrap().is_some());
+	assert!(MapCache::load(&path, 2).unwrap().is_none());
+	assert_eq!(leftover_temp_files(&dir), 0);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn concurrent_saves_do_not_clobber_each_other() {
+	let dir = temp_dir("map_cache_concurrent_test");
+	let path = MapCache::path_for(&dir.join("map.obj"));
+
+	let threads: Vec<_> = (0..8).map(|_| {
+		let path = path.clone();
+		std::thread::spawn(move || {
+			for _ in 0..20 {
+				empty_cache().save(&path, 1).unwrap();
+			}
+		})
+	}).collect();
+	for thread in threads {
+		thread.join().unwrap();
+	}
+
+	assert!(MapCache::load(&path, 1).unwrap().is_some());
+	assert_eq!(leftover_temp_files(&dir), 0);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn load_map_reuses_cache_until_key_changes() {
+	let dir = temp_dir("map_cache_reuse_test");
+	vfs_mut(|vfs| vfs.mount_first(Mount::directory(&dir)));
+	let obj_path = dir.join("cache_map.obj");
+	let toml_path = dir.join("cache_map.toml");
+	let cache_path = MapCache::path_for(&obj_path);
+	fs::write(&obj_path, "v 0 0 0\nv 1 0 0\nv 0 0 1\no floor\nf 1 2 3\n").unwrap();
+	fs::write(&toml_path, "[quadoctree]\ncache = true\n").unwrap();
+
+	let map = GameMap::load_map("cache_map", "cubik", None, None, true).unwrap();
+	assert!(map.collision.is_some());
+	assert!(cache_path.exists());
+	assert_eq!(leftover_temp_files(&dir), 0);
+
+	// a valid cache is read rather than written again
+	let past = SystemTime::now() - Duration::from_secs(60);
+	set_modified(&cache_path, past);
+	let map = GameMap::load_map("cache_map", "cubik", None, None, true).unwrap();
+	assert!(map.collision.is_some());
+	assert_eq!(modified(&cache_path), past);
+	// the meshes come from the cache too
+	assert_eq!(map.meshes.keys().map(|name| name.trim()).collect::<Vec<_>>(), vec!["floor"]);
+	assert_eq!(map.meshes.values().next().unwrap().triangle_count(), 1);
+
+	// editing the OBJ changes the key
+	fs::write(&obj_path, "v 0 0 0\nv 2 0 0\nv 0 0 2\no floor\nf 1 2 3\n").unwrap();
+	GameMap::load_map("cache_map", "cubik", None, None, true).unwrap();
+	assert!(modified(&cache_path) > past);
+
+	// and so does changing the build parameters
+	set_modified(&cache_path, past);
+	fs::write(&toml_path, "[quadoctree]\ncache = true\nbucket_capacity = 8\n").unwrap();
+	GameMap::load_map("cache_map", "cubik", None, None, true).unwrap();
+	assert!(modified(&cache_path) > past);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
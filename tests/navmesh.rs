@@ -0,0 +1,74 @@
+use cubik::map::GameMap;
+use cubik::quadoctree::{BoundingBox, CollisionObj};
+
+const APP_ID: &str = "cubik";
+
+fn load_map2() -> GameMap {
+	GameMap::load_map("models/map2", APP_ID, None, None, true).unwrap()
+}
+
+// Bounds of the collision polygon containing the point.
+fn collider_bounds_at(map: &GameMap, point: &[f32; 3]) -> BoundingBox {
+	let mut result = None;
+	map.collision_index().unwrap().visit_items(&mut |obj, _| {
+		let bounds = obj.bounds();
+		if let CollisionObj::Polygon(..) = obj {
+			if (0..3).all(|i| bounds.start_pos[i] <= point[i] && point[i] <= bounds.end_pos[i]) {
+				result = Some(bounds);
+			}
+		}
+	});
+	result.unwrap()
+}
+
+// Distance in the xz plane from the point to the box.
+fn xz_distance(bounds: &BoundingBox, point: &[f32; 3]) -> f32 {
+	let dx = (bounds.start_pos[0] - point[0]).max(point[0] - bounds.end_pos[0]).max(0.);
+	let dz = (bounds.start_pos[2] - point[2]).max(point[2] - bounds.end_pos[2]).max(0.);
+	(dx * dx + dz * dz).sqrt()
+}
+
+#[test]
+fn path_between_spawn_points_exists() {
+	let map = load_map2();
+	let navmesh = map.navmesh.as_ref().unwrap();
+	let spawns = map.spawn_points();
+	assert_eq!(spawns.len(), 2);
+
+	let path = navmesh.find_path(&spawns[0], &spawns[1]).unwrap();
+	assert!(path.len() >= 2);
+	assert_eq!(path[0], spawns[0]);
+	assert_eq!(*path.last().unwrap(), spawns[1]);
+}
+
+#[test]
+fn path_goes_around_collision_box() {
+	let map = load_map2();
+	let navmesh = map.navmesh.as_ref().unwrap();
+	// the torus collision box sits between these points
+	let (start, end) = ([2.7, 1., -3.8], [2.7, 1., -1.8]);
+	let obstacle = collider_bounds_at(&map, &[2.7, 1., -2.7]);
+
+	let path = navmesh.find_path(&start, &end).unwrap();
+	assert!(path.len() > 2);
+	let min_clearance = navmesh.config.agent_radius;
+	for segment in path.windows(2) {
+		for step in 0..=20 {
+			let t = step as f32 / 20.;
+			let point = [
+				segment[0][0] + (segment[1][0] - segment[0][0]) * t,
+				0.,
+				segment[0][2] + (segment[1][2] - segment[0][2]) * t
+			];
+			assert!(xz_distance(&obstacle, &point) >= min_clearance, "path point {:?} is too close to {:?}", point, obstacle);
+		}
+	}
+}
+
+#[test]
+fn no_polygon_under_collision_box() {
+	let map = load_map2();
+	let navmesh = map.navmesh.as_ref().unwrap();
+	assert!(navmesh.find_polygon(&[2.7, 0.6, -2.7]).is_none());
+	assert!(navmesh.find_polygon(&[0., 0.5, 0.]).is_some());
+}
@@ -0,0 +1,48 @@
+use cubik::npc::{Npc, NpcEvent, NpcState, NpcTarget};
+
+fn npc_at_origin() -> Npc {
+	Npc::new([0., 0., 0.], [0., 0.275, 0.], [0.2, 0.275, 0.2])
+}
+
+#[test]
+fn npc_attacks_target_by_id() {
+	let mut npc = npc_at_origin();
+	let targets = [NpcTarget { id: 42, position: [0.3, 0., 0.] }];
+	npc.update(0.1, None, None, &targets);
+	assert_eq!(npc.state, NpcState::Attack);
+	npc.update(0.1, None, None, &targets);
+
+	let events = npc.take_events();
+	assert!(events.contains(&NpcEvent::Attack { target: 42 }));
+	assert_eq!(npc.target(), Some(42));
+}
+
+#[test]
+fn npc_chases_target_in_view() {
+	let mut npc = npc_at_origin();
+	let targets = [NpcTarget { id: 1, position: [3., 0., 0.] }, NpcTarget { id: 2, position: [-3., 0., 0.] }];
+	npc.update(0.1, None, None, &targets);
+	assert_eq!(npc.state, NpcState::Chase);
+	// the second target is behind the NPC
+	assert_eq!(npc.target(), Some(1));
+}
+
+#[test]
+fn npc_ignores_non_finite_targets() {
+	let mut npc = npc_at_origin();
+	let targets = [NpcTarget { id: 1, position: [f32::NAN, 0., 0.] }, NpcTarget { id: 2, position: [0.3, f32::INFINITY, 0.] }];
+	npc.update(0.1, None, None, &targets);
+	assert_eq!(npc.state, NpcState::Idle);
+	assert_eq!(npc.target(), None);
+}
+
+#[test]
+fn npc_does_not_attack_removed_target() {
+	let mut npc = npc_at_origin();
+	npc.update(0.1, None, None, &[NpcTarget { id: 7, position: [0.3, 0., 0.] }]);
+	assert_eq!(npc.state, NpcState::Attack);
+	npc.take_events();
+
+	npc.update(0.1, None, None, &[NpcTarget { id: 8, position: [-3., 0., 0.] }]);
+	assert!(!npc.take_events().iter().any(|event| matches!(event, NpcEvent::Attack { .. })));
+}
@@ -0,0 +1,192 @@
+use cubik::cube::generate_cube_collideobj;
+use cubik::player::{Player, PlayerConfig, PlayerControlType};
+use cubik::quadoctree::{CollisionObj, CollisionFilter};
+use cubik::spatial::{CollisionIndices, SpatialIndex};
+
+const CUBE_OFFSET: [f32; 3] = [0., 0.8, 0.];
+const CUBE_SIZE: [f32; 3] = [0.2, 0.8, 0.2];
+const FLOOR_TOP: f32 = 0.;
+
+// Drops the player from high enough that each update moves it much further than the floor is thick.
+fn drop_onto(floor: CollisionObj) -> Player {
+	let mut index = CollisionIndices::new_bvh(4);
+	index.insert(floor, CollisionFilter::default()).unwrap();
+	index.optimize();
+
+	let mut player = Player::new([0., 50., 0.], PlayerControlType::Singleplayer, CUBE_OFFSET, CUBE_SIZE);
+	for _ in 0..40 {
+		player.update(0.25, Some(&index), None, None);
+	}
+	player
+}
+
+fn assert_resting_on_floor(player: &Player) {
+	let bottom = player.camera.position[1] + CUBE_OFFSET[1] - CUBE_SIZE[1];
+	assert!(bottom >= FLOOR_TOP - 0.01 && bottom < FLOOR_TOP + 0.05, "player bottom ended at {}", bottom);
+	assert!(player.is_grounded);
+}
+
+#[test]
+fn falling_player_lands_on_thin_floor() {
+	let floor = generate_cube_collideobj(&[0., 0., 0.], &[0., FLOOR_TOP - 0.005, 0.], &[5., 0.005, 5.], 0.);
+	assert_resting_on_floor(&drop_onto(floor));
+}
+
+#[test]
+fn falling_player_lands_on_terrain_triangle() {
+	let floor = CollisionObj::Triangle([[-5., FLOOR_TOP, -5.], [5., FLOOR_TOP, -5.], [0., FLOOR_TOP, 5.]]);
+	assert_resting_on_floor(&drop_onto(floor));
+}
+
+#[test]
+fn falling_player_lands_on_beam_between_box_corners() {
+	// narrower than the player and off center, so no ray from the box corners or center would hit it
+	let floor = generate_cube_collideobj(&[0., 0., 0.], &[0.1, FLOOR_TOP - 0.005, 0.], &[0.03, 0.005, 5.], 0.);
+	assert_resting_on_floor(&drop_onto(floor));
+}
+
+#[test]
+fn near_vertical_slope_limit_is_clamped() {
+	let config = PlayerConfig { max_slope_angle: 1.5707, ..Default::default() }.clamped();
+	assert!(config.max_slope_angle.cos() > 0.05);
+	assert_eq!(PlayerConfig::default().clamped().max_slope_angle, PlayerConfig::default().max_slope_angle);
+}
+
+#[test]
+fn player_without_collision_stops_after_release() {
+	let mut player = Player::new([0., 0., 0.], PlayerControlType::Singleplayer, CUBE_OFFSET, CUBE_SIZE);
+	player.camera.update_direction();
+	player.input_state.move_forward = true;
+	for _ in 0..30 {
+		player.update(0.05, None, None, None);
+	}
+	assert!(player.velocity[0].abs() + player.velocity[2].abs() > 0.5);
+
+	player.input_state.move_forward = false;
+	for _ in 0..60 {
+		player.update(0.05, None, None, None);
+	}
+	let stopped_at = player.camera.position;
+	player.update(0.05, None, None, None);
+	assert_eq!(player.camera.position, stopped_at);
+	assert_eq!(player.velocity[1], 0.);
+}
+
+fn index_of(objs: Vec<CollisionObj>) -> CollisionIndices {
+	let mut index = CollisionIndices::new_bvh(4);
+	for obj in objs {
+		index.insert(obj, CollisionFilter::default()).unwrap();
+	}
+	index.optimize();
+	index
+}
+
+// Two triangles of a plane going down along +x at the angle, passing through the origin.
+fn ramp(angle: f32) -> Vec<CollisionObj> {
+	let drop = |x: f32| -x * angle.tan();
+	vec![
+		CollisionObj::Triangle([[-10., drop(-10.), -10.], [10., drop(10.), -10.], [10., drop(10.), 10.]]),
+		CollisionObj::Triangle([[-10., drop(-10.), -10.], [10., drop(10.), 10.], [-10., drop(-10.), 10.]])
+	]
+}
+
+fn floor_box() -> CollisionObj {
+	generate_cube_collideobj(&[0., 0., 0.], &[0., FLOOR_TOP - 0.5, 0.], &[10., 0.5, 10.], 0.)
+}
+
+// Facing along +x, or rotated towards +z by the yaw.
+fn player_at(position: [f32; 3], yaw: f32) -> Player {
+	let mut player = Player::new(position, PlayerControlType::Singleplayer, CUBE_OFFSET, CUBE_SIZE);
+	player.camera.pitch_yaw.1 = yaw;
+	player.camera.update_direction();
+	player
+}
+
+fn run(player: &mut Player, index: &CollisionIndices, updates: usize) {
+	for _ in 0..updates {
+		player.update(0.05, Some(index), None, None);
+	}
+}
+
+#[test]
+fn player_slides_off_steep_ramp() {
+	let index = index_of(ramp(1.1));
+	let mut player = player_at([0., 0.5, 0.], 0.);
+	run(&mut player, &index, 60);
+	assert!(player.camera.position[0] > 0.5, "player stayed at x {}", player.camera.position[0]);
+	assert!(!player.is_grounded);
+}
+
+#[test]
+fn player_stands_on_shallow_ramp() {
+	let index = index_of(ramp(0.25));
+	let mut player = player_at([0., 0.5, 0.], 0.);
+	run(&mut player, &index, 60);
+	assert!(player.camera.position[0].abs() < 0.05, "player slid to x {}", player.camera.position[0]);
+	assert!(player.is_grounded);
+}
+
+#[test]
+fn player_steps_up_onto_low_box() {
+	let step_height = 0.08;
+	let step = generate_cube_collideobj(&[0., 0., 0.], &[2., FLOOR_TOP + step_height / 2., 0.], &[1., step_height / 2., 2.], 0.);
+	let index = index_of(vec![floor_box(), step]);
+	let mut player = player_at([0., FLOOR_TOP + 0.05, 0.], 0.);
+	run(&mut player, &index, 10);
+	assert!(player.is_grounded);
+
+	player.input_state.move_forward = true;
+	run(&mut player, &index, 40);
+	assert!(player.camera.position[0] > 1.5, "player stopped at x {}", player.camera.position[0]);
+	let bottom = player.camera.position[1] + CUBE_OFFSET[1] - CUBE_SIZE[1];
+	assert!((bottom - (FLOOR_TOP + step_height)).abs() < 0.05, "player bottom ended at {}", bottom);
+}
+
+#[test]
+fn player_is_blocked_by_box_taller_than_step() {
+	let wall = generate_cube_collideobj(&[0., 0., 0.], &[2., FLOOR_TOP + 0.25, 0.], &[1., 0.25, 2.], 0.);
+	let index = index_of(vec![floor_box(), wall]);
+	let mut player = player_at([0., FLOOR_TOP + 0.05, 0.], 0.);
+	run(&mut player, &index, 10);
+
+	player.input_state.move_forward = true;
+	run(&mut player, &index, 40);
+	assert!(player.camera.position[0] < 1., "player went through at x {}", player.camera.position[0]);
+}
+
+#[test]
+fn player_stays_grounded_walking_down_steps() {
+	// each step is lower than the ground snap distance, so the player never leaves the ground
+	let mut objs = vec![floor_box()];
+	for i in 0..6 {
+		let top = FLOOR_TOP + 0.6 - i as f32 * 0.1;
+		objs.push(generate_cube_collideobj(&[0., 0., 0.], &[i as f32 * 0.5, top / 2., 0.], &[0.25, top / 2., 2.], 0.));
+	}
+	let index = index_of(objs);
+	let mut player = player_at([0., FLOOR_TOP + 0.65, 0.], 0.);
+	run(&mut player, &index, 10);
+	assert!(player.is_grounded);
+
+	player.input_state.move_forward = true;
+	for _ in 0..30 {
+		player.update(0.05, Some(&index), None, None);
+		assert!(player.is_grounded, "player left the ground at x {}", player.camera.position[0]);
+	}
+	assert!(player.camera.position[0] > 1.5, "player stopped at x {}", player.camera.position[0]);
+}
+
+#[test]
+fn player_slides_along_wall() {
+	let wall = generate_cube_collideobj(&[0., 0., 0.], &[1.5, FLOOR_TOP + 1., 0.], &[0.5, 1., 10.], 0.);
+	let index = index_of(vec![floor_box(), wall]);
+	// facing diagonally into the wall
+	let mut player = player_at([0., FLOOR_TOP + 0.05, 0.], std::f32::consts::FRAC_PI_4);
+	run(&mut player, &index, 10);
+
+	player.input_state.move_forward = true;
+	run(&mut player, &index, 60);
+	assert!(player.camera.position[0] < 1., "player went through the wall at x {}", player.camera.position[0]);
+	// the velocity along the wall is kept, instead of being stopped on every contact
+	assert!(player.camera.position[2] > 2., "player stuck at z {}", player.camera.position[2]);
+	assert!(player.velocity[0].abs() < 0.05);
+}
@@ -0,0 +1,88 @@
+use cubik::ecs::{World, Transform};
+use cubik::script::{ScriptHost, ScriptCommand, ScriptEntity};
+
+#[test]
+fn syntax_error_reports_file_and_line() {
+	let mut host = ScriptHost::new(None);
+	let err = host.load_source("doors.rhai", "let a = 1;\nlet b = 2;\nlet c = ;\n").unwrap_err();
+	assert_eq!(err.file, "doors.rhai");
+	assert_eq!(err.line, Some(3));
+	assert!(err.to_string().starts_with("doors.rhai:3:"));
+	assert_eq!(host.script_count(), 0);
+}
+
+#[test]
+fn runtime_error_in_update_is_returned() {
+	let mut host = ScriptHost::new(None);
+	host.load_source("broken.rhai", "fn on_update(time_delta) {\n\tlet x = 1;\n\tx.no_such_method();\n}\n").unwrap();
+	host.load_source("fine.rhai", "fn on_update(time_delta) {\n\tsend_chat(\"tick\");\n}\n").unwrap();
+
+	let errors = host.update(0.1);
+	assert_eq!(errors.len(), 1);
+	assert_eq!(errors[0].file, "broken.rhai");
+	assert_eq!(errors[0].line, Some(3));
+	// the other script still ran
+	assert_eq!(host.take_commands(), vec![ScriptCommand::Chat { text: "tick".to_string() }]);
+}
+
+#[test]
+fn infinite_loop_is_stopped() {
+	let mut host = ScriptHost::new(None);
+	host.load_source("loop.rhai", "fn on_update(time_delta) {\n\tloop {}\n}\n").unwrap();
+	let errors = host.update(0.1);
+	assert_eq!(errors.len(), 1);
+	assert_eq!(errors[0].file, "loop.rhai");
+	assert!(errors[0].message.contains("operations"), "{}", errors[0]);
+
+	// a loop in the top level statements fails the load instead
+	assert!(host.load_source("loop_on_load.rhai", "while true {}\n").is_err());
+	assert_eq!(host.script_count(), 1);
+}
+
+#[test]
+fn moving_unknown_entity_is_an_error() {
+	let mut host = ScriptHost::new(None);
+	let err = host.load_source("move.rhai", "move_entity(42, 1, 2, 3);\n").unwrap_err();
+	assert_eq!(err.file, "move.rhai");
+	assert_eq!(err.line, Some(1));
+	assert!(err.message.contains("unknown entity 42"));
+	assert!(host.take_commands().is_empty());
+}
+
+const ENTITY_SCRIPT: &str = r#"
+fn on_load() {
+	set_value("crate", spawn_entity("crate", 1, 2, 3));
+	play_sound("spawn");
+}
+
+fn on_update(time_delta) {
+	move_entity(get_value("crate"), 4.5, 2, 3);
+}
+
+fn on_trigger_enter(trigger, kind, id) {
+	despawn_entity(get_value("crate"));
+}
+"#;
+
+#[test]
+fn entity_commands_are_applied_to_world() {
+	let mut host = ScriptHost::new(None);
+	let mut world = World::new();
+	host.load_source("entities.rhai", ENTITY_SCRIPT).unwrap();
+
+	// non entity commands are passed back to the game
+	let remaining = host.apply_to_world(&mut world);
+	assert_eq!(remaining, vec![ScriptCommand::PlaySound { name: "spawn".to_string(), position: None }]);
+	let entity = host.entity(1).unwrap();
+	assert_eq!(world.storage::<ScriptEntity>().get(entity).unwrap().model, "crate");
+	assert_eq!(world.storage::<Transform>().get(entity).unwrap().position, [1., 2., 3.]);
+
+	assert!(host.update(0.1).is_empty());
+	assert!(host.apply_to_world(&mut world).is_empty());
+	assert_eq!(world.storage::<Transform>().get(entity).unwrap().position, [4.5, 2., 3.]);
+
+	assert!(host.call("on_trigger_enter", ("door".to_string(), "player".to_string(), 1i64)).is_empty());
+	host.apply_to_world(&mut world);
+	assert!(!world.is_alive(entity));
+	assert!(host.entity(1).is_none());
+}
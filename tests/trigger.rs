@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use cubik::assets::{Mount, vfs_mut};
+use cubik::trigger::{TriggerVolume, TriggerTracker, TriggerOccupant, TriggerEventKind};
+use cubik::wavefront::{LoadTargets, load_obj};
+
+// A box from (0, 0, 0) to (2, 2, 2) named trigger_door.
+const TRIGGER_OBJ: &str = "v 0 0 0
+v 2 0 0
+v 2 0 2
+v 0 0 2
+v 0 2 0
+v 2 2 0
+v 2 2 2
+v 0 2 2
+o trigger_door
+f 1 2 3 4
+f 5 8 7 6
+f 1 5 6 2
+f 2 6 7 3
+f 3 7 8 4
+f 4 8 5 1
+";
+
+fn load_triggers(dir: &str) -> HashMap<String, TriggerVolume> {
+	let mut files = HashMap::new();
+	files.insert(format!("{}/triggers.obj", dir), TRIGGER_OBJ.as_bytes().to_vec());
+	vfs_mut(|vfs| vfs.mount_first(Mount::memory(files)));
+
+	let mut triggers = HashMap::new();
+	let targets = LoadTargets { triggers: Some(&mut triggers), ..Default::default() };
+	load_obj(&format!("{}/triggers.obj", dir), "cubik", None, None, &[1., 1., 1.], targets).unwrap();
+	triggers
+}
+
+fn kinds(events: &[cubik::trigger::TriggerEvent]) -> Vec<(TriggerOccupant, TriggerEventKind)> {
+	events.iter().map(|event| (event.occupant, event.kind)).collect()
+}
+
+#[test]
+fn box_volume_contains_points_inside_only() {
+	let triggers = load_triggers("trigger_contains");
+	let volume = &triggers["trigger_door"];
+	assert!(volume.contains_point(&[1., 1., 1.]));
+	assert!(volume.contains_point(&[0.1, 1.9, 0.1]));
+	assert!(!volume.contains_point(&[3., 1., 1.]));
+	assert!(!volume.contains_point(&[1., -0.5, 1.]));
+	assert!(!volume.contains_point(&[1., 1., 2.5]));
+}
+
+#[test]
+fn occupants_enter_stay_and_exit() {
+	let triggers = load_triggers("trigger_sequence");
+	let mut tracker = TriggerTracker::new();
+	let player = TriggerOccupant::Player(1);
+	let entity = TriggerOccupant::Entity(7);
+	let (inside, outside) = ([1., 1., 1.], [5., 1., 1.]);
+
+	let events = tracker.update(&triggers, vec![(player, inside), (entity, outside)]);
+	assert_eq!(kinds(&events), vec![(player, TriggerEventKind::Enter)]);
+	assert_eq!(events[0].trigger, "trigger_door");
+
+	let events = tracker.update(&triggers, vec![(player, inside), (entity, inside)]);
+	assert_eq!(kinds(&events), vec![(player, TriggerEventKind::Stay), (entity, TriggerEventKind::Enter)]);
+	assert_eq!(tracker.occupants("trigger_door"), vec![player, entity]);
+
+	let events = tracker.update(&triggers, vec![(player, outside), (entity, inside)]);
+	assert_eq!(kinds(&events), vec![(entity, TriggerEventKind::Stay), (player, TriggerEventKind::Exit)]);
+	assert_eq!(tracker.occupants("trigger_door"), vec![entity]);
+
+	// an occupant that is no longer reported leaves as well
+	let events = tracker.update(&triggers, vec![]);
+	assert_eq!(kinds(&events), vec![(entity, TriggerEventKind::Exit)]);
+	assert!(tracker.occupants("trigger_door").is_empty());
+	assert!(tracker.update(&triggers, vec![]).is_empty());
+}
+
+#[test]
+fn two_players_occupy_one_volume() {
+	let triggers = load_triggers("trigger_two");
+	let mut tracker = TriggerTracker::new();
+	let (first, second) = (TriggerOccupant::Player(1), TriggerOccupant::Player(2));
+
+	let events = tracker.update(&triggers, vec![(first, [0.5, 1., 0.5]), (second, [1.5, 1., 1.5])]);
+	assert_eq!(kinds(&events), vec![(first, TriggerEventKind::Enter), (second, TriggerEventKind::Enter)]);
+	assert_eq!(tracker.occupants("trigger_door"), vec![first, second]);
+
+	let events = tracker.update(&triggers, vec![(first, [0.5, 1., 0.5])]);
+	assert_eq!(kinds(&events), vec![(first, TriggerEventKind::Stay), (second, TriggerEventKind::Exit)]);
+	assert_eq!(tracker.occupants("trigger_door"), vec![first]);
+}
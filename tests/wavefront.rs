@@ -0,0 +1,254 @@
+use std::io;
+use std::path::PathBuf;
+use std::collections::{HashMap, BTreeMap};
+use cubik::assets::{Mount, vfs_mut};
+use cubik::draw::{MtlInfo, Vertex};
+use cubik::mesh::{Mesh, pack_meshes};
+use cubik::textures::{CHECKERBOARD, insert_named_texture};
+use cubik::wavefront::{LoadTargets, WavefrontLoadError, DEFAULT_OBJECT_NAME, load_obj};
+
+// Mounts the files in memory under the directory, ahead of the asset directories on disk.
+fn mount_files(dir: &str, files: &[(&str, &str)]) {
+	let files: HashMap<String, Vec<u8>> = files.iter()
+		.map(|(name, data)| (format!("{}/{}", dir, name), data.as_bytes().to_vec())).collect();
+	vfs_mut(|vfs| vfs.mount_first(Mount::memory(files)));
+}
+
+// A quad made of two triangles sharing an edge, and a smooth shaded cube without normals.
+const SHAPES_OBJ: &str = "v 0 0 0
+v 1 0 0
+v 1 0 1
+v 0 0 1
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 1 0
+o plane
+f 1/1/1 2/2/1 3/3/1
+f 1/1/1 3/3/1 4/4/1
+v 0 1 0
+v 1 1 0
+v 1 2 0
+v 0 2 0
+v 0 1 1
+v 1 1 1
+v 1 2 1
+v 0 2 1
+o cube
+s 1
+f 5 8 7 6
+f 9 10 11 12
+f 5 6 10 9
+f 6 7 11 10
+f 7 8 12 11
+f 8 5 9 12
+";
+
+#[test]
+fn face_corners_share_vertices() {
+	mount_files("wavefront_dedup", &[("shapes.obj", SHAPES_OBJ)]);
+	let meshes = load_obj("wavefront_dedup/shapes.obj", "cubik", None, None, &[1., 1., 1.], LoadTargets::default()).unwrap();
+	let names: Vec<&str> = meshes.keys().map(|name| name.trim()).collect();
+	assert_eq!(names, vec!["cube", "plane"]);
+
+	let plane = meshes.values().nth(1).unwrap();
+	assert_eq!(plane.vertices.len(), 4);
+	assert_eq!(plane.indices.len(), 6);
+
+	let cube = meshes.values().next().unwrap();
+	assert_eq!(cube.vertices.len(), 8);
+	assert_eq!(cube.indices.len(), 36);
+	assert_eq!(cube.triangle_count(), 12);
+}
+
+#[test]
+fn packed_meshes_keep_per_object_ranges() {
+	mount_files("wavefront_packed", &[("shapes.obj", SHAPES_OBJ)]);
+	let meshes = load_obj("wavefront_packed/shapes.obj", "cubik", None, None, &[1., 1., 1.], LoadTargets::default()).unwrap();
+	let packed = pack_meshes(&meshes);
+	assert_eq!(packed.vertices.len(), 12);
+	assert_eq!(packed.indices.len(), 42);
+
+	let ranges: Vec<_> = packed.objects.iter().map(|(name, range, _)| (name.trim(), range.clone())).collect();
+	assert_eq!(ranges, vec![("cube", 0..36), ("plane", 36..42)]);
+	assert!(packed.indices[36..].iter().all(|i| (8..12).contains(i)));
+	assert!(packed.indices[..36].iter().all(|i| *i < 8));
+
+	let (_, range, submeshes) = &packed.objects[1];
+	assert_eq!(submeshes.len(), 1);
+	assert_eq!(submeshes[0].index_range, *range);
+
+	// the packed triangles are the ones of the separate meshes
+	let plane = meshes.values().nth(1).unwrap();
+	for (packed_index, index) in packed.indices[36..].iter().zip(plane.indices.iter()) {
+		assert_eq!(packed.vertices[*packed_index as usize].position, plane.vertices[*index as usize].position);
+	}
+}
+
+fn assert_default_material(material: &MtlInfo) {
+	let default = MtlInfo::default();
+	assert_eq!(material.color, default.color);
+	assert_eq!(material.opacity, default.opacity);
+	assert_eq!(material.illumination_model, default.illumination_model);
+	assert!(material.diffuse_texture.is_none());
+}
+
+const TRIANGLE: &str = "v 0 0 0
+v 1 0 0
+v 0 0 1
+o triangle
+";
+
+#[test]
+fn object_without_usemtl_uses_default_material() {
+	mount_files("wavefront_no_usemtl", &[("model.obj", &format!("{}f 1 2 3\n", TRIANGLE))]);
+	let meshes = load_obj("wavefront_no_usemtl/model.obj", "cubik", None, None, &[1., 1., 1.], LoadTargets::default()).unwrap();
+	let mesh = meshes.values().next().unwrap();
+	assert_eq!(mesh.submeshes.len(), 1);
+	assert_eq!(mesh.submeshes[0].index_range, 0..3);
+	assert_default_material(&mesh.submeshes[0].material);
+}
+
+#[test]
+fn missing_material_library_uses_default_material() {
+	mount_files("wavefront_missing_mtl", &[("model.obj", &format!("mtllib missing.mtl\n{}usemtl red\nf 1 2 3\n", TRIANGLE))]);
+	let meshes = load_obj("wavefront_missing_mtl/model.obj", "cubik", None, None, &[1., 1., 1.], LoadTargets::default()).unwrap();
+	let mesh = meshes.values().next().unwrap();
+	assert_eq!(mesh.submeshes.len(), 1);
+	assert_default_material(&mesh.submeshes[0].material);
+}
+
+#[test]
+fn unknown_material_uses_default_material() {
+	let mtl = "newmtl red
+Kd 1 0 0
+map_Kd red.png
+";
+	let obj = format!("mtllib model.mtl\n{}usemtl red\nf 1 2 3\nusemtl blue\nf 3 2 1\n", TRIANGLE);
+	mount_files("wavefront_unknown_mtl", &[("model.obj", &obj), ("model.mtl", mtl)]);
+	let meshes = load_obj("wavefront_unknown_mtl/model.obj", "cubik", None, None, &[1., 1., 1.], LoadTargets::default()).unwrap();
+	let mesh = meshes.values().next().unwrap();
+	assert_eq!(mesh.submeshes.len(), 2);
+	assert_eq!(mesh.submeshes[0].material.color, [1., 0., 0.]);
+	// without a display the texture is not loaded, only its name is kept
+	assert_eq!(mesh.submeshes[0].material.diffuse_texture.as_deref(), Some("red.png"));
+	assert_eq!(mesh.submeshes[1].index_range, 3..6);
+	assert_default_material(&mesh.submeshes[1].material);
+}
+
+#[test]
+fn missing_texture_falls_back_to_checkerboard() {
+	let mut textures: HashMap<String, u32> = HashMap::new();
+	let missing = || Err(io::Error::new(io::ErrorKind::NotFound, "missing").into());
+	assert_eq!(insert_named_texture(&mut textures, "missing.png", missing, || Ok(0)).unwrap(), CHECKERBOARD);
+	assert_eq!(textures.get(CHECKERBOARD), Some(&0));
+
+	// the placeholder is only created once
+	let name = insert_named_texture(&mut textures, "other.png", missing, || panic!("placeholder created twice")).unwrap();
+	assert_eq!(name, CHECKERBOARD);
+
+	assert_eq!(insert_named_texture(&mut textures, "found.png", || Ok(1), || Ok(0)).unwrap(), "found.png");
+	assert_eq!(textures.get("found.png"), Some(&1));
+	let name = insert_named_texture(&mut textures, "found.png", || panic!("texture loaded twice"), || Ok(0)).unwrap();
+	assert_eq!(name, "found.png");
+	assert_eq!(textures.len(), 2);
+}
+
+fn object_names(meshes: &BTreeMap<String, Mesh>) -> Vec<&str> {
+	meshes.keys().map(|name| name.trim()).collect()
+}
+
+#[test]
+fn faces_before_first_object_get_their_own_object() {
+	let obj = "v 0 0 0\nv 1 0 0\nv 0 0 1\nf 1 2 3\no named\nv 0 1 0\nv 1 1 0\nv 0 1 1\nf 4 5 6\n";
+	mount_files("wavefront_unnamed", &[("model.obj", obj)]);
+	let meshes = load_obj("wavefront_unnamed/model.obj", "cubik", None, None, &[1., 1., 1.], LoadTargets::default()).unwrap();
+	assert_eq!(object_names(&meshes), vec![DEFAULT_OBJECT_NAME, "named"]);
+	assert_eq!(meshes[DEFAULT_OBJECT_NAME].indices.len(), 3);
+	assert!(meshes[DEFAULT_OBJECT_NAME].vertices.iter().all(|v| v.position[1] == 0.));
+	let named = meshes.values().nth(1).unwrap();
+	assert_eq!(named.indices.len(), 3);
+	assert!(named.vertices.iter().all(|v| v.position[1] == 1.));
+}
+
+#[test]
+fn file_without_objects_loads_faces_as_default_object() {
+	mount_files("wavefront_no_objects", &[("faces.obj", "v 0 0 0\nv 1 0 0\nv 0 0 1\nf 1 2 3\n"), ("empty.obj", "v 0 0 0\nv 1 0 0\n")]);
+	let meshes = load_obj("wavefront_no_objects/faces.obj", "cubik", None, None, &[1., 1., 1.], LoadTargets::default()).unwrap();
+	assert_eq!(object_names(&meshes), vec![DEFAULT_OBJECT_NAME]);
+	assert_eq!(meshes[DEFAULT_OBJECT_NAME].indices.len(), 3);
+
+	let result = load_obj("wavefront_no_objects/empty.obj", "cubik", None, None, &[1., 1., 1.], LoadTargets::default());
+	assert!(matches!(result, Err(WavefrontLoadError::FormatError { .. })));
+}
+
+#[test]
+fn negative_indices_count_back_from_latest_vertex() {
+	let obj = "v 9 9 9\nv 0 0 0\nv 1 0 0\nv 0 0 1\nvt 0.5 0.5\nvt 1 1\nvn 0 -1 0\nvn 0 1 0\no triangle\nf -3/-2/-1 -2/-1/-1 -1/-1/-1\n";
+	mount_files("wavefront_negative", &[("model.obj", obj)]);
+	let meshes = load_obj("wavefront_negative/model.obj", "cubik", None, None, &[1., 1., 1.], LoadTargets::default()).unwrap();
+	let mesh = meshes.values().next().unwrap();
+	let positions: Vec<[f32; 3]> = mesh.indices.iter().map(|i| mesh.vertices[*i as usize].position).collect();
+	// the loader reverses the winding
+	assert_eq!(positions, vec![[0., 0., 1.], [1., 0., 0.], [0., 0., 0.]]);
+	assert_eq!(mesh.vertices[mesh.indices[2] as usize].texcoords, [0.5, 0.5]);
+	assert_eq!(mesh.vertices[mesh.indices[1] as usize].texcoords, [1., 1.]);
+	assert!(mesh.vertices.iter().all(|v| v.normal == [0., 1., 0.]));
+}
+
+#[test]
+fn faces_with_only_texcoords_or_normals() {
+	let obj = format!("{}vt 0.25 0.75\nvn 0 -1 0\nf 1/1 2/1 3/1\nf 1//1 3//1 2//1\n", TRIANGLE);
+	mount_files("wavefront_partial_refs", &[("model.obj", &obj)]);
+	let meshes = load_obj("wavefront_partial_refs/model.obj", "cubik", None, None, &[1., 1., 1.], LoadTargets::default()).unwrap();
+	let mesh = meshes.values().next().unwrap();
+	assert_eq!(mesh.indices.len(), 6);
+
+	// v/vt corners get the face normal, v//vn corners get no texcoords
+	let first: Vec<&Vertex> = mesh.indices[..3].iter().map(|i| &mesh.vertices[*i as usize]).collect();
+	assert!(first.iter().all(|v| v.texcoords == [0.25, 0.75]));
+	assert!(first.iter().all(|v| (v.normal[1].abs() - 1.).abs() < 1e-6));
+	let second: Vec<&Vertex> = mesh.indices[3..].iter().map(|i| &mesh.vertices[*i as usize]).collect();
+	assert!(second.iter().all(|v| v.texcoords == [0., 0.]));
+	assert!(second.iter().all(|v| v.normal == [0., -1., 0.]));
+}
+
+#[test]
+fn polygon_with_nan_vertex_does_not_panic() {
+	let obj = "v 0 0 0\nv 1 0 0\nv nan 0 1\nv 0 0 1\no quad\nf 1 2 3 4\n";
+	mount_files("wavefront_nan", &[("model.obj", obj)]);
+	assert!(load_obj("wavefront_nan/model.obj", "cubik", None, None, &[1., 1., 1.], LoadTargets::default()).is_ok());
+}
+
+// Loads the OBJ and returns the location and cause of the parse error it fails with.
+fn parse_error(dir: &str, obj: &str) -> (PathBuf, usize, String, WavefrontLoadError) {
+	mount_files(dir, &[("model.obj", obj)]);
+	match load_obj(&format!("{}/model.obj", dir), "cubik", None, None, &[1., 1., 1.], LoadTargets::default()) {
+		Err(WavefrontLoadError::ParseError { path, line, text, source }) => (path, line, text, *source),
+		Err(e) => panic!("expected a parse error, got {}", e),
+		Ok(_) => panic!("expected a parse error")
+	}
+}
+
+#[test]
+fn bad_float_reports_location() {
+	let (path, line, text, source) = parse_error("wavefront_bad_float", "v 0 0 0\nv 1 0.5.2 0\nv 0 0 1\no triangle\nf 1 2 3\n");
+	assert_eq!(path, PathBuf::from("wavefront_bad_float/model.obj"));
+	assert_eq!(line, 2);
+	assert_eq!(text, "v 1 0.5.2 0");
+	assert!(matches!(source, WavefrontLoadError::FloatParseError(_)));
+}
+
+#[test]
+fn bad_face_index_reports_location() {
+	let (path, line, text, source) = parse_error("wavefront_bad_index", &format!("{}f 1 2 3\nf 1 2 4\n", TRIANGLE));
+	assert_eq!(path, PathBuf::from("wavefront_bad_index/model.obj"));
+	assert_eq!(line, 6);
+	assert_eq!(text, "f 1 2 4");
+	assert!(matches!(source, WavefrontLoadError::BadIndexError { .. }));
+
+	let (_, line, text, source) = parse_error("wavefront_unparsable_index", &format!("{}f 1 2 x\n", TRIANGLE));
+	assert_eq!((line, text.as_str()), (5, "f 1 2 x"));
+	assert!(matches!(source, WavefrontLoadError::IntParseError(_)));
+}
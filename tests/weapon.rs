@@ -0,0 +1,135 @@
+use cubik::cube::generate_cube_collideobj;
+use cubik::collider::{CollisionFilter, LAYER_WORLD, LAYER_PROJECTILE, LAYER_ALL};
+use cubik::quadoctree::CollisionObj;
+use cubik::spatial::{CollisionIndices, SpatialIndex};
+use cubik::weapon::{Weapon, WeaponConfig, WeaponKind, FireResult, trace_shot};
+
+const TARGET_SIZE: [f32; 3] = [0.2, 0.5, 0.2];
+
+fn fire_hitscan(weapon: &mut Weapon, targets: &[(u8, &CollisionObj)]) -> Option<Option<u8>> {
+	match weapon.fire(1u8, &[0., 0.5, 0.], &[1., 0., 0.], None, targets)? {
+		FireResult::Hitscan(hit) => Some(hit.and_then(|hit| hit.target)),
+		FireResult::Projectile(_) => panic!("expected a hitscan shot")
+	}
+}
+
+#[test]
+fn weapon_waits_for_fire_interval() {
+	let mut weapon = Weapon::new(WeaponConfig { fire_interval: 0.2, ..Default::default() }, 0);
+	assert!(fire_hitscan(&mut weapon, &[]).is_some());
+	assert!(fire_hitscan(&mut weapon, &[]).is_none());
+	weapon.update(0.1);
+	assert!(!weapon.can_fire());
+	weapon.update(0.1);
+	assert!(fire_hitscan(&mut weapon, &[]).is_some());
+	assert_eq!(weapon.ammo, weapon.config.magazine_size - 2);
+}
+
+#[test]
+fn reload_takes_what_reserve_has_left() {
+	let config = WeaponConfig { magazine_size: 12, fire_interval: 0., reload_time: 1., ..Default::default() };
+	let mut weapon = Weapon::new(config, 5);
+	assert!(!weapon.reload());
+	for _ in 0..7 {
+		assert!(fire_hitscan(&mut weapon, &[]).is_some());
+	}
+	assert_eq!(weapon.ammo, 5);
+
+	assert!(weapon.reload());
+	assert!(!weapon.can_fire());
+	assert!(!weapon.update(0.5));
+	assert!(weapon.update(0.5));
+	assert_eq!((weapon.ammo, weapon.reserve_ammo), (10, 0));
+	assert!(!weapon.reload());
+}
+
+#[test]
+fn reload_fills_magazine_from_larger_reserve() {
+	let config = WeaponConfig { magazine_size: 12, fire_interval: 0., reload_time: 1., ..Default::default() };
+	let mut weapon = Weapon::new(config, 30);
+	for _ in 0..12 {
+		assert!(fire_hitscan(&mut weapon, &[]).is_some());
+	}
+	assert!(fire_hitscan(&mut weapon, &[]).is_none());
+	assert!(weapon.reload());
+	assert!(weapon.update(1.));
+	assert_eq!((weapon.ammo, weapon.reserve_ammo), (12, 18));
+}
+
+#[test]
+fn damage_falls_off_with_distance() {
+	let config = WeaponConfig { damage: 20., falloff_start: 10., falloff_end: 30., min_damage_factor: 0.5, ..Default::default() };
+	assert_eq!(config.damage_at(5.), 20.);
+	assert_eq!(config.damage_at(20.), 15.);
+	assert_eq!(config.damage_at(30.), 10.);
+	assert_eq!(config.damage_at(100.), 10.);
+}
+
+#[test]
+fn shots_skip_their_owner() {
+	// the shot starts inside the owner's box
+	let owner = generate_cube_collideobj(&[0., 0.5, 0.], &[0., 0., 0.], &TARGET_SIZE, 0.);
+	let other = generate_cube_collideobj(&[0., 0.5, 0.], &[3., 0., 0.], &TARGET_SIZE, 0.);
+	let targets = [(1u8, &owner), (2u8, &other)];
+
+	let hit = trace_shot(None, &[0., 0.5, 0.], &[1., 0., 0.], 50., &CollisionFilter::default(), &targets).unwrap();
+	assert_eq!(hit.0, Some(1));
+
+	let mut weapon = Weapon::new(Default::default(), 0);
+	assert_eq!(fire_hitscan(&mut weapon, &targets), Some(Some(2)));
+}
+
+#[test]
+fn projectile_skips_its_owner() {
+	let owner = generate_cube_collideobj(&[0., 0.5, 0.], &[0., 0., 0.], &TARGET_SIZE, 0.);
+	let other = generate_cube_collideobj(&[0., 0.5, 0.], &[3., 0., 0.], &TARGET_SIZE, 0.);
+	let targets = [(1u8, &owner), (2u8, &other)];
+
+	let config = WeaponConfig { kind: WeaponKind::Projectile { speed: 10., gravity: 0. }, ..Default::default() };
+	let mut weapon = Weapon::new(config, 0);
+	let mut projectile = match weapon.fire(1u8, &[0., 0.5, 0.], &[1., 0., 0.], None, &targets) {
+		Some(FireResult::Projectile(projectile)) => projectile,
+		_ => panic!("expected a projectile")
+	};
+	let mut hit = None;
+	for _ in 0..10 {
+		hit = projectile.update(0.05, None, &targets);
+		if hit.is_some() {
+			break;
+		}
+	}
+	assert_eq!(hit.unwrap().target, Some(2));
+	assert!(!projectile.is_expired());
+}
+
+// Fires a weapon of each kind at a wall at x = 2 with a target behind it, returning what each one hit.
+fn fire_through_wall(wall_filter: CollisionFilter) -> (Option<u8>, Option<u8>) {
+	let mut index = CollisionIndices::new_bvh(4);
+	index.insert(CollisionObj::Triangle([[2., -1., -1.], [2., 2., -1.], [2., 2., 1.]]), wall_filter).unwrap();
+	index.insert(CollisionObj::Triangle([[2., -1., -1.], [2., 2., 1.], [2., -1., 1.]]), wall_filter).unwrap();
+	index.optimize();
+	let other = generate_cube_collideobj(&[0., 0.5, 0.], &[3., 0., 0.], &TARGET_SIZE, 0.);
+	let targets = [(2u8, &other)];
+
+	let mut hitscan = Weapon::new(Default::default(), 0);
+	let hitscan_target = match hitscan.fire(1u8, &[0., 0.5, 0.], &[1., 0., 0.], Some(&index), &targets) {
+		Some(FireResult::Hitscan(hit)) => hit.unwrap().target,
+		_ => panic!("expected a hitscan shot")
+	};
+
+	let config = WeaponConfig { kind: WeaponKind::Projectile { speed: 10., gravity: 0. }, ..Default::default() };
+	let mut launcher = Weapon::new(config, 0);
+	let mut projectile = match launcher.fire(1u8, &[0., 0.5, 0.], &[1., 0., 0.], Some(&index), &targets) {
+		Some(FireResult::Projectile(projectile)) => projectile,
+		_ => panic!("expected a projectile")
+	};
+	let projectile_target = (0..10).find_map(|_| projectile.update(0.05, Some(&index), &targets)).unwrap().target;
+	(hitscan_target, projectile_target)
+}
+
+#[test]
+fn hitscan_and_projectiles_use_weapon_filter() {
+	// glass that players cannot pass but shots can
+	assert_eq!(fire_through_wall(CollisionFilter::new(LAYER_WORLD, LAYER_ALL & !LAYER_PROJECTILE)), (Some(2), Some(2)));
+	assert_eq!(fire_through_wall(CollisionFilter::new(LAYER_WORLD, LAYER_ALL)), (None, None));
+}